@@ -1,28 +1,14 @@
 use crate::kvstore::KeyValueStore;
-use bincode::{deserialize, serialize};
 use bytes::Bytes;
 use hyper::{client::HttpConnector, Body, Client, Request};
-use paxos::{Ballot, Commander, Configuration, NodeId, Sender, Slot, SlottedValue};
-use serde::{Deserialize, Serialize};
+use paxos::{
+    Ballot, BatchSender, Commander, Configuration, DecodeError, Message, NodeId, Sender, Slot,
+    SlottedValue,
+};
 use std::collections::HashMap;
 
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
-enum Command {
-    Proposal(Bytes),
-    Prepare(#[serde(with = "BallotDef")] Ballot),
-    Promise(NodeId, #[serde(with = "BallotDef")] Ballot, Vec<SlotValueTuple>),
-    Accept(Slot, #[serde(with = "BallotDef")] Ballot, Bytes),
-    Reject(NodeId, #[serde(with = "BallotDef")] Ballot, #[serde(with = "BallotDef")] Ballot),
-    Accepted(NodeId, Slot, #[serde(with = "BallotDef")] Ballot),
-    Resolution(Slot, #[serde(with = "BallotDef")] Ballot, Bytes),
-}
-
-#[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
-struct SlotValueTuple(Slot, #[serde(with = "BallotDef")] Ballot, Bytes);
-
-#[derive(Serialize, Deserialize)]
-#[serde(remote = "Ballot")]
-struct BallotDef(pub u32, pub u32);
+/// Replica that sends the messages of each event to a peer in one request
+pub type PaxosReplica = paxos::Replica<BatchSender<PaxosSender>>;
 
 pub struct PaxosSender {
     peers: HashMap<NodeId, PaxosCommander>,
@@ -42,25 +28,9 @@ impl PaxosSender {
     }
 }
 
-pub fn invoke(replica: &mut paxos::Replica<PaxosSender>, command: Bytes) {
-    let cmd = match deserialize(&command) {
-        Ok(cmd) => cmd,
-        Err(_) => return,
-    };
-
-    match cmd {
-        Command::Proposal(val) => replica.proposal(val),
-        Command::Prepare(bal) => replica.prepare(bal),
-        Command::Promise(node, bal, accepted) => replica.promise(
-            node,
-            bal,
-            accepted.into_iter().map(|SlotValueTuple(slot, bal, val)| (slot, bal, val)).collect(),
-        ),
-        Command::Accept(slot, bal, val) => replica.accept(slot, bal, val),
-        Command::Reject(node, proposed, preempted) => replica.reject(node, proposed, preempted),
-        Command::Accepted(node, slot, bal) => replica.accepted(node, slot, bal),
-        Command::Resolution(slot, bal, val) => replica.resolution(slot, bal, val),
-    };
+pub fn invoke(replica: &mut PaxosReplica, command: Bytes) -> Result<(), DecodeError> {
+    Message::decode(command)?.dispatch(replica);
+    Ok(())
 }
 
 impl Sender for PaxosSender {
@@ -85,15 +55,8 @@ impl Sender for PaxosSender {
 pub struct PaxosCommander(Client<HttpConnector, Body>, String);
 
 impl PaxosCommander {
-    fn send(&mut self, cmd: Command) {
-        let bytes = match serialize(&cmd) {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                error!("Error serializing command: {:?}", e);
-                return;
-            }
-        };
-
+    fn send(&mut self, cmd: Message) {
+        let bytes = cmd.encode();
         let request = Request::builder().method("POST").uri(&self.1).body(bytes.into()).unwrap();
         tokio::spawn(self.0.request(request));
     }
@@ -101,34 +64,58 @@ impl PaxosCommander {
 
 impl Commander for PaxosCommander {
     fn proposal(&mut self, val: Bytes) {
-        self.send(Command::Proposal(val));
+        self.send(Message::Proposal(val));
+    }
+
+    fn forward(&mut self, entry: Bytes) {
+        self.send(Message::Forward(entry));
     }
 
     fn prepare(&mut self, bal: Ballot) {
-        self.send(Command::Prepare(bal));
+        self.send(Message::Prepare(bal));
     }
 
-    fn promise(&mut self, node: NodeId, bal: Ballot, accepted: Vec<SlottedValue>) {
-        self.send(Command::Promise(
-            node,
-            bal,
-            accepted.into_iter().map(|(slot, bal, val)| SlotValueTuple(slot, bal, val)).collect(),
-        ));
+    fn promise(&mut self, node: NodeId, bal: Ballot, open: Slot, accepted: Vec<SlottedValue>) {
+        self.send(Message::Promise(node, bal, open, accepted));
     }
 
     fn accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.send(Command::Accept(slot, bal, val));
+        self.send(Message::Accept(slot, bal, val));
     }
 
     fn reject(&mut self, node: NodeId, proposed: Ballot, preempted: Ballot) {
-        self.send(Command::Reject(node, proposed, preempted));
+        self.send(Message::Reject(node, proposed, preempted));
     }
 
     fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
-        self.send(Command::Accepted(node, slot, bal));
+        self.send(Message::Accepted(node, slot, bal));
     }
 
     fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.send(Command::Resolution(slot, bal, val));
+        self.send(Message::Resolution(slot, bal, val));
+    }
+
+    fn snapshot_request(&mut self, node: NodeId, slot: Slot) {
+        self.send(Message::SnapshotRequest(node, slot));
+    }
+
+    fn install_snapshot(&mut self, slot: Slot, snapshot: Bytes) {
+        self.send(Message::InstallSnapshot(slot, snapshot));
+    }
+
+    fn catchup(&mut self, node: NodeId, start: Slot, end: Slot) {
+        self.send(Message::Catchup(node, start, end));
+    }
+
+    fn heartbeat(&mut self, bal: Ballot, seq: u64) {
+        self.send(Message::Heartbeat(bal, seq));
+    }
+
+    fn heartbeat_ack(&mut self, node: NodeId, bal: Ballot, seq: u64) {
+        self.send(Message::HeartbeatAck(node, bal, seq));
+    }
+
+    fn batch(&mut self, messages: Vec<Message>) {
+        self.send(Message::Batch(messages));
     }
 }
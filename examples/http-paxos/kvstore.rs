@@ -1,14 +1,14 @@
 use bincode;
 use bytes::Bytes;
-use paxos::{ReplicatedState, Slot};
+use paxos::{ProposalId, ReplicatedState, Slot};
 use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, convert::TryFrom};
 use tokio::sync::oneshot::{channel, Receiver, Sender};
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KvCommand {
-    Get { request_id: u64, key: Bytes },
-    Set { request_id: u64, key: Bytes, value: Bytes },
+    Get { key: Bytes },
+    Set { key: Bytes, value: Bytes },
 }
 
 impl Into<Bytes> for KvCommand {
@@ -28,52 +28,84 @@ impl TryFrom<Bytes> for KvCommand {
     }
 }
 
+/// Client awaiting the decision of a proposed command
+enum Pending {
+    Get(Bytes, Sender<Option<(Slot, Bytes)>>),
+    Set(Sender<Slot>),
+}
+
 #[derive(Default)]
 pub struct KeyValueStore {
     values: HashMap<Bytes, Bytes>,
-    pending_set: HashMap<u64, Sender<Slot>>,
-    pending_get: HashMap<u64, Sender<Option<(Slot, Bytes)>>>,
+    pending: HashMap<ProposalId, Pending>,
+    pending_read: HashMap<u64, Sender<bool>>,
 }
 
 impl KeyValueStore {
-    pub fn register_get(&mut self, id: u64) -> Receiver<Option<(Slot, Bytes)>> {
+    pub fn register_get(&mut self, id: ProposalId, key: Bytes) -> Receiver<Option<(Slot, Bytes)>> {
+        let (snd, recv) = channel();
+        self.pending.insert(id, Pending::Get(key, snd));
+        recv
+    }
+
+    pub fn register_set(&mut self, id: ProposalId) -> Receiver<Slot> {
         let (snd, recv) = channel();
-        self.pending_get.insert(id, snd);
+        self.pending.insert(id, Pending::Set(snd));
         recv
     }
 
-    pub fn register_set(&mut self, id: u64) -> Receiver<Slot> {
+    pub fn register_read(&mut self, id: u64) -> Receiver<bool> {
         let (snd, recv) = channel();
-        self.pending_set.insert(id, snd);
+        self.pending_read.insert(id, snd);
         recv
     }
 
+    pub fn get(&self, key: &Bytes) -> Option<Bytes> {
+        self.values.get(key).cloned()
+    }
+
     pub fn prune_listeners(&mut self) {
-        self.pending_get.retain(|_, val| !val.is_closed());
-        self.pending_set.retain(|_, val| !val.is_closed());
+        self.pending.retain(|_, val| match val {
+            Pending::Get(_, sender) => !sender.is_closed(),
+            Pending::Set(sender) => !sender.is_closed(),
+        });
+        self.pending_read.retain(|_, val| !val.is_closed());
     }
 }
 
 impl ReplicatedState for KeyValueStore {
-    fn execute(&mut self, slot: Slot, cmd: Bytes) {
-        match KvCommand::try_from(cmd) {
-            Ok(KvCommand::Get { request_id, key }) => {
-                let sender = match self.pending_get.remove(&request_id) {
-                    Some(sender) => sender,
-                    None => return,
-                };
-                match self.values.get(&key).cloned() {
-                    Some(val) => sender.send(Some((slot, val))).unwrap_or(()),
-                    None => sender.send(None).unwrap_or(()),
-                }
-            }
-            Ok(KvCommand::Set { request_id, key, value }) => {
-                self.values.insert(key, value);
-                if let Some(sender) = self.pending_set.remove(&request_id) {
-                    sender.send(slot).unwrap_or(());
-                }
+    fn execute(&mut self, _slot: Slot, cmd: Bytes) {
+        if let Ok(KvCommand::Set { key, value }) = KvCommand::try_from(cmd) {
+            self.values.insert(key, value);
+        }
+    }
+
+    fn proposal_decided(&mut self, id: ProposalId, slot: Slot) {
+        // the command has just been executed, so reads observe its slot
+        match self.pending.remove(&id) {
+            Some(Pending::Get(key, sender)) => {
+                let value = self.values.get(&key).cloned();
+                sender.send(value.map(|val| (slot, val))).unwrap_or(())
             }
-            Err(()) => {}
+            Some(Pending::Set(sender)) => sender.send(slot).unwrap_or(()),
+            None => {}
+        }
+    }
+
+    fn proposal_abandoned(&mut self, id: ProposalId) {
+        // dropping the sender fails the request
+        self.pending.remove(&id);
+    }
+
+    fn read_ready(&mut self, id: u64) {
+        if let Some(sender) = self.pending_read.remove(&id) {
+            sender.send(true).unwrap_or(());
+        }
+    }
+
+    fn read_aborted(&mut self, id: u64) {
+        if let Some(sender) = self.pending_read.remove(&id) {
+            sender.send(false).unwrap_or(());
         }
     }
 }
@@ -18,7 +18,7 @@ use hyper::{
     service::{make_service_fn, service_fn},
     Server,
 };
-use paxos::{Configuration, NodeId, Replica};
+use paxos::{BatchSender, Configuration, NodeId, Replica};
 use std::{env::args, net::SocketAddr, process::exit};
 
 fn config() -> paxos::Configuration {
@@ -49,6 +49,9 @@ fn config() -> paxos::Configuration {
             .filter(|n| *n != node_id)
             .map(|n| (n as NodeId, format!("127.0.0.1:808{}", n).parse().unwrap())),
     )
+    .with_read_lease(10, 2)
+    .with_proposal_timeout(100)
+    .with_proposal_queue(1024, 1 << 20)
 }
 
 #[tokio::main]
@@ -58,8 +61,9 @@ async fn main() {
     let conf = config();
     let addr: SocketAddr = format!("127.0.0.1:808{}", conf.current()).parse().unwrap();
     let sender = commands::PaxosSender::new(&conf);
-    let handler = service::Handler::new(Replica::new(sender, conf));
+    let handler = service::Handler::new(Replica::new(BatchSender::new(sender), conf));
     let _ = handler.spawn_cleanup_loop();
+    handler.spawn_tick_loop();
 
     let service = make_service_fn(move |_| {
         let handler = handler.clone();
@@ -1,18 +1,21 @@
-use crate::{commands, kvstore::KvCommand};
+use crate::{
+    commands::{self, PaxosReplica},
+    kvstore::KvCommand,
+};
 use bytes::Bytes;
 use hyper::{Body, Method, Request, Response, StatusCode};
-use paxos::{Commander, Sender};
+use paxos::Sender;
 use rand::random;
 use std::{sync::Arc, time::Duration};
 use tokio::{self, sync::Mutex, task::JoinHandle, time::interval};
 
 #[derive(Clone)]
 pub struct Handler {
-    replica: Arc<Mutex<paxos::Replica<commands::PaxosSender>>>,
+    replica: Arc<Mutex<PaxosReplica>>,
 }
 
 impl Handler {
-    pub fn new(replica: paxos::Replica<commands::PaxosSender>) -> Handler {
+    pub fn new(replica: PaxosReplica) -> Handler {
         Handler { replica: Arc::new(Mutex::new(replica)) }
     }
 
@@ -30,26 +33,67 @@ impl Handler {
         })
     }
 
+    /// start a loop to drive timeouts within the replica
+    pub fn spawn_tick_loop(&self) -> JoinHandle<()> {
+        let replica_arch_timer = self.replica.clone();
+        tokio::spawn(async move {
+            let mut ticks = interval(Duration::from_millis(100));
+            loop {
+                ticks.tick().await;
+
+                let mut replica = replica_arch_timer.lock().await;
+                replica.tick();
+            }
+        })
+    }
+
+    /// Reads a key at the leader without proposing the read, returning `None`
+    /// if the replica is not the leader
+    async fn read_local(&self, key: &Bytes) -> Option<Option<Bytes>> {
+        let id = random::<u64>();
+        let receiver = {
+            let mut replica = self.replica.lock().await;
+            // the leader serves reads locally while it holds the lease
+            if let Some(value) = replica.read(|kv| kv.get(key)) {
+                return Some(value);
+            }
+
+            let receiver = replica.sender_mut().state_machine().register_read(id);
+            if !replica.read_barrier(id) {
+                return None;
+            }
+            receiver
+        };
+
+        match receiver.await {
+            Ok(true) => Some(self.replica.lock().await.sender_mut().state_machine().get(key)),
+            _ => None,
+        }
+    }
+
     pub async fn handle(&self, req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
         let path = Bytes::from(req.uri().path()[1..].to_string());
         match (req.method(), path) {
             (&Method::POST, key) if key == "paxos" => {
-                {
-                    let cmd = hyper::body::to_bytes(req.into_body()).await?;
-                    let mut replica = self.replica.lock().await;
-                    commands::invoke(&mut replica, cmd);
+                let cmd = hyper::body::to_bytes(req.into_body()).await?;
+                let mut replica = self.replica.lock().await;
+                match commands::invoke(&mut replica, cmd) {
+                    Ok(()) => respond(StatusCode::ACCEPTED),
+                    Err(e) => {
+                        warn!("Error decoding message: {}", e);
+                        respond(StatusCode::BAD_REQUEST)
+                    }
                 }
-
-                respond(StatusCode::ACCEPTED)
             }
             (&Method::POST, key) => {
                 let value = hyper::body::to_bytes(req.into_body()).await?;
-                let id = random::<u64>();
                 let receiver = {
                     let mut replica = self.replica.lock().await;
-                    let receiver = replica.sender_mut().state_machine().register_set(id);
-                    replica.proposal(KvCommand::Set { request_id: id, key, value }.into());
-                    receiver
+                    let id = match replica.propose(KvCommand::Set { key, value }.into()) {
+                        Ok(id) => id,
+                        Err(_) => return respond(StatusCode::SERVICE_UNAVAILABLE),
+                    };
+                    replica.sender_mut().state_machine().register_set(id)
                 };
 
                 match receiver.await {
@@ -62,12 +106,19 @@ impl Handler {
                 }
             }
             (&Method::GET, key) => {
-                let id = random::<u64>();
+                match self.read_local(&key).await {
+                    Some(Some(value)) => return Ok(Response::new(value.into())),
+                    Some(None) => return respond(StatusCode::NOT_FOUND),
+                    None => {}
+                }
+
                 let receiver = {
                     let mut replica = self.replica.lock().await;
-                    let receiver = replica.sender_mut().state_machine().register_get(id);
-                    replica.proposal(KvCommand::Get { request_id: id, key }.into());
-                    receiver
+                    let id = match replica.propose(KvCommand::Get { key: key.clone() }.into()) {
+                        Ok(id) => id,
+                        Err(_) => return respond(StatusCode::SERVICE_UNAVAILABLE),
+                    };
+                    replica.sender_mut().state_machine().register_get(id, key)
                 };
 
                 match receiver.await {
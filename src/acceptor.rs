@@ -1,6 +1,9 @@
-use crate::{config::QuorumSet, Ballot, NodeId};
+use crate::{
+    config::{Phase, QuorumSet},
+    Ballot, NodeId, QuorumSystem,
+};
 use bytes::Bytes;
-use std::cmp::max;
+use std::{cmp::max, sync::Arc};
 
 /// Encoding of Acceptor (persistent Paxos memory) role
 pub struct Acceptor {
@@ -9,12 +12,23 @@ pub struct Acceptor {
 }
 
 impl Acceptor {
-    /// New acceptor with last promised ballot and Phase 2 quorum size
-    pub fn new(promised: Option<Ballot>, quorum: usize) -> Acceptor {
-        assert!(quorum > 1);
+    /// New acceptor with last promised ballot and the quorum system used to
+    /// detect Phase 2 quorum
+    pub fn new(promised: Option<Ballot>, quorum: Arc<dyn QuorumSystem>) -> Acceptor {
         Acceptor { state: AcceptorState::AwaitValue { promised, quorum } }
     }
 
+    /// Replaces the quorum system used to detect Phase 2 quorum
+    pub fn reconfigure(&mut self, system: Arc<dyn QuorumSystem>) {
+        match self.state {
+            AcceptorState::AwaitValue { ref mut quorum, .. } => *quorum = system,
+            AcceptorState::AwaitQuorum { ref mut quorum, .. } => {
+                *quorum = quorum.reconfigure(system);
+            }
+            AcceptorState::Resolved { .. } => {}
+        }
+    }
+
     /// Ballot of the last promise
     pub fn promised(&self) -> Option<Ballot> {
         match self.state {
@@ -33,6 +47,17 @@ impl Acceptor {
         }
     }
 
+    /// Proposed ballot and value along with the set of acceptors that have
+    /// sent ACCEPTED for the proposal, if the acceptor is awaiting quorum.
+    pub fn pending_quorum(&self) -> Option<(Ballot, Bytes, &QuorumSet)> {
+        match self.state {
+            AcceptorState::AwaitQuorum { proposed: (bal, ref val), ref quorum, .. } => {
+                Some((bal, val.clone(), quorum))
+            }
+            _ => None,
+        }
+    }
+
     /// Shows the resolution, if available
     pub fn resolution(&self) -> Option<(Ballot, Bytes)> {
         if let AcceptorState::Resolved { accepted, ref value } = self.state {
@@ -139,16 +164,17 @@ impl Acceptor {
     /// highest see by the acceptor.
     pub fn notice_value(&mut self, ballot: Ballot, value: Bytes) -> Option<(Ballot, Bytes)> {
         let (next_state, preempted_proposal) = match self.state {
-            AcceptorState::AwaitValue { promised, quorum } => {
-                // quorum must be at least _2_. We remove 1 from this state in order
-                // to consider this node as accepting all Phase 2 proposals as both
-                // the Distinguished Proposer and an Acceptor
-                assert!(quorum > 1);
+            AcceptorState::AwaitValue { promised, ref quorum } => {
+                // the proposer of the ballot is considered as accepting all of
+                // its Phase 2 proposals as both the Distinguished Proposer and
+                // an Acceptor
+                let mut quorum = QuorumSet::new(quorum.clone(), Phase::Two);
+                quorum.insert(ballot.1);
                 (
                     Some(AcceptorState::AwaitQuorum {
                         promised: max(Some(ballot), promised).unwrap(),
                         proposed: (ballot, value),
-                        quorum: QuorumSet::with_size(quorum - 1),
+                        quorum,
                     }),
                     None,
                 )
@@ -156,11 +182,13 @@ impl Acceptor {
             AcceptorState::AwaitQuorum { promised, proposed: (bal, ref val), ref quorum }
                 if bal < ballot =>
             {
+                let mut quorum = quorum.cleared();
+                quorum.insert(ballot.1);
                 (
                     Some(AcceptorState::AwaitQuorum {
                         promised: max(promised, ballot),
                         proposed: (ballot, value),
-                        quorum: QuorumSet::with_size(quorum.len()),
+                        quorum,
                     }),
                     Some((bal, val.clone())),
                 )
@@ -228,8 +256,8 @@ enum AcceptorState {
         /// last promised ballot within this instance
         promised: Option<Ballot>,
 
-        /// Size of the Phase 2 quorum
-        quorum: usize,
+        /// Quorum system used to detect Phase 2 quorum
+        quorum: Arc<dyn QuorumSystem>,
     },
 
     /// The acceptor has received an ACCEPT message from a proposer with a value
@@ -257,10 +285,11 @@ enum AcceptorState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::FlexibleQuorum;
 
     #[test]
     fn receive_prepare() {
-        let mut acceptor = Acceptor::new(None, 2);
+        let mut acceptor = Acceptor::new(None, Arc::new(FlexibleQuorum::new(2, 2)));
 
         // acceptor promises the ballot when nothing promised
         let res = acceptor.receive_prepare(Ballot(100, 1));
@@ -284,7 +313,7 @@ mod tests {
         acceptor.state = AcceptorState::AwaitQuorum {
             promised: Ballot(102, 2),
             proposed: (Ballot(102, 2), "123".into()),
-            quorum: QuorumSet::with_size(2),
+            quorum: QuorumSet::new(Arc::new(FlexibleQuorum::new(2, 2)), Phase::Two),
         };
 
         let res = acceptor.receive_prepare(Ballot(103, 1));
@@ -300,7 +329,7 @@ mod tests {
 
     #[test]
     fn receive_accept() {
-        let mut acceptor = Acceptor::new(None, 2);
+        let mut acceptor = Acceptor::new(None, Arc::new(FlexibleQuorum::new(2, 2)));
 
         // acceptor allows ACCEPT without a promise
         let res = acceptor.receive_accept(Ballot(101, 1), "ab".into());
@@ -332,7 +361,7 @@ mod tests {
         let res = acceptor.receive_accept(Ballot(105, 5), "cde".into());
         assert_eq!(res, AcceptResponse::Resolved);
 
-        let mut acceptor = Acceptor::new(None, 2);
+        let mut acceptor = Acceptor::new(None, Arc::new(FlexibleQuorum::new(2, 2)));
         acceptor.receive_prepare(Ballot(100, 4));
         assert_eq!(acceptor.promised(), Some(Ballot(100, 4)));
 
@@ -346,7 +375,7 @@ mod tests {
 
     #[test]
     fn receive_accepted() {
-        let mut acceptor = Acceptor::new(None, 3);
+        let mut acceptor = Acceptor::new(None, Arc::new(FlexibleQuorum::new(3, 3)));
 
         // accepts new ballot
         assert_eq!(
@@ -357,6 +386,9 @@ mod tests {
         // ignores previous ballots from the same acceptor
         acceptor.receive_accepted(1, Ballot(90, 0));
         assert!(!acceptor.resolved());
+        assert!(
+            acceptor.pending_quorum().map(|(b, _, q)| b == Ballot(90, 0) && q.contains(1)).unwrap()
+        );
         acceptor.receive_accepted(1, Ballot(90, 0));
         assert!(!acceptor.resolved());
 
@@ -367,6 +399,7 @@ mod tests {
         // allows quorum to be reached
         acceptor.receive_accepted(2, Ballot(90, 0));
         assert!(acceptor.resolved());
+        assert!(acceptor.pending_quorum().is_none());
         assert_eq!(acceptor.resolution(), Some((Ballot(90, 0), "abc".into())));
 
         // ignores subsequent requests after quorum
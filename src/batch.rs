@@ -0,0 +1,118 @@
+use crate::{Commander, Message, NodeId, Sender};
+use std::{collections::BTreeMap, mem};
+
+/// Sender that batches the messages sent to each node.
+///
+/// Commands are collected as messages for each node rather than sent
+/// immediately. Once the replica has handled an event, such as a received
+/// message or a tick, it flushes the sender (see `Sender::flush`), which
+/// sends the messages for each node within a single batch (see
+/// `Commander::batch`). After an election, the ACCEPT
+/// messages for every open slot reach each peer in a single batch.
+pub struct BatchSender<S> {
+    inner: S,
+    pending: BTreeMap<NodeId, Vec<Message>>,
+}
+
+impl<S: Sender> BatchSender<S> {
+    /// Creates a sender that batches messages sent with another sender
+    pub fn new(inner: S) -> BatchSender<S> {
+        BatchSender { inner, pending: BTreeMap::new() }
+    }
+
+    /// Reference to the wrapped sender
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Mutable reference to the wrapped sender
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+
+impl<S: Sender> Sender for BatchSender<S> {
+    type Commander = Vec<Message>;
+    type StateMachine = S::StateMachine;
+
+    fn send_to<F>(&mut self, node: NodeId, command: F)
+    where
+        F: FnOnce(&mut Self::Commander),
+    {
+        command(self.pending.entry(node).or_default());
+    }
+
+    fn state_machine(&mut self) -> &mut Self::StateMachine {
+        self.inner.state_machine()
+    }
+
+    /// Sends the collected messages, one batch per node. A single message
+    /// is sent without a batch.
+    fn flush(&mut self) {
+        for (node, mut messages) in mem::take(&mut self.pending) {
+            trace!("Sending batch of {} messages to node {}", messages.len(), node);
+            if messages.len() == 1 {
+                let message = messages.pop().unwrap();
+                self.inner.send_to(node, |c| message.dispatch(c));
+            } else {
+                self.inner.send_to(node, |c| c.batch(messages));
+            }
+        }
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Ballot, ReplicatedState, Slot};
+    use bytes::Bytes;
+
+    #[derive(Default)]
+    struct StateMachine;
+
+    impl ReplicatedState for StateMachine {
+        fn execute(&mut self, _slot: Slot, _command: Bytes) {}
+    }
+
+    #[derive(Default)]
+    struct VecSender(BTreeMap<NodeId, Vec<Message>>, StateMachine);
+
+    impl Sender for VecSender {
+        type Commander = Vec<Message>;
+        type StateMachine = StateMachine;
+
+        fn send_to<F>(&mut self, node: NodeId, command: F)
+        where
+            F: FnOnce(&mut Self::Commander),
+        {
+            command(self.0.entry(node).or_default());
+        }
+
+        fn state_machine(&mut self) -> &mut StateMachine {
+            &mut self.1
+        }
+    }
+
+    #[test]
+    fn batch_flush() {
+        let mut sender = BatchSender::new(VecSender::default());
+        sender.send_to(1, |c| c.accept(0, Ballot(1, 0), "0".into()));
+        sender.send_to(1, |c| c.accept(1, Ballot(1, 0), "1".into()));
+        sender.send_to(2, |c| c.accept(0, Ballot(1, 0), "0".into()));
+        assert!(sender.inner().0.is_empty());
+
+        sender.flush();
+        let batch = Message::Batch(vec![
+            Message::Accept(0, Ballot(1, 0), "0".into()),
+            Message::Accept(1, Ballot(1, 0), "1".into()),
+        ]);
+        assert_eq!(vec![batch], sender.inner().0[&1]);
+        assert_eq!(vec![Message::Accept(0, Ballot(1, 0), "0".into())], sender.inner().0[&2]);
+
+        // nothing is sent until more messages are collected
+        sender.inner_mut().0.clear();
+        sender.flush();
+        assert!(sender.inner().0.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use crate::{Ballot, Message, NodeId, SlottedValue};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{error, fmt};
+
+/// Version of the wire format written by `Message::encode`. Version 2 adds
+/// the start of the open window to PROMISE.
+const VERSION: u8 = 2;
+
+const PROPOSAL: u8 = 0;
+const PREPARE: u8 = 1;
+const PROMISE: u8 = 2;
+const ACCEPT: u8 = 3;
+const REJECT: u8 = 4;
+const ACCEPTED: u8 = 5;
+const RESOLUTION: u8 = 6;
+const SNAPSHOT_REQUEST: u8 = 7;
+const INSTALL_SNAPSHOT: u8 = 8;
+const CATCHUP: u8 = 9;
+const HEARTBEAT: u8 = 10;
+const HEARTBEAT_ACK: u8 = 11;
+const BATCH: u8 = 12;
+const FORWARD: u8 = 13;
+
+/// Maximum length of a varint encoding a `u64`
+const MAX_VARINT_LEN: usize = 10;
+
+impl Message {
+    /// Encodes the message in a compact binary format.
+    ///
+    /// The message starts with a version byte and the type of the message.
+    /// Integers (slots, ballots and node identifiers) are encoded as
+    /// variable-length integers, and values are prefixed with their length.
+    pub fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(16 + self.payload_len());
+        buf.put_u8(VERSION);
+        match self {
+            Message::Proposal(val) => {
+                buf.put_u8(PROPOSAL);
+                put_bytes(&mut buf, val);
+            }
+            Message::Forward(entry) => {
+                buf.put_u8(FORWARD);
+                put_bytes(&mut buf, entry);
+            }
+            Message::Prepare(bal) => {
+                buf.put_u8(PREPARE);
+                put_ballot(&mut buf, *bal);
+            }
+            Message::Promise(node, bal, open, accepted) => {
+                buf.put_u8(PROMISE);
+                put_varint(&mut buf, u64::from(*node));
+                put_ballot(&mut buf, *bal);
+                put_varint(&mut buf, *open);
+                put_varint(&mut buf, accepted.len() as u64);
+                for (slot, bal, val) in accepted {
+                    put_varint(&mut buf, *slot);
+                    put_ballot(&mut buf, *bal);
+                    put_bytes(&mut buf, val);
+                }
+            }
+            Message::Accept(slot, bal, val) => {
+                buf.put_u8(ACCEPT);
+                put_varint(&mut buf, *slot);
+                put_ballot(&mut buf, *bal);
+                put_bytes(&mut buf, val);
+            }
+            Message::Reject(node, proposed, preempted) => {
+                buf.put_u8(REJECT);
+                put_varint(&mut buf, u64::from(*node));
+                put_ballot(&mut buf, *proposed);
+                put_ballot(&mut buf, *preempted);
+            }
+            Message::Accepted(node, slot, bal) => {
+                buf.put_u8(ACCEPTED);
+                put_varint(&mut buf, u64::from(*node));
+                put_varint(&mut buf, *slot);
+                put_ballot(&mut buf, *bal);
+            }
+            Message::Resolution(slot, bal, val) => {
+                buf.put_u8(RESOLUTION);
+                put_varint(&mut buf, *slot);
+                put_ballot(&mut buf, *bal);
+                put_bytes(&mut buf, val);
+            }
+            Message::SnapshotRequest(node, slot) => {
+                buf.put_u8(SNAPSHOT_REQUEST);
+                put_varint(&mut buf, u64::from(*node));
+                put_varint(&mut buf, *slot);
+            }
+            Message::InstallSnapshot(slot, snapshot) => {
+                buf.put_u8(INSTALL_SNAPSHOT);
+                put_varint(&mut buf, *slot);
+                put_bytes(&mut buf, snapshot);
+            }
+            Message::Catchup(node, start, end) => {
+                buf.put_u8(CATCHUP);
+                put_varint(&mut buf, u64::from(*node));
+                put_varint(&mut buf, *start);
+                put_varint(&mut buf, *end);
+            }
+            Message::Heartbeat(bal, seq) => {
+                buf.put_u8(HEARTBEAT);
+                put_ballot(&mut buf, *bal);
+                put_varint(&mut buf, *seq);
+            }
+            Message::HeartbeatAck(node, bal, seq) => {
+                buf.put_u8(HEARTBEAT_ACK);
+                put_varint(&mut buf, u64::from(*node));
+                put_ballot(&mut buf, *bal);
+                put_varint(&mut buf, *seq);
+            }
+            Message::Batch(messages) => {
+                buf.put_u8(BATCH);
+                put_varint(&mut buf, messages.len() as u64);
+                for message in messages {
+                    put_bytes(&mut buf, &message.encode());
+                }
+            }
+        }
+        buf.freeze()
+    }
+
+    /// Decodes a message created with `encode`. Values within the message
+    /// are slices of `bytes` rather than copies.
+    pub fn decode(bytes: Bytes) -> Result<Message, DecodeError> {
+        Message::decode_in(bytes, false)
+    }
+
+    /// Decodes a message, rejecting a BATCH within a BATCH such that the
+    /// depth of recursion is bounded.
+    fn decode_in(mut bytes: Bytes, batched: bool) -> Result<Message, DecodeError> {
+        let buf = &mut bytes;
+        let version = get_u8(buf)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let message = match get_u8(buf)? {
+            PROPOSAL => Message::Proposal(get_bytes(buf)?),
+            FORWARD => Message::Forward(get_bytes(buf)?),
+            PREPARE => Message::Prepare(get_ballot(buf)?),
+            PROMISE => {
+                let node = get_node(buf)?;
+                let bal = get_ballot(buf)?;
+                let open = get_varint(buf)?;
+                let len = get_varint(buf)?;
+                // each accepted value occupies at least 4 bytes
+                if len > (buf.remaining() / 4) as u64 {
+                    return Err(DecodeError::Truncated);
+                }
+                let mut accepted: Vec<SlottedValue> = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    accepted.push((get_varint(buf)?, get_ballot(buf)?, get_bytes(buf)?));
+                }
+                Message::Promise(node, bal, open, accepted)
+            }
+            ACCEPT => Message::Accept(get_varint(buf)?, get_ballot(buf)?, get_bytes(buf)?),
+            REJECT => Message::Reject(get_node(buf)?, get_ballot(buf)?, get_ballot(buf)?),
+            ACCEPTED => Message::Accepted(get_node(buf)?, get_varint(buf)?, get_ballot(buf)?),
+            RESOLUTION => Message::Resolution(get_varint(buf)?, get_ballot(buf)?, get_bytes(buf)?),
+            SNAPSHOT_REQUEST => Message::SnapshotRequest(get_node(buf)?, get_varint(buf)?),
+            INSTALL_SNAPSHOT => Message::InstallSnapshot(get_varint(buf)?, get_bytes(buf)?),
+            CATCHUP => Message::Catchup(get_node(buf)?, get_varint(buf)?, get_varint(buf)?),
+            HEARTBEAT => Message::Heartbeat(get_ballot(buf)?, get_varint(buf)?),
+            HEARTBEAT_ACK => {
+                Message::HeartbeatAck(get_node(buf)?, get_ballot(buf)?, get_varint(buf)?)
+            }
+            BATCH if batched => return Err(DecodeError::NestedBatch),
+            BATCH => {
+                let len = get_varint(buf)?;
+                // each message occupies at least 3 bytes
+                if len > (buf.remaining() / 3) as u64 {
+                    return Err(DecodeError::Truncated);
+                }
+                let mut messages = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    messages.push(Message::decode_in(get_bytes(buf)?, true)?);
+                }
+                Message::Batch(messages)
+            }
+            message_type => return Err(DecodeError::UnknownMessage(message_type)),
+        };
+
+        if buf.has_remaining() {
+            return Err(DecodeError::TrailingBytes(buf.remaining()));
+        }
+        Ok(message)
+    }
+
+    /// Length of the values carried by the message
+    fn payload_len(&self) -> usize {
+        match self {
+            Message::Proposal(val)
+            | Message::Forward(val)
+            | Message::Accept(_, _, val)
+            | Message::Resolution(_, _, val)
+            | Message::InstallSnapshot(_, val) => val.len(),
+            Message::Promise(_, _, _, accepted) => {
+                accepted.iter().map(|(_, _, val)| 3 * MAX_VARINT_LEN + val.len()).sum()
+            }
+            Message::Batch(messages) => {
+                messages.iter().map(|m| 4 * MAX_VARINT_LEN + m.payload_len()).sum()
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// Error returned when a message cannot be decoded
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeError {
+    /// The message was encoded with an unsupported version of the format
+    UnsupportedVersion(u8),
+    /// The type of the message is unknown
+    UnknownMessage(u8),
+    /// The message ended before all of its fields were decoded
+    Truncated,
+    /// A variable-length integer exceeds the range of its type
+    Overflow,
+    /// Bytes remain after the message was decoded
+    TrailingBytes(usize),
+    /// A BATCH contains another BATCH
+    NestedBatch,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(fmt, "unsupported message version {}", version)
+            }
+            DecodeError::UnknownMessage(message_type) => {
+                write!(fmt, "unknown message type {}", message_type)
+            }
+            DecodeError::Truncated => write!(fmt, "truncated message"),
+            DecodeError::Overflow => write!(fmt, "integer overflow"),
+            DecodeError::TrailingBytes(len) => {
+                write!(fmt, "{} trailing bytes after message", len)
+            }
+            DecodeError::NestedBatch => write!(fmt, "batch nested within a batch"),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+fn put_varint(buf: &mut BytesMut, mut n: u64) {
+    while n >= 0x80 {
+        buf.put_u8((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.put_u8(n as u8);
+}
+
+fn put_ballot(buf: &mut BytesMut, Ballot(round, node): Ballot) {
+    put_varint(buf, u64::from(round));
+    put_varint(buf, u64::from(node));
+}
+
+fn put_bytes(buf: &mut BytesMut, val: &Bytes) {
+    put_varint(buf, val.len() as u64);
+    buf.put_slice(val);
+}
+
+fn get_u8(buf: &mut Bytes) -> Result<u8, DecodeError> {
+    if !buf.has_remaining() {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(buf.get_u8())
+}
+
+fn get_varint(buf: &mut Bytes) -> Result<u64, DecodeError> {
+    let mut n = 0u64;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = get_u8(buf)?;
+        let bits = u64::from(byte & 0x7f);
+        // the final byte holds a single bit of a u64
+        if i == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Err(DecodeError::Overflow);
+        }
+        n |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(DecodeError::Overflow)
+}
+
+fn get_u32(buf: &mut Bytes) -> Result<u32, DecodeError> {
+    let n = get_varint(buf)?;
+    if n > u64::from(u32::MAX) {
+        return Err(DecodeError::Overflow);
+    }
+    Ok(n as u32)
+}
+
+fn get_node(buf: &mut Bytes) -> Result<NodeId, DecodeError> {
+    get_u32(buf)
+}
+
+fn get_ballot(buf: &mut Bytes) -> Result<Ballot, DecodeError> {
+    Ok(Ballot(get_u32(buf)?, get_node(buf)?))
+}
+
+fn get_bytes(buf: &mut Bytes) -> Result<Bytes, DecodeError> {
+    let len = get_varint(buf)?;
+    if len > buf.remaining() as u64 {
+        return Err(DecodeError::Truncated);
+    }
+    Ok(buf.split_to(len as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::Proposal("123".into()),
+            Message::Forward("123".into()),
+            Message::Prepare(Ballot(1, 2)),
+            Message::Promise(2, Ballot(1, 2), 0, vec![]),
+            Message::Promise(
+                2,
+                Ballot(1, 2),
+                u64::MAX,
+                vec![(4, Ballot(0, 1), "4".into()), (u64::MAX, Ballot(u32::MAX, 1), "".into())],
+            ),
+            Message::Accept(300, Ballot(1, 2), "4".into()),
+            Message::Reject(3, Ballot(1, 2), Ballot(2, 0)),
+            Message::Accepted(3, 4, Ballot(1, 2)),
+            Message::Resolution(4, Ballot(1, 2), Bytes::from(vec![0u8; 1000])),
+            Message::SnapshotRequest(1, 5),
+            Message::InstallSnapshot(5, "snapshot".into()),
+            Message::Catchup(1, 2, 6),
+            Message::Heartbeat(Ballot(1, 2), 7),
+            Message::HeartbeatAck(u32::MAX, Ballot(1, 2), 7),
+            Message::Batch(vec![]),
+            Message::Batch(vec![
+                Message::Accept(300, Ballot(1, 2), "4".into()),
+                Message::Heartbeat(Ballot(1, 2), 7),
+            ]),
+        ]
+    }
+
+    #[test]
+    fn codec_roundtrip() {
+        for message in messages() {
+            assert_eq!(Ok(message.clone()), Message::decode(message.encode()));
+        }
+    }
+
+    #[test]
+    fn codec_compact() {
+        let encoded = Message::Accepted(3, 4, Ballot(1, 2)).encode();
+        assert_eq!(&[VERSION, ACCEPTED, 3, 4, 1, 2], &encoded[..]);
+
+        let encoded = Message::Accept(300, Ballot(1, 2), "4".into()).encode();
+        assert_eq!(&[VERSION, ACCEPT, 0xac, 0x02, 1, 2, 1, b'4'], &encoded[..]);
+    }
+
+    #[test]
+    fn codec_errors() {
+        assert_eq!(Err(DecodeError::Truncated), Message::decode(Bytes::new()));
+        assert_eq!(Err(DecodeError::UnsupportedVersion(9)), Message::decode(vec![9, 0].into()));
+        assert_eq!(Err(DecodeError::UnknownMessage(99)), Message::decode(vec![VERSION, 99].into()));
+
+        // PROMISE messages of version 1 lack the open window
+        let promise = Bytes::from(vec![1, PROMISE, 3, 1, 2, 1, 5, 1, 2, 1, b'4']);
+        assert_eq!(Err(DecodeError::UnsupportedVersion(1)), Message::decode(promise));
+
+        // every prefix of a message is truncated
+        let encoded = Message::Accept(300, Ballot(1, 2), "44".into()).encode();
+        for len in 0..encoded.len() {
+            assert_eq!(Err(DecodeError::Truncated), Message::decode(encoded.slice(..len)));
+        }
+
+        let mut trailing = BytesMut::from(&encoded[..]);
+        trailing.put_u8(0);
+        assert_eq!(Err(DecodeError::TrailingBytes(1)), Message::decode(trailing.freeze()));
+
+        // batches do not nest
+        let nested = Message::Batch(vec![Message::Batch(vec![Message::Catchup(1, 2, 6)])]);
+        assert_eq!(Err(DecodeError::NestedBatch), Message::decode(nested.encode()));
+
+        // node identifiers are 32 bits
+        let mut overflow = BytesMut::new();
+        overflow.put_u8(VERSION);
+        overflow.put_u8(SNAPSHOT_REQUEST);
+        put_varint(&mut overflow, u64::from(u32::MAX) + 1);
+        put_varint(&mut overflow, 0);
+        assert_eq!(Err(DecodeError::Overflow), Message::decode(overflow.freeze()));
+
+        let ballot = Bytes::from(vec![VERSION, PREPARE, 0xff, 0xff, 0xff, 0xff, 0x1f, 0]);
+        assert_eq!(Err(DecodeError::Overflow), Message::decode(ballot));
+
+        // varints are at most 64 bits
+        let mut varint = Bytes::from(vec![0xff; 11]);
+        assert_eq!(Err(DecodeError::Overflow), get_varint(&mut varint));
+        let mut varint =
+            Bytes::from(vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        assert_eq!(Ok(u64::MAX), get_varint(&mut varint));
+    }
+
+    #[test]
+    fn codec_zero_copy() {
+        let encoded = Message::Proposal(Bytes::from(vec![7u8; 64])).encode();
+        match Message::decode(encoded.clone()).unwrap() {
+            Message::Proposal(val) => assert_eq!(encoded[3..].as_ptr(), val.as_ptr()),
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+}
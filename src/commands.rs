@@ -1,9 +1,6 @@
-use crate::{Ballot, NodeId, ReplicatedState, Slot, SlottedValue};
+use crate::{Ballot, Message, NodeId, ReplicatedState, Slot, SlottedValue};
 use bytes::Bytes;
 
-#[cfg(test)]
-use std::iter::Extend;
-
 /// Sends commands to other replicas in addition to applying
 /// resolved commands at the current replica
 pub trait Sender {
@@ -20,20 +17,34 @@ pub trait Sender {
 
     /// Resolves the state machine to apply values.
     fn state_machine(&mut self) -> &mut Self::StateMachine;
+
+    /// Sends the messages buffered by the sender, if any. The replica
+    /// flushes the sender once it has handled an event, such as a received
+    /// message, a tick or a proposal.
+    fn flush(&mut self) {}
 }
 
 /// Receiver of Paxos commands.
 pub trait Commander {
-    /// Receive a proposal
+    /// Receive a proposal of a value from a client. The value is applied to
+    /// the state machine as-is, even if it resembles a control entry of the
+    /// log.
     fn proposal(&mut self, val: Bytes);
 
+    /// Receive an entry of the log forwarded by a follower to the
+    /// distinguished proposer. Unlike `proposal`, the entry may be a control
+    /// entry, such as a reconfiguration or a proposal tagged with its
+    /// identifier.
+    fn forward(&mut self, entry: Bytes);
+
     /// Receive a Phase 1a PREPARE message containing the proposed ballot
     fn prepare(&mut self, bal: Ballot);
 
     /// Receive a Phase 1b PROMISE message containing the node
-    /// that generated the promise, the ballot promised and all accepted
-    /// values within the open window.
-    fn promise(&mut self, node: NodeId, bal: Ballot, accepted: Vec<SlottedValue>);
+    /// that generated the promise, the ballot promised, the start of the
+    /// open window (all prior slots have been decided by the node) and all
+    /// accepted values within the open window.
+    fn promise(&mut self, node: NodeId, bal: Ballot, open: Slot, accepted: Vec<SlottedValue>);
 
     /// Receive a Phase 2a ACCEPT message that contains the the slot, proposed
     /// ballot and value of the proposal. The ballot contains the node of
@@ -55,50 +66,44 @@ pub trait Commander {
     /// NOTE: Resolutions may arrive out-of-order. No guarantees are made on
     /// slot order.
     fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes);
-}
-
-#[derive(PartialEq, Eq, Debug)]
-#[cfg(test)]
-pub enum Command {
-    Proposal(Bytes),
-    Prepare(Ballot),
-    Promise(NodeId, Ballot, Vec<(Slot, Ballot, Bytes)>),
-    Accept(Slot, Ballot, Bytes),
-    Reject(NodeId, Ballot, Ballot),
-    Accepted(NodeId, Slot, Ballot),
-    Resolution(Slot, Ballot, Bytes),
-}
-
-#[cfg(test)]
-impl<T> Commander for T
-where
-    T: Extend<Command>,
-{
-    fn proposal(&mut self, bytes: Bytes) {
-        self.extend(Some(Command::Proposal(bytes)));
-    }
-
-    fn prepare(&mut self, bal: Ballot) {
-        self.extend(Some(Command::Prepare(bal)));
-    }
 
-    fn promise(&mut self, node: NodeId, bal: Ballot, accepted: Vec<SlottedValue>) {
-        self.extend(Some(Command::Promise(node, bal, accepted)));
-    }
-
-    fn accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.extend(Some(Command::Accept(slot, bal, val)));
-    }
-
-    fn reject(&mut self, node: NodeId, proposed: Ballot, promised: Ballot) {
-        self.extend(Some(Command::Reject(node, proposed, promised)));
-    }
-
-    fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
-        self.extend(Some(Command::Accepted(node, slot, bal)));
-    }
-
-    fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.extend(Some(Command::Resolution(slot, bal, val)));
+    /// Receives a request from a lagging replica for the decisions starting
+    /// at a slot. If the slot has been compacted, the receiver responds with
+    /// a snapshot of the state machine followed by RESOLUTION messages for the
+    /// decided slots after the snapshot. Otherwise only the RESOLUTION
+    /// messages are sent.
+    fn snapshot_request(&mut self, node: NodeId, slot: Slot);
+
+    /// Receives a snapshot of the state machine taken at a slot, which
+    /// replaces the state of the replica up to and including the slot.
+    fn install_snapshot(&mut self, slot: Slot, snapshot: Bytes);
+
+    /// Receives a request from a replica for the resolutions of the slots
+    /// within `start..end`, which the replica has not resolved. The
+    /// receiver responds with RESOLUTION messages for the slots it has
+    /// resolved within the range, preceded by a snapshot if the start of the
+    /// range has been compacted.
+    fn catchup(&mut self, node: NodeId, start: Slot, end: Slot);
+
+    /// Receives a periodic HEARTBEAT from the distinguished proposer
+    /// containing its ballot along with an increasing sequence number.
+    /// Followers that stop receiving heartbeats start an election.
+    fn heartbeat(&mut self, bal: Ballot, seq: u64);
+
+    /// Receives an acknowledgement of a HEARTBEAT from a follower, which
+    /// confirms the leadership of the ballot as of the sequence number.
+    fn heartbeat_ack(&mut self, node: NodeId, bal: Ballot, seq: u64);
+
+    /// Receives a batch of messages sent to the node while the sending
+    /// replica handled a single event (see `BatchSender`). Batches do not
+    /// contain other batches, and `Message::dispatch` drops batches that do.
+    /// The default implementation delivers each message in order.
+    fn batch(&mut self, messages: Vec<Message>)
+    where
+        Self: Sized,
+    {
+        for message in messages {
+            message.dispatch(self);
+        }
     }
 }
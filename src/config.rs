@@ -1,17 +1,51 @@
-use crate::NodeId;
+use crate::{
+    quorum::{FlexibleQuorum, MajorityQuorum, QuorumSystem},
+    NodeId,
+};
 use std::{
-    cmp::Ordering,
+    cmp,
     collections::{hash_map, HashMap},
-    fmt,
+    error, fmt,
     net::SocketAddr,
+    sync::Arc,
 };
 
+/// Default number of ticks before unanswered messages are re-sent
+const DEFAULT_RETRANSMIT_TICKS: u64 = 10;
+
+/// Default number of ticks between heartbeats from the leader
+const DEFAULT_HEARTBEAT_TICKS: u64 = 3;
+
+/// Default range of ticks before a follower starts an election
+const DEFAULT_ELECTION_TIMEOUT: (u64, u64) = (10, 20);
+
+/// Default number of slots after which a reconfiguration takes effect
+const DEFAULT_ALPHA: u64 = 32;
+
+/// Builds the quorum system from the sorted voting members
+type QuorumBuilder = Arc<dyn Fn(&[NodeId]) -> Arc<dyn QuorumSystem> + Send + Sync>;
+
 /// Configuration holds the state of the membership of the cluster.
 #[derive(Clone)]
 pub struct Configuration {
     current: NodeId,
     peers: HashMap<NodeId, SocketAddr>,
+    learners: HashMap<NodeId, SocketAddr>,
     socket_to_peer: HashMap<SocketAddr, NodeId>,
+    snapshot_interval: Option<u64>,
+    retransmit_ticks: u64,
+    heartbeat_ticks: u64,
+    election_timeout: (u64, u64),
+    quorum: Option<Arc<dyn QuorumSystem>>,
+    quorum_builder: Option<QuorumBuilder>,
+    thrifty: bool,
+    alpha: u64,
+    read_lease: Option<LeaseConfig>,
+    proposal_timeout: Option<u64>,
+    proposal_queue: Option<QueueLimits>,
+    batching: Option<BatchLimits>,
+    max_in_flight: Option<u64>,
+    member: bool,
 }
 
 impl Configuration {
@@ -23,14 +57,368 @@ impl Configuration {
         let peers: HashMap<NodeId, SocketAddr> = peers.collect();
         let socket_to_peer: HashMap<SocketAddr, NodeId> =
             peers.iter().map(|e| (*e.1, *e.0)).collect();
-        Configuration { current, peers, socket_to_peer }
+        Configuration {
+            current,
+            peers,
+            learners: HashMap::new(),
+            socket_to_peer,
+            snapshot_interval: None,
+            retransmit_ticks: DEFAULT_RETRANSMIT_TICKS,
+            heartbeat_ticks: DEFAULT_HEARTBEAT_TICKS,
+            election_timeout: DEFAULT_ELECTION_TIMEOUT,
+            quorum: None,
+            quorum_builder: None,
+            thrifty: false,
+            alpha: DEFAULT_ALPHA,
+            read_lease: None,
+            proposal_timeout: None,
+            proposal_queue: None,
+            batching: None,
+            max_in_flight: None,
+            member: true,
+        }
+    }
+
+    /// Sets the number of executed slots between snapshots of the state
+    /// machine. Decisions covered by a snapshot are discarded from the log.
+    pub fn with_snapshot_interval(mut self, slots: u64) -> Configuration {
+        assert!(slots > 0);
+        self.snapshot_interval = Some(slots);
+        self
+    }
+
+    /// Number of executed slots between snapshots, if snapshots are enabled
+    pub fn snapshot_interval(&self) -> Option<u64> {
+        self.snapshot_interval
+    }
+
+    /// Sets the number of ticks after which PREPARE and ACCEPT messages
+    /// that have not been answered by a peer are re-sent.
+    pub fn with_retransmit_ticks(mut self, ticks: u64) -> Configuration {
+        assert!(ticks > 0);
+        self.retransmit_ticks = ticks;
+        self
+    }
+
+    /// Number of ticks after which unanswered messages are re-sent
+    pub fn retransmit_ticks(&self) -> u64 {
+        self.retransmit_ticks
+    }
+
+    /// Sets the number of ticks between heartbeats sent by the leader to
+    /// its followers.
+    pub fn with_heartbeat_ticks(mut self, ticks: u64) -> Configuration {
+        assert!(ticks > 0);
+        self.heartbeat_ticks = ticks;
+        self
+    }
+
+    /// Number of ticks between heartbeats from the leader
+    pub fn heartbeat_ticks(&self) -> u64 {
+        self.heartbeat_ticks
+    }
+
+    /// Sets the range of ticks, inclusive, after which a follower that has
+    /// not heard from the leader starts an election. The timeout is drawn
+    /// randomly from the range and should be well above the heartbeat
+    /// interval.
+    pub fn with_election_timeout(mut self, min_ticks: u64, max_ticks: u64) -> Configuration {
+        assert!(min_ticks > 0 && min_ticks <= max_ticks);
+        self.election_timeout = (min_ticks, max_ticks);
+        self
+    }
+
+    /// Minimum and maximum number of ticks before a follower starts an
+    /// election
+    pub fn election_timeout(&self) -> (u64, u64) {
+        self.election_timeout
+    }
+
+    /// Sets distinct sizes for Phase 1 and Phase 2 quorums (Flexible Paxos).
+    ///
+    /// Any Phase 1 quorum must intersect every Phase 2 quorum, so the sizes
+    /// must satisfy `phase1 + phase2 > n` where `n` is the number of voting
+    /// members, including the current node unless it is a learner (see
+    /// `as_learner`). When the membership changes, the Phase 2 size is kept
+    /// where possible and the Phase 1 size is raised as needed for the
+    /// quorums to intersect.
+    pub fn with_quorum_size(
+        self,
+        phase1: usize,
+        phase2: usize,
+    ) -> Result<Configuration, ConfigError> {
+        let nodes = self.nodes();
+        if phase1 == 0
+            || phase1 > nodes
+            || phase2 == 0
+            || phase2 > nodes
+            || phase1 + phase2 <= nodes
+        {
+            return Err(ConfigError::InvalidQuorum { phase1, phase2, nodes });
+        }
+        Ok(self.with_quorum_system(move |members: &[NodeId]| {
+            let nodes = members.len();
+            let phase2 = cmp::min(phase2, cmp::max(nodes, 1));
+            let phase1 = cmp::max(cmp::min(phase1, nodes), nodes + 1 - phase2);
+            FlexibleQuorum::new(cmp::max(phase1, 1), phase2)
+        }))
+    }
+
+    /// Sets the quorum system that decides which sets of nodes form Phase 1
+    /// and Phase 2 quorums, such as `GridQuorum` or `WeightedQuorum`.
+    ///
+    /// The quorum system is built from the sorted voting members, and is
+    /// rebuilt whenever the membership changes (see `reconfigure`).
+    pub fn with_quorum_system<F, Q>(mut self, builder: F) -> Configuration
+    where
+        F: Fn(&[NodeId]) -> Q + Send + Sync + 'static,
+        Q: QuorumSystem + 'static,
+    {
+        self.quorum_builder = Some(Arc::new(move |members: &[NodeId]| {
+            Arc::new(builder(members)) as Arc<dyn QuorumSystem>
+        }));
+        self.rebuild_quorum();
+        self
     }
 
-    /// Size of phase 1 and phase 2 quorums.
+    /// Quorum system for phase 1 and phase 2 quorums. Unless configured
+    /// otherwise, both phases use a simple majority of the nodes.
+    pub fn quorum_system(&self) -> Arc<dyn QuorumSystem> {
+        match self.quorum {
+            Some(ref quorum) => quorum.clone(),
+            None => Arc::new(MajorityQuorum::with_members(self.members())),
+        }
+    }
+
+    /// Number of nodes in the smallest phase 1 and phase 2 quorums of the
+    /// quorum system.
     pub fn quorum_size(&self) -> (usize, usize) {
-        // TODO: allow flexible quorum
-        let size = 1 + (self.peers.len() / 2);
-        (size, size)
+        self.quorum_system().quorum_size()
+    }
+
+    /// Enables thrifty mode, in which PREPARE and ACCEPT messages are only
+    /// sent to the peers required for quorum, preferring peers that have
+    /// responded most recently. Peers that do not respond are replaced with
+    /// the remaining peers once messages are re-sent (see
+    /// `with_retransmit_ticks`).
+    pub fn with_thrifty(mut self, thrifty: bool) -> Configuration {
+        self.thrifty = thrifty;
+        self
+    }
+
+    /// Flag indicating whether thrifty mode is enabled
+    pub fn thrifty(&self) -> bool {
+        self.thrifty
+    }
+
+    /// Sets the number of slots, α, after which a reconfiguration decided at
+    /// slot `s` takes effect (at slot `s + α`). The leader does not propose
+    /// values for slots `>= e + α + 1` until slot `e` has been executed, which
+    /// bounds the number of slots in flight.
+    pub fn with_alpha(mut self, alpha: u64) -> Configuration {
+        assert!(alpha > 0);
+        self.alpha = alpha;
+        self
+    }
+
+    /// Number of slots after which a reconfiguration takes effect
+    pub fn alpha(&self) -> u64 {
+        self.alpha
+    }
+
+    /// Bounds the number of open slots at the leader, from the first
+    /// unresolved slot onward. Proposals beyond the bound wait in the queue
+    /// until earlier slots are resolved, which limits the instances in flight
+    /// under load. By default only α bounds the open slots.
+    pub fn with_max_in_flight(mut self, slots: u64) -> Configuration {
+        assert!(slots > 0);
+        self.max_in_flight = Some(slots);
+        self
+    }
+
+    /// Maximum number of open slots at the leader, if bounded
+    pub fn max_in_flight(&self) -> Option<u64> {
+        self.max_in_flight
+    }
+
+    /// Enables read leases, allowing the leader to serve linearizable reads
+    /// from its state machine (see `Replica::read`).
+    ///
+    /// Acceptors that send a PROMISE or ACCEPTED for a ballot do not promise
+    /// any other ballot for `lease_ticks` ticks, delaying elections after a
+    /// leader fails by up to the lease. The leader holds the lease for
+    /// `lease_ticks - drift_ticks` ticks from when it sent the messages, where
+    /// `drift_ticks` bounds how far the clocks of nodes may drift apart over a
+    /// lease. The leader renews the lease with each heartbeat, so the lease
+    /// should be well above the heartbeat interval (see
+    /// `with_heartbeat_ticks`). Grants are not persisted, so a recovered
+    /// acceptor does not promise any ballot for `lease_ticks + drift_ticks`
+    /// ticks.
+    pub fn with_read_lease(mut self, lease_ticks: u64, drift_ticks: u64) -> Configuration {
+        assert!(lease_ticks > drift_ticks);
+        self.read_lease = Some(LeaseConfig { lease_ticks, drift_ticks });
+        self
+    }
+
+    /// Duration of read leases and the bound on clock drift, if read leases
+    /// are enabled
+    pub fn read_lease(&self) -> Option<LeaseConfig> {
+        self.read_lease
+    }
+
+    /// Abandons proposals submitted with `Replica::propose` that have not
+    /// been decided within a number of ticks, notifying the state machine
+    /// with `ReplicatedState::proposal_abandoned`. By default proposals are
+    /// never abandoned.
+    ///
+    /// An abandoned proposal may still be decided, such as when the value was
+    /// forwarded to a leader that has since failed but had sent ACCEPT
+    /// messages for the value. A decision within α slots (see `with_alpha`)
+    /// of the slots known when the proposal was abandoned is reported with
+    /// `ReplicatedState::proposal_decided`, while later decisions are not
+    /// reported.
+    pub fn with_proposal_timeout(mut self, ticks: u64) -> Configuration {
+        assert!(ticks > 0);
+        self.proposal_timeout = Some(ticks);
+        self
+    }
+
+    /// Number of ticks after which undecided proposals are abandoned, if any
+    pub fn proposal_timeout(&self) -> Option<u64> {
+        self.proposal_timeout
+    }
+
+    /// Bounds the queue of values awaiting a slot to a number of values and
+    /// a budget of bytes. Values are queued while an election is in progress
+    /// or the leader is limited by α. Once the queue is full, proposals are
+    /// rejected with `ProposeError::WouldBlock`. By default the queue is
+    /// unbounded.
+    pub fn with_proposal_queue(mut self, capacity: usize, bytes: usize) -> Configuration {
+        assert!(capacity > 0);
+        self.proposal_queue = Some(QueueLimits { capacity, bytes });
+        self
+    }
+
+    /// Maximum number of values and bytes of the proposal queue, if bounded
+    pub fn proposal_queue(&self) -> Option<QueueLimits> {
+        self.proposal_queue
+    }
+
+    /// Enables batching of proposals at the leader, such that values queued
+    /// within a window of `ticks` are proposed within a single slot. A batch
+    /// is proposed once it holds `count` values or `bytes` in total, or once
+    /// the oldest value has waited `ticks` ticks. Values of a batch are
+    /// executed individually, in the order they were queued.
+    pub fn with_batching(mut self, count: usize, bytes: usize, ticks: u64) -> Configuration {
+        assert!(count > 0);
+        self.batching = Some(BatchLimits { count, bytes, ticks });
+        self
+    }
+
+    /// Maximum number of values, bytes and ticks of a batch, if batching is
+    /// enabled
+    pub fn batching(&self) -> Option<BatchLimits> {
+        self.batching
+    }
+
+    /// Adds learners to the configuration. Learners are sent the decisions
+    /// of the leader and execute them against their state machine, but do not
+    /// take part in either phase of the algorithm, so they do not slow down
+    /// consensus.
+    pub fn with_learners<I>(mut self, learners: I) -> Configuration
+    where
+        I: Iterator<Item = (NodeId, SocketAddr)>,
+    {
+        for (node, addr) in learners {
+            if node == self.current || self.peers.contains_key(&node) {
+                continue;
+            }
+            if let Some(old) = self.learners.insert(node, addr) {
+                self.socket_to_peer.remove(&old);
+            }
+            self.socket_to_peer.insert(addr, node);
+        }
+        self
+    }
+
+    /// Configures the current node as a learner. The node executes the
+    /// decisions it is sent, but never sends promises or accepts values, and
+    /// does not start elections. Proposals are forwarded to the leader.
+    pub fn as_learner(mut self) -> Configuration {
+        self.member = false;
+        self.rebuild_quorum();
+        self
+    }
+
+    /// Flag indicating whether the current node is a member of the
+    /// configuration. Learners, as well as nodes removed from the
+    /// configuration, are not members and do not start elections.
+    pub fn is_member(&self) -> bool {
+        self.member
+    }
+
+    /// Applies a change to the membership of the configuration, rebuilding
+    /// the quorum system from the new voting members.
+    ///
+    /// Removing the last voting member is rejected, leaving the
+    /// configuration unchanged.
+    pub fn reconfigure(&mut self, change: &Reconfiguration) -> Result<(), ConfigError> {
+        if let Reconfiguration::RemoveNode(node) = *change {
+            let member =
+                if node == self.current { self.member } else { self.peers.contains_key(&node) };
+            if member && self.nodes() == 1 {
+                return Err(ConfigError::NoMembers);
+            }
+        }
+
+        match *change {
+            Reconfiguration::AddNode(node, _) if node == self.current => {
+                self.member = true;
+            }
+            Reconfiguration::AddNode(node, addr) => {
+                // learners are promoted to members
+                if let Some(old) = self.learners.remove(&node) {
+                    self.socket_to_peer.remove(&old);
+                }
+                if let Some(old) = self.peers.insert(node, addr) {
+                    self.socket_to_peer.remove(&old);
+                }
+                self.socket_to_peer.insert(addr, node);
+            }
+            Reconfiguration::RemoveNode(node) if node == self.current => {
+                self.member = false;
+            }
+            Reconfiguration::RemoveNode(node) => {
+                if let Some(addr) = self.peers.remove(&node).or_else(|| self.learners.remove(&node))
+                {
+                    self.socket_to_peer.remove(&addr);
+                }
+            }
+        }
+        self.rebuild_quorum();
+        Ok(())
+    }
+
+    /// Rebuilds the configured quorum system for the current voting members
+    fn rebuild_quorum(&mut self) {
+        if let Some(ref builder) = self.quorum_builder {
+            self.quorum = Some(builder(&self.members()));
+        }
+    }
+
+    /// Sorted voting members, including the current node unless it is a
+    /// learner
+    fn members(&self) -> Vec<NodeId> {
+        let current = if self.member { Some(self.current) } else { None };
+        let mut members = self.peers.keys().cloned().chain(current).collect::<Vec<_>>();
+        members.sort();
+        members
+    }
+
+    /// Number of voting members, including the current node unless it is a
+    /// learner
+    fn nodes(&self) -> usize {
+        self.peers.len() + if self.member { 1 } else { 0 }
     }
 
     /// Current node identifier
@@ -43,24 +431,106 @@ impl Configuration {
         PeerIntoIter { r: &self }
     }
 
-    /// Gets all addresses contained in the configuration
+    /// Iterator containing `NodeId` values of learners
+    pub fn learners<'a>(&'a self) -> impl Iterator<Item = NodeId> + 'a {
+        self.learners.keys().cloned()
+    }
+
+    /// Gets all addresses contained in the configuration, including the
+    /// addresses of learners
     pub fn addresses<'a>(&'a self) -> impl Iterator<Item = (NodeId, SocketAddr)> + 'a {
-        self.peers.iter().map(|(node, addr)| (*node, *addr))
+        self.peers.iter().chain(self.learners.iter()).map(|(node, addr)| (*node, *addr))
     }
 }
 
 impl fmt::Debug for Configuration {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        let quorum_size = self.quorum_size();
+        let quorum = self.quorum_system();
         fmt.debug_struct("Configuration")
             .field("current_node_id", &self.current)
             .field("peers", &self.peers)
+            .field("learners", &self.learners)
             .field("peers_to_socket", &self.socket_to_peer)
-            .field("quorum", &quorum_size)
+            .field("quorum", &quorum)
+            .field("snapshot_interval", &self.snapshot_interval)
+            .field("retransmit_ticks", &self.retransmit_ticks)
+            .field("heartbeat_ticks", &self.heartbeat_ticks)
+            .field("election_timeout", &self.election_timeout)
+            .field("thrifty", &self.thrifty)
+            .field("alpha", &self.alpha)
+            .field("read_lease", &self.read_lease)
+            .field("proposal_timeout", &self.proposal_timeout)
+            .field("proposal_queue", &self.proposal_queue)
+            .field("batching", &self.batching)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("member", &self.member)
             .finish()
     }
 }
 
+/// Timing of read leases (see `Configuration::with_read_lease`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LeaseConfig {
+    /// Ticks for which acceptors grant the lease
+    pub lease_ticks: u64,
+    /// Bound on the drift between clocks of nodes over a lease, in ticks
+    pub drift_ticks: u64,
+}
+
+/// Limits of the proposal queue (see `Configuration::with_proposal_queue`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueLimits {
+    /// Maximum number of queued values
+    pub capacity: usize,
+    /// Budget of bytes across the queued values
+    pub bytes: usize,
+}
+
+/// Limits of a batch of proposals (see `Configuration::with_batching`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// Maximum number of values in a batch
+    pub count: usize,
+    /// Budget of bytes across the values of a batch
+    pub bytes: usize,
+    /// Ticks the oldest queued value waits before a batch is proposed
+    pub ticks: u64,
+}
+
+/// Change to the membership of the cluster, replicated through the log.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reconfiguration {
+    /// Adds a node, or updates the address of an existing node
+    AddNode(NodeId, SocketAddr),
+    /// Removes a node
+    RemoveNode(NodeId),
+}
+
+/// Error returned for an invalid configuration
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConfigError {
+    /// Phase 1 and Phase 2 quorums of the sizes are not guaranteed to
+    /// intersect within the number of nodes
+    InvalidQuorum { phase1: usize, phase2: usize, nodes: usize },
+    /// The change would remove the last voting member
+    NoMembers,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::InvalidQuorum { phase1, phase2, nodes } => write!(
+                fmt,
+                "invalid quorum sizes {} (Phase 1) and {} (Phase 2) for {} nodes",
+                phase1, phase2, nodes
+            ),
+            ConfigError::NoMembers => write!(fmt, "the last voting member cannot be removed"),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
 /// `IntoIterator` for peer node identifiers
 pub struct PeerIntoIter<'a> {
     r: &'a Configuration,
@@ -88,52 +558,59 @@ impl<'a> Iterator for PeerIter<'a> {
     }
 }
 
+/// Phase of the Paxos algorithm for which a quorum is tracked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Phase 1 (PREPARE/PROMISE)
+    One,
+    /// Phase 2 (ACCEPT/ACCEPTED)
+    Two,
+}
+
 /// `QuorumSet` tracks nodes that have sent certain messages and will
-/// detect when quorum is reached. Duplicates are treated as a single
-/// message to determine quorum.
+/// detect when quorum is reached according to the quorum system.
+/// Duplicates are treated as a single message to determine quorum.
 ///
 /// Once the `QuorumSet` has quorum, additional nodes will not be added.
 /// The purpose of the datastructure is to track _when_ quorum is
 /// reached rather than being a general purpose set.
 #[derive(Clone, Debug)]
 pub struct QuorumSet {
-    // Instead of using a HashSet or Vec, which may allocate more
-    // than once, the QuorumSet has a specific size as a sized slice.
-    // The datastructure ensures that the node IDs are stored in
-    // sorted order.
-    //
+    system: Arc<dyn QuorumSystem>,
+    phase: Phase,
     // Quorums are typically small (2-4 nodes) so a smaller
     // data structure that isn't fancy is appropriate both
     // from a run time perspective and space perspective.
-    values: Box<[Option<NodeId>]>,
+    // The datastructure ensures that the node IDs are stored in
+    // sorted order.
+    values: Vec<NodeId>,
+    quorum: bool,
 }
 
 impl QuorumSet {
-    /// Creates a QuorumSet with a given size for quorum.
-    pub fn with_size(size: usize) -> QuorumSet {
-        assert!(size > 0);
-        QuorumSet { values: vec![None; size].into_boxed_slice() }
+    /// Creates a QuorumSet for a phase of the quorum system.
+    pub fn new(system: Arc<dyn QuorumSystem>, phase: Phase) -> QuorumSet {
+        QuorumSet { system, phase, values: Vec::new(), quorum: false }
     }
 
-    /// Size of the quorum
-    pub fn len(&self) -> usize {
-        self.values.len()
+    /// Creates an empty QuorumSet with the same quorum system and phase.
+    pub fn cleared(&self) -> QuorumSet {
+        QuorumSet::new(self.system.clone(), self.phase)
     }
 
-    /// Flag indicating whether quorum has been reached.
-    pub fn has_quorum(&self) -> bool {
-        let s = &self.values;
-        assert!(s.len() > 0);
-        s[s.len() - 1].is_some()
+    /// Creates a QuorumSet containing the same nodes for another quorum
+    /// system.
+    pub fn reconfigure(&self, system: Arc<dyn QuorumSystem>) -> QuorumSet {
+        let mut set = QuorumSet::new(system, self.phase);
+        for node in self.values.iter() {
+            set.insert(*node);
+        }
+        set
     }
 
-    #[inline]
-    fn binary_search(&self, n: NodeId) -> Result<usize, usize> {
-        // TODO: remove binary search in favor of linear
-        self.values.binary_search_by(move |v| match *v {
-            Some(v) => v.cmp(&n),
-            None => Ordering::Greater,
-        })
+    /// Flag indicating whether quorum has been reached.
+    pub fn has_quorum(&self) -> bool {
+        self.quorum
     }
 
     /// Inserts a node into the set
@@ -142,73 +619,60 @@ impl QuorumSet {
             return;
         }
 
-        let loc = self.binary_search(n);
-        if let Err(loc) = loc {
-            // if theres an existing occupant, then move
-            // all the values over to the right to make
-            // a hole for the new value in the correct
-            // place
-            if self.values[loc].is_some() {
-                let len = self.values.len();
-                for i in (loc..len - 1).rev() {
-                    self.values.swap(i, i + 1);
-                }
-            }
-
-            self.values[loc] = Some(n);
+        if let Err(loc) = self.values.binary_search(&n) {
+            self.values.insert(loc, n);
+            self.quorum = match self.phase {
+                Phase::One => self.system.is_phase1_quorum(&self.values),
+                Phase::Two => self.system.is_phase2_quorum(&self.values),
+            };
         }
     }
 
     /// Flag indicating whether the set contains a given node
     pub fn contains(&self, n: NodeId) -> bool {
-        self.binary_search(n).is_ok()
-    }
-
-    /// Flag indicating whether the set is empty
-    #[allow(dead_code)]
-    pub fn is_empty(&self) -> bool {
-        self.values[0].is_none()
+        self.values.binary_search(&n).is_ok()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::GridQuorum;
     use test;
 
     #[test]
     fn quorumset() {
-        let mut qs = QuorumSet::with_size(4);
+        let mut qs = QuorumSet::new(Arc::new(FlexibleQuorum::new(4, 4)), Phase::One);
 
         assert!(!qs.has_quorum());
-        assert!(qs.is_empty());
+        assert!(qs.values.is_empty());
 
         qs.insert(5);
         assert!(qs.contains(5));
         assert!(!qs.has_quorum());
-        assert!(!qs.is_empty());
-        assert_eq!(&[Some(5), None, None, None], qs.values.as_ref());
+        assert!(!qs.values.is_empty());
+        assert_eq!(&[5], qs.values.as_slice());
 
         qs.insert(7);
         assert!(qs.contains(7));
         assert!(!qs.has_quorum());
-        assert!(!qs.is_empty());
-        assert_eq!(&[Some(5), Some(7), None, None], qs.values.as_ref());
+        assert!(!qs.values.is_empty());
+        assert_eq!(&[5, 7], qs.values.as_slice());
 
         qs.insert(7);
         assert!(qs.contains(5));
         assert!(qs.contains(7));
         assert!(!qs.has_quorum());
-        assert!(!qs.is_empty());
-        assert_eq!(&[Some(5), Some(7), None, None], qs.values.as_ref());
+        assert!(!qs.values.is_empty());
+        assert_eq!(&[5, 7], qs.values.as_slice());
 
         qs.insert(2);
         assert!(qs.contains(5));
         assert!(qs.contains(7));
         assert!(qs.contains(2));
         assert!(!qs.has_quorum());
-        assert!(!qs.is_empty());
-        assert_eq!(&[Some(2), Some(5), Some(7), None], qs.values.as_ref());
+        assert!(!qs.values.is_empty());
+        assert_eq!(&[2, 5, 7], qs.values.as_slice());
 
         qs.insert(6);
         assert!(qs.contains(5));
@@ -216,29 +680,168 @@ mod tests {
         assert!(qs.contains(2));
         assert!(qs.contains(6));
         assert!(qs.has_quorum());
-        assert!(!qs.is_empty());
-        assert_eq!(&[Some(2), Some(5), Some(6), Some(7)], qs.values.as_ref());
+        assert!(!qs.values.is_empty());
+        assert_eq!(&[2, 5, 6, 7], qs.values.as_slice());
 
         // ignroe adds when there is quorum
         qs.insert(10);
-        assert_eq!(&[Some(2), Some(5), Some(6), Some(7)], qs.values.as_ref());
+        assert_eq!(&[2, 5, 6, 7], qs.values.as_slice());
     }
 
     #[test]
     fn quorum_one() {
-        let mut qs = QuorumSet::with_size(1);
-        assert!(qs.is_empty());
+        let mut qs = QuorumSet::new(Arc::new(FlexibleQuorum::new(1, 1)), Phase::One);
+        assert!(qs.values.is_empty());
         assert!(!qs.has_quorum());
 
         qs.insert(5);
-        assert!(!qs.is_empty());
+        assert!(!qs.values.is_empty());
         assert!(qs.has_quorum());
     }
 
+    fn config(peers: u32) -> Configuration {
+        Configuration::new(
+            0,
+            (1..=peers).map(|n| (n, format!("127.0.0.1:{}", 4000 + n).parse().unwrap())),
+        )
+    }
+
+    #[test]
+    fn quorum_size() {
+        let majority = config(2).quorum_system();
+        assert!(!majority.is_phase1_quorum(&[0]));
+        assert!(majority.is_phase1_quorum(&[0, 1]));
+        assert!(majority.is_phase2_quorum(&[0, 1]));
+        let majority = config(3).quorum_system();
+        assert!(!majority.is_phase1_quorum(&[0, 1]));
+        assert!(majority.is_phase1_quorum(&[0, 1, 2]));
+        assert!(majority.is_phase2_quorum(&[0, 1, 2]));
+        let majority = config(4).quorum_system();
+        assert!(majority.is_phase1_quorum(&[0, 1, 2]));
+        assert!(majority.is_phase2_quorum(&[0, 1, 2]));
+        assert_eq!((3, 3), config(4).quorum_size());
+
+        let flexible = config(4).with_quorum_size(4, 2).unwrap().quorum_system();
+        assert!(!flexible.is_phase1_quorum(&[0, 1, 2]));
+        assert!(flexible.is_phase1_quorum(&[0, 1, 2, 3]));
+        assert!(!flexible.is_phase2_quorum(&[0]));
+        assert!(flexible.is_phase2_quorum(&[0, 1]));
+        assert_eq!((4, 2), config(4).with_quorum_size(4, 2).unwrap().quorum_size());
+        let flexible = config(5).with_quorum_size(5, 2).unwrap().quorum_system();
+        assert!(flexible.is_phase1_quorum(&[0, 1, 2, 3, 4]));
+        assert!(flexible.is_phase2_quorum(&[0, 1]));
+
+        assert_eq!(
+            Err(ConfigError::InvalidQuorum { phase1: 3, phase2: 2, nodes: 5 }),
+            config(4).with_quorum_size(3, 2).map(|_| ())
+        );
+        assert!(config(4).with_quorum_size(0, 5).is_err());
+        assert!(config(4).with_quorum_size(6, 3).is_err());
+
+        // a single acceptor may form a quorum if the sizes intersect
+        assert!(config(4).with_quorum_size(5, 1).is_ok());
+        assert!(config(0).with_quorum_size(1, 1).is_ok());
+        assert!(config(1).with_quorum_size(2, 1).is_ok());
+
+        // learners do not count towards the number of nodes
+        assert!(config(4).as_learner().with_quorum_size(3, 2).is_ok());
+        assert_eq!(
+            Err(ConfigError::InvalidQuorum { phase1: 2, phase2: 2, nodes: 4 }),
+            config(4).as_learner().with_quorum_size(2, 2).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn reconfigure() {
+        let mut config = config(2);
+        let add = Reconfiguration::AddNode(3, "127.0.0.1:4003".parse().unwrap());
+        assert_eq!(Ok(()), config.reconfigure(&add));
+        assert_eq!(vec![1, 2, 3], sorted_peers(&config));
+        assert!(!config.quorum_system().is_phase2_quorum(&[0, 1]));
+        assert!(config.quorum_system().is_phase2_quorum(&[0, 1, 2]));
+
+        assert_eq!(Ok(()), config.reconfigure(&Reconfiguration::RemoveNode(1)));
+        assert_eq!(vec![2, 3], sorted_peers(&config));
+        assert_eq!(2, config.addresses().count());
+        assert!(config.is_member());
+
+        assert_eq!(Ok(()), config.reconfigure(&Reconfiguration::RemoveNode(0)));
+        assert_eq!(vec![2, 3], sorted_peers(&config));
+        assert!(!config.is_member());
+    }
+
+    #[test]
+    fn reconfigure_quorum_system() {
+        // the Phase 1 size grows with the membership to intersect Phase 2
+        let mut flexible = config(2).with_quorum_size(3, 2).unwrap();
+        let add = Reconfiguration::AddNode(3, "127.0.0.1:4003".parse().unwrap());
+        assert_eq!(Ok(()), flexible.reconfigure(&add));
+        assert_eq!((3, 2), flexible.quorum_size());
+        let add = Reconfiguration::AddNode(4, "127.0.0.1:4004".parse().unwrap());
+        assert_eq!(Ok(()), flexible.reconfigure(&add));
+        assert_eq!((4, 2), flexible.quorum_size());
+        assert_eq!(Ok(()), flexible.reconfigure(&Reconfiguration::RemoveNode(4)));
+        assert_eq!((3, 2), flexible.quorum_size());
+        assert_eq!(Ok(()), flexible.reconfigure(&Reconfiguration::RemoveNode(0)));
+        assert_eq!(Ok(()), flexible.reconfigure(&Reconfiguration::RemoveNode(1)));
+        assert_eq!((2, 2), flexible.quorum_size());
+
+        // custom quorum systems are rebuilt from the voting members
+        let mut grid = config(3).with_quorum_system(|members: &[NodeId]| {
+            GridQuorum::new(members.chunks(2).map(|row| row.to_vec()).collect())
+        });
+        assert!(grid.quorum_system().is_phase1_quorum(&[0, 1]));
+        assert_eq!(Ok(()), grid.reconfigure(&Reconfiguration::RemoveNode(1)));
+        assert!(!grid.quorum_system().is_phase1_quorum(&[0, 1]));
+        assert!(grid.quorum_system().is_phase1_quorum(&[0, 2]));
+        assert!(grid.quorum_system().is_phase2_quorum(&[0, 3]));
+
+        // the last voting member cannot be removed
+        let mut single = config(1);
+        assert_eq!(Ok(()), single.reconfigure(&Reconfiguration::RemoveNode(1)));
+        assert_eq!(
+            Err(ConfigError::NoMembers),
+            single.reconfigure(&Reconfiguration::RemoveNode(0))
+        );
+        assert!(single.is_member());
+    }
+
+    #[test]
+    fn learners() {
+        let config = config(2)
+            .with_learners(vec![(5, "127.0.0.1:4005".parse().unwrap())].into_iter())
+            .with_learners(vec![(1, "127.0.0.1:4001".parse().unwrap())].into_iter());
+        assert_eq!(vec![1, 2], sorted_peers(&config));
+        assert_eq!(vec![5], config.learners().collect::<Vec<_>>());
+        assert_eq!(3, config.addresses().count());
+
+        // learners do not count towards quorum
+        assert!(!config.quorum_system().is_phase2_quorum(&[0, 5]));
+        assert!(!config.clone().as_learner().is_member());
+
+        let mut promoted = config.clone();
+        promoted
+            .reconfigure(&Reconfiguration::AddNode(5, "127.0.0.1:4005".parse().unwrap()))
+            .unwrap();
+        assert_eq!(vec![1, 2, 5], sorted_peers(&promoted));
+        assert_eq!(0, promoted.learners().count());
+
+        let mut removed = config;
+        removed.reconfigure(&Reconfiguration::RemoveNode(5)).unwrap();
+        assert_eq!(0, removed.learners().count());
+        assert_eq!(2, removed.addresses().count());
+    }
+
+    fn sorted_peers(config: &Configuration) -> Vec<NodeId> {
+        let mut peers = config.peers().into_iter().collect::<Vec<_>>();
+        peers.sort();
+        peers
+    }
+
     #[bench]
     fn bench_quorum_set(b: &mut test::Bencher) {
         b.iter(|| {
-            let mut qs = QuorumSet::with_size(5);
+            let mut qs = QuorumSet::new(Arc::new(FlexibleQuorum::new(5, 5)), Phase::One);
             qs.insert(5);
             qs.has_quorum();
             qs.insert(2);
@@ -0,0 +1,335 @@
+use crate::{
+    Configuration, MemoryStorage, Message, NodeId, ProposalId, ProposeError, Replica,
+    ReplicatedState, Sender, Slot, Storage,
+};
+use bytes::Bytes;
+use futures::{
+    channel::{mpsc, oneshot},
+    select,
+    sink::{Sink, SinkExt},
+    stream::{Fuse, Stream, StreamExt},
+    Future,
+};
+use std::{collections::HashMap, io};
+
+type Completion = oneshot::Sender<Result<Slot, ProposeError>>;
+
+/// Asynchronous driver of a `Replica`.
+///
+/// The driver owns the replica along with a stream of inbound messages, a
+/// timer stream and a sink of outbound messages addressed to peers. Each
+/// item of the timer ticks the replica. Values are proposed with a `Handle`,
+/// which resolves once the value has been executed by the state machine.
+pub struct Driver<R, I, T, O, St = MemoryStorage> {
+    replica: Replica<Outbox<R>, St>,
+    inbound: Fuse<I>,
+    timer: Fuse<T>,
+    outbound: O,
+    proposals: mpsc::UnboundedReceiver<(Bytes, Completion)>,
+}
+
+/// Handle used to submit proposals to a `Driver`.
+#[derive(Clone)]
+pub struct Handle {
+    proposals: mpsc::UnboundedSender<(Bytes, Completion)>,
+}
+
+impl Handle {
+    /// Proposes a value, resolving to the slot at which the value was
+    /// decided once it has been executed by the state machine.
+    pub fn propose(&self, val: Bytes) -> impl Future<Output = Result<Slot, ProposeError>> {
+        let (tx, rx) = oneshot::channel();
+        let submitted = self.proposals.unbounded_send((val, tx)).is_ok();
+        async move {
+            if !submitted {
+                return Err(ProposeError::Stopped);
+            }
+            rx.await.unwrap_or(Err(ProposeError::Stopped))
+        }
+    }
+}
+
+impl<R, I, T, O> Driver<R, I, T, O>
+where
+    R: ReplicatedState,
+    I: Stream<Item = Message> + Unpin,
+    T: Stream + Unpin,
+    O: Sink<(NodeId, Message)> + Unpin,
+{
+    /// Creates a driver for a replica with acceptor state kept in memory,
+    /// along with a handle to submit proposals.
+    pub fn new(
+        config: Configuration,
+        state_machine: R,
+        inbound: I,
+        timer: T,
+        outbound: O,
+    ) -> (Driver<R, I, T, O>, Handle) {
+        let outbox = Outbox::new(state_machine);
+        Driver::from_replica(Replica::new(outbox, config), inbound, timer, outbound)
+    }
+}
+
+impl<R, I, T, O, St> Driver<R, I, T, O, St>
+where
+    R: ReplicatedState,
+    I: Stream<Item = Message> + Unpin,
+    T: Stream + Unpin,
+    O: Sink<(NodeId, Message)> + Unpin,
+    St: Storage,
+{
+    /// Creates a driver for a replica recovered from acceptor state
+    /// persisted in the storage, along with a handle to submit proposals.
+    pub fn recover(
+        config: Configuration,
+        state_machine: R,
+        storage: St,
+        inbound: I,
+        timer: T,
+        outbound: O,
+    ) -> io::Result<(Self, Handle)> {
+        let outbox = Outbox::new(state_machine);
+        let replica = Replica::recover(outbox, config, storage)?;
+        Ok(Driver::from_replica(replica, inbound, timer, outbound))
+    }
+
+    fn from_replica(
+        replica: Replica<Outbox<R>, St>,
+        inbound: I,
+        timer: T,
+        outbound: O,
+    ) -> (Driver<R, I, T, O, St>, Handle) {
+        let (tx, rx) = mpsc::unbounded();
+        let driver = Driver {
+            replica,
+            inbound: inbound.fuse(),
+            timer: timer.fuse(),
+            outbound,
+            proposals: rx,
+        };
+        (driver, Handle { proposals: tx })
+    }
+
+    /// Configuration of the replica
+    pub fn config(&self) -> &Configuration {
+        self.replica.config()
+    }
+
+    /// State machine of the replica
+    pub fn state_machine(&self) -> &R {
+        &self.replica.sender().state_machine.inner
+    }
+
+    /// Runs the replica until the inbound stream ends, sending messages
+    /// produced by the replica to the outbound sink after each event.
+    pub async fn run(mut self) -> Result<(), O::Error> {
+        loop {
+            select! {
+                msg = self.inbound.next() => match msg {
+                    Some(msg) => msg.dispatch(&mut self.replica),
+                    None => break,
+                },
+                tick = self.timer.next() => {
+                    if tick.is_some() {
+                        self.replica.tick();
+                    }
+                },
+                proposal = self.proposals.next() => {
+                    if let Some((val, completion)) = proposal {
+                        match self.replica.propose(val) {
+                            Ok(id) => {
+                                self.replica.sender_mut().state_machine.pending.insert(id, completion);
+                            }
+                            Err(e) => {
+                                let _ = completion.send(Err(e));
+                            }
+                        }
+                    }
+                },
+                complete => break,
+            }
+
+            let messages = self.replica.sender_mut().messages.drain(..).collect::<Vec<_>>();
+            for message in messages {
+                self.outbound.feed(message).await?;
+            }
+            self.outbound.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Sender of the driven replica, buffering outbound messages until they are
+/// sent to the sink.
+struct Outbox<R> {
+    messages: Vec<(NodeId, Message)>,
+    state_machine: Tracked<R>,
+}
+
+impl<R: ReplicatedState> Outbox<R> {
+    fn new(state_machine: R) -> Outbox<R> {
+        Outbox {
+            messages: Vec::new(),
+            state_machine: Tracked { pending: HashMap::new(), inner: state_machine },
+        }
+    }
+}
+
+impl<R: ReplicatedState> Sender for Outbox<R> {
+    type Commander = Vec<Message>;
+    type StateMachine = Tracked<R>;
+
+    fn send_to<F>(&mut self, node: NodeId, command: F)
+    where
+        F: FnOnce(&mut Self::Commander),
+    {
+        let mut messages = Vec::new();
+        command(&mut messages);
+        self.messages.extend(messages.into_iter().map(|msg| (node, msg)));
+    }
+
+    fn state_machine(&mut self) -> &mut Self::StateMachine {
+        &mut self.state_machine
+    }
+}
+
+/// State machine that completes proposals submitted through a `Handle` as
+/// the replica reports them decided or abandoned.
+struct Tracked<R> {
+    pending: HashMap<ProposalId, Completion>,
+    inner: R,
+}
+
+impl<R: ReplicatedState> ReplicatedState for Tracked<R> {
+    fn execute(&mut self, slot: Slot, command: Bytes) {
+        self.inner.execute(slot, command)
+    }
+
+    fn proposal_decided(&mut self, id: ProposalId, slot: Slot) {
+        if let Some(completion) = self.pending.remove(&id) {
+            let _ = completion.send(Ok(slot));
+        }
+    }
+
+    fn proposal_abandoned(&mut self, id: ProposalId) {
+        if let Some(completion) = self.pending.remove(&id) {
+            let _ = completion.send(Err(ProposeError::TimedOut));
+        }
+    }
+
+    fn applied(&self) -> Option<Slot> {
+        self.inner.applied()
+    }
+
+    fn snapshot(&mut self, slot: Slot) -> Option<Bytes> {
+        self.inner.snapshot(slot)
+    }
+
+    fn restore(&mut self, slot: Slot, snapshot: Bytes) {
+        self.inner.restore(slot, snapshot)
+    }
+
+    fn read_ready(&mut self, id: u64) {
+        self.inner.read_ready(id)
+    }
+
+    fn read_aborted(&mut self, id: u64) {
+        self.inner.read_aborted(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::LocalPool, stream, task::LocalSpawnExt};
+
+    #[derive(Default)]
+    struct StateMachine(Vec<(Slot, Bytes)>);
+
+    impl ReplicatedState for StateMachine {
+        fn execute(&mut self, slot: Slot, command: Bytes) {
+            self.0.push((slot, command));
+        }
+    }
+
+    fn config(node: NodeId) -> Configuration {
+        let peers = (0..3u32)
+            .filter(|n| *n != node)
+            .map(|n| (n, format!("127.0.0.1:{}", 4000 + n).parse().unwrap()));
+        Configuration::new(node, peers)
+    }
+
+    #[test]
+    fn driver_propose() {
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+
+        // routes messages sent by each driver to the inbound stream of the peer
+        let (outbound, mut routed) = mpsc::unbounded::<(NodeId, Message)>();
+        let mut inbound = Vec::new();
+        let mut handles = Vec::new();
+        for node in 0..3 {
+            let (tx, rx) = mpsc::unbounded();
+            inbound.push(tx);
+            let (driver, handle) = Driver::new(
+                config(node),
+                StateMachine::default(),
+                rx,
+                stream::pending::<()>(),
+                outbound.clone().sink_map_err(|_| ()),
+            );
+            handles.push(handle);
+            spawner.spawn_local(async move { driver.run().await.unwrap() }).unwrap();
+        }
+        spawner
+            .spawn_local(async move {
+                while let Some((node, msg)) = routed.next().await {
+                    let _ = inbound[node as usize].unbounded_send(msg);
+                }
+            })
+            .unwrap();
+
+        let slots = pool.run_until(async {
+            let first = handles[0].propose("123".into()).await;
+            let second = handles[1].propose("456".into()).await;
+            (first, second)
+        });
+        assert_eq!((Ok(0), Ok(1)), slots);
+    }
+
+    #[test]
+    fn driver_stopped() {
+        let (driver, handle) = Driver::new(
+            config(0),
+            StateMachine::default(),
+            stream::empty(),
+            stream::pending::<()>(),
+            futures::sink::drain(),
+        );
+        let propose = handle.propose("123".into());
+
+        let mut pool = LocalPool::new();
+        pool.run_until(driver.run()).unwrap();
+        assert_eq!(Err(ProposeError::Stopped), pool.run_until(propose));
+    }
+
+    #[test]
+    fn driver_proposal_timeout() {
+        let (ticks, timer) = mpsc::unbounded();
+        let (inbound, rx) = mpsc::unbounded();
+        let config = config(0).with_proposal_timeout(2);
+        let (driver, handle) =
+            Driver::new(config, StateMachine::default(), rx, timer, futures::sink::drain());
+
+        let mut pool = LocalPool::new();
+        pool.spawner().spawn_local(async move { driver.run().await.unwrap() }).unwrap();
+
+        // peers never respond, so the proposal is not decided
+        let propose = handle.propose("123".into());
+        pool.run_until_stalled();
+        ticks.unbounded_send(()).unwrap();
+        ticks.unbounded_send(()).unwrap();
+        assert_eq!(Err(ProposeError::TimedOut), pool.run_until(propose));
+        drop(inbound);
+    }
+}
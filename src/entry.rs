@@ -0,0 +1,304 @@
+use crate::{NodeId, ProposalId, Reconfiguration, Slot};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{cmp, net::SocketAddr};
+
+/// Prefix reserved for entries of the log that are interpreted by the
+/// replica rather than the state machine.
+const MAGIC: &[u8] = b"\xffPAXOS\x00";
+
+/// Value of the state machine that begins with the reserved prefix
+const VALUE_ENTRY: u8 = 0;
+const RECONFIGURATION_ENTRY: u8 = 1;
+const SNAPSHOT_ENTRY: u8 = 2;
+const PROPOSAL_ENTRY: u8 = 3;
+const BATCH_ENTRY: u8 = 4;
+
+const ADD_NODE: u8 = 1;
+const REMOVE_NODE: u8 = 2;
+
+/// Entry of the replicated log.
+///
+/// Values of the state machine are stored in the log as-is, unless the value
+/// begins with the reserved prefix, in which case it is escaped. Control
+/// entries are framed with the reserved prefix followed by the type of the
+/// entry.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Entry {
+    /// Value applied to the state machine
+    Value(Bytes),
+    /// Change to the membership of the cluster
+    Reconfiguration(Reconfiguration),
+    /// Value applied to the state machine, tagged with the node that
+    /// proposed the value and the identifier of the proposal at that node
+    Proposal(NodeId, ProposalId, Bytes),
+    /// Values proposed within a single slot, each of which is an encoded
+    /// entry
+    Batch(Vec<Bytes>),
+}
+
+impl Entry {
+    /// Encodes the entry for the log
+    pub fn encode(&self) -> Bytes {
+        match self {
+            Entry::Value(val) if !val.starts_with(MAGIC) => val.clone(),
+            Entry::Value(val) => {
+                let mut buf = header(VALUE_ENTRY, val.len());
+                buf.put_slice(val);
+                buf.freeze()
+            }
+            Entry::Reconfiguration(change) => {
+                let mut buf = header(RECONFIGURATION_ENTRY, 32);
+                put_reconfiguration(&mut buf, change);
+                buf.freeze()
+            }
+            Entry::Proposal(node, id, val) => {
+                let mut buf = header(PROPOSAL_ENTRY, 12 + val.len());
+                buf.put_u32_le(*node);
+                buf.put_u64_le(*id);
+                buf.put_slice(val);
+                buf.freeze()
+            }
+            Entry::Batch(entries) => {
+                let len = entries.iter().map(|e| 4 + e.len()).sum::<usize>();
+                let mut buf = header(BATCH_ENTRY, 4 + len);
+                buf.put_u32_le(entries.len() as u32);
+                for entry in entries {
+                    buf.put_u32_le(entry.len() as u32);
+                    buf.put_slice(entry);
+                }
+                buf.freeze()
+            }
+        }
+    }
+
+    /// Decodes an entry from the log. Values that begin with the reserved
+    /// prefix but are not valid entries are treated as values.
+    pub fn decode(bytes: Bytes) -> Entry {
+        if !bytes.starts_with(MAGIC) || bytes.len() <= MAGIC.len() {
+            return Entry::Value(bytes);
+        }
+
+        let mut payload = bytes.slice(MAGIC.len() + 1..);
+        match bytes[MAGIC.len()] {
+            VALUE_ENTRY => Entry::Value(payload),
+            RECONFIGURATION_ENTRY => match get_reconfiguration(&mut payload) {
+                Some(change) if !payload.has_remaining() => Entry::Reconfiguration(change),
+                _ => Entry::Value(bytes),
+            },
+            PROPOSAL_ENTRY if payload.remaining() >= 12 => {
+                let node = payload.get_u32_le();
+                let id = payload.get_u64_le();
+                Entry::Proposal(node, id, payload)
+            }
+            BATCH_ENTRY => match get_batch(&mut payload) {
+                Some(entries) if !payload.has_remaining() => Entry::Batch(entries),
+                _ => Entry::Value(bytes),
+            },
+            _ => Entry::Value(bytes),
+        }
+    }
+}
+
+/// Flag indicating whether an encoded entry is a reconfiguration, which is
+/// proposed in a slot of its own rather than within a batch
+pub fn is_reconfiguration(bytes: &Bytes) -> bool {
+    bytes.starts_with(MAGIC) && bytes.get(MAGIC.len()) == Some(&RECONFIGURATION_ENTRY)
+}
+
+/// Encodes a snapshot of the state machine along with the reconfigurations
+/// that have been executed up to the snapshot, each with the slot at which it
+/// takes effect. Snapshots without reconfigurations are left as-is unless
+/// escaping is required.
+pub fn encode_snapshot(reconfigurations: &[(Slot, Reconfiguration)], snapshot: Bytes) -> Bytes {
+    if reconfigurations.is_empty() && !snapshot.starts_with(MAGIC) {
+        return snapshot;
+    }
+
+    let mut buf = header(SNAPSHOT_ENTRY, 4 + 32 * reconfigurations.len() + snapshot.len());
+    buf.put_u32_le(reconfigurations.len() as u32);
+    for (slot, change) in reconfigurations {
+        buf.put_u64_le(*slot);
+        put_reconfiguration(&mut buf, change);
+    }
+    buf.put_slice(&snapshot);
+    buf.freeze()
+}
+
+/// Decodes a snapshot created with `encode_snapshot`
+pub fn decode_snapshot(bytes: Bytes) -> (Vec<(Slot, Reconfiguration)>, Bytes) {
+    if !bytes.starts_with(MAGIC) || bytes.len() <= MAGIC.len() {
+        return (Vec::new(), bytes);
+    }
+    if bytes[MAGIC.len()] != SNAPSHOT_ENTRY {
+        return (Vec::new(), bytes);
+    }
+
+    let mut payload = bytes.slice(MAGIC.len() + 1..);
+    if payload.remaining() < 4 {
+        return (Vec::new(), bytes);
+    }
+    let len = payload.get_u32_le();
+    let mut reconfigurations = Vec::new();
+    for _ in 0..len {
+        if payload.remaining() < 8 {
+            return (Vec::new(), bytes);
+        }
+        let slot = payload.get_u64_le();
+        match get_reconfiguration(&mut payload) {
+            Some(change) => reconfigurations.push((slot, change)),
+            None => return (Vec::new(), bytes),
+        }
+    }
+    (reconfigurations, payload)
+}
+
+fn header(entry_type: u8, len: usize) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(MAGIC.len() + 1 + len);
+    buf.put_slice(MAGIC);
+    buf.put_u8(entry_type);
+    buf
+}
+
+fn get_batch(buf: &mut Bytes) -> Option<Vec<Bytes>> {
+    if buf.remaining() < 4 {
+        return None;
+    }
+    let len = buf.get_u32_le() as usize;
+    let mut entries = Vec::with_capacity(cmp::min(len, buf.remaining() / 4));
+    for _ in 0..len {
+        if buf.remaining() < 4 {
+            return None;
+        }
+        let entry_len = buf.get_u32_le() as usize;
+        if buf.remaining() < entry_len {
+            return None;
+        }
+        entries.push(buf.split_to(entry_len));
+    }
+    Some(entries)
+}
+
+fn put_reconfiguration(buf: &mut BytesMut, change: &Reconfiguration) {
+    match change {
+        Reconfiguration::AddNode(node, addr) => {
+            let addr = addr.to_string();
+            buf.put_u8(ADD_NODE);
+            buf.put_u32_le(*node);
+            buf.put_u16_le(addr.len() as u16);
+            buf.put_slice(addr.as_bytes());
+        }
+        Reconfiguration::RemoveNode(node) => {
+            buf.put_u8(REMOVE_NODE);
+            buf.put_u32_le(*node);
+        }
+    }
+}
+
+fn get_reconfiguration(buf: &mut Bytes) -> Option<Reconfiguration> {
+    if buf.remaining() < 5 {
+        return None;
+    }
+    match buf.get_u8() {
+        ADD_NODE if buf.remaining() >= 6 => {
+            let node = buf.get_u32_le();
+            let len = buf.get_u16_le() as usize;
+            if buf.remaining() < len {
+                return None;
+            }
+            let addr = std::str::from_utf8(&buf[..len]).ok()?.parse::<SocketAddr>().ok()?;
+            buf.advance(len);
+            Some(Reconfiguration::AddNode(node, addr))
+        }
+        REMOVE_NODE => Some(Reconfiguration::RemoveNode(buf.get_u32_le())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_value() {
+        let val = Bytes::from("123");
+        assert_eq!(val, Entry::Value(val.clone()).encode());
+        assert_eq!(Entry::Value(val.clone()), Entry::decode(val));
+
+        // values with the reserved prefix are escaped
+        let mut escaped = BytesMut::new();
+        escaped.put_slice(MAGIC);
+        escaped.put_slice(b"abc");
+        let escaped = escaped.freeze();
+        let encoded = Entry::Value(escaped.clone()).encode();
+        assert_ne!(escaped, encoded);
+        assert_eq!(Entry::Value(escaped.clone()), Entry::decode(encoded));
+
+        // invalid entries are treated as values
+        assert_eq!(Entry::Value(escaped.clone()), Entry::decode(escaped));
+        assert_eq!(Entry::Value(MAGIC.into()), Entry::decode(MAGIC.into()));
+    }
+
+    #[test]
+    fn entry_reconfiguration() {
+        let add =
+            Entry::Reconfiguration(Reconfiguration::AddNode(5, "127.0.0.1:4005".parse().unwrap()));
+        assert_eq!(add, Entry::decode(add.encode()));
+
+        let remove = Entry::Reconfiguration(Reconfiguration::RemoveNode(2));
+        assert_eq!(remove, Entry::decode(remove.encode()));
+        assert!(is_reconfiguration(&remove.encode()));
+        assert!(!is_reconfiguration(&Entry::Proposal(2, 7, "123".into()).encode()));
+
+        // truncated entries are treated as values
+        let truncated = add.encode().slice(..MAGIC.len() + 4);
+        assert_eq!(Entry::Value(truncated.clone()), Entry::decode(truncated));
+    }
+
+    #[test]
+    fn entry_proposal() {
+        let proposal = Entry::Proposal(2, 7, "123".into());
+        assert_eq!(proposal, Entry::decode(proposal.encode()));
+
+        let empty = Entry::Proposal(2, 8, Bytes::new());
+        assert_eq!(empty, Entry::decode(empty.encode()));
+
+        let truncated = proposal.encode().slice(..MAGIC.len() + 5);
+        assert_eq!(Entry::Value(truncated.clone()), Entry::decode(truncated));
+    }
+
+    #[test]
+    fn entry_batch() {
+        let entries = vec![
+            Entry::Value("123".into()).encode(),
+            Entry::Proposal(2, 7, "456".into()).encode(),
+            Bytes::new(),
+        ];
+        let batch = Entry::Batch(entries);
+        assert_eq!(batch, Entry::decode(batch.encode()));
+
+        // truncated batches are treated as values
+        let encoded = batch.encode();
+        let truncated = encoded.slice(..encoded.len() - 1);
+        assert_eq!(Entry::Value(truncated.clone()), Entry::decode(truncated));
+    }
+
+    #[test]
+    fn snapshot() {
+        let snapshot = Bytes::from("0,1,2");
+        assert_eq!(snapshot, encode_snapshot(&[], snapshot.clone()));
+        assert_eq!((Vec::new(), snapshot.clone()), decode_snapshot(snapshot.clone()));
+
+        let reconfigurations = vec![
+            (10, Reconfiguration::RemoveNode(2)),
+            (12, Reconfiguration::AddNode(5, "127.0.0.1:4005".parse().unwrap())),
+        ];
+        let encoded = encode_snapshot(&reconfigurations, snapshot.clone());
+        assert_eq!((reconfigurations, snapshot), decode_snapshot(encoded));
+
+        let mut escaped = BytesMut::new();
+        escaped.put_slice(MAGIC);
+        escaped.put_u8(SNAPSHOT_ENTRY);
+        let escaped = escaped.freeze();
+        assert_eq!((Vec::new(), escaped.clone()), decode_snapshot(encode_snapshot(&[], escaped)));
+    }
+}
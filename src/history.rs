@@ -0,0 +1,453 @@
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashSet},
+    hash::Hash,
+};
+
+/// Sequential specification of an object, used to check whether a history
+/// of concurrent operations on the object is linearizable.
+pub trait Model: Clone + Eq + Hash {
+    /// Operation invoked on the object
+    type Input;
+    /// Response to an operation
+    type Output: PartialEq;
+
+    /// Applies an operation to the object, returning the new state of the
+    /// object along with the response to the operation
+    fn step(&self, input: &Self::Input) -> (Self, Self::Output);
+}
+
+/// Register holding a single value, which is initially `None`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Register(pub Option<Bytes>);
+
+/// Operation on a `Register`. Reads respond with the value of the register
+/// and writes respond with `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RegisterOp {
+    Read,
+    Write(Bytes),
+}
+
+impl Model for Register {
+    type Input = RegisterOp;
+    type Output = Option<Bytes>;
+
+    fn step(&self, input: &RegisterOp) -> (Register, Option<Bytes>) {
+        match input {
+            RegisterOp::Read => (self.clone(), self.0.clone()),
+            RegisterOp::Write(val) => (Register(Some(val.clone())), None),
+        }
+    }
+}
+
+/// Map of keys to values, which is initially empty
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct KeyValue(pub BTreeMap<Bytes, Bytes>);
+
+/// Operation on a `KeyValue` map. Gets respond with the value of the key, if
+/// any, and puts respond with `None`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KvOp {
+    Get(Bytes),
+    Put(Bytes, Bytes),
+}
+
+impl Model for KeyValue {
+    type Input = KvOp;
+    type Output = Option<Bytes>;
+
+    fn step(&self, input: &KvOp) -> (KeyValue, Option<Bytes>) {
+        match input {
+            KvOp::Get(key) => (self.clone(), self.0.get(key).cloned()),
+            KvOp::Put(key, val) => {
+                let mut map = self.0.clone();
+                map.insert(key.clone(), val.clone());
+                (KeyValue(map), None)
+            }
+        }
+    }
+}
+
+/// Operation recorded in a `History`
+struct Operation<M: Model> {
+    input: M::Input,
+    /// Response to the operation, if the operation completed
+    output: Option<M::Output>,
+    /// Logical time of the invocation
+    invoked: u64,
+    /// Logical time of the response, if the operation completed
+    completed: Option<u64>,
+}
+
+/// History of operations invoked on an object by concurrent clients.
+///
+/// Invocations and responses are recorded in real-time order as clients
+/// observe them. An operation that never completes, such as a proposal that
+/// was abandoned or lost with a crashed node, may or may not have taken
+/// effect.
+///
+/// The history is linearizable if each operation appears to take effect
+/// atomically at some point between its invocation and response, which is
+/// checked against a sequential `Model` of the object.
+pub struct History<M: Model> {
+    operations: Vec<Operation<M>>,
+    /// Logical clock ordering invocations and responses
+    clock: u64,
+}
+
+impl<M: Model> Default for History<M> {
+    fn default() -> History<M> {
+        History::new()
+    }
+}
+
+impl<M: Model> History<M> {
+    /// Creates an empty history
+    pub fn new() -> History<M> {
+        History { operations: Vec::new(), clock: 0 }
+    }
+
+    /// Records the invocation of an operation, returning the index of the
+    /// operation within the history
+    pub fn invoke(&mut self, input: M::Input) -> usize {
+        self.clock += 1;
+        self.operations.push(Operation {
+            input,
+            output: None,
+            invoked: self.clock,
+            completed: None,
+        });
+        self.operations.len() - 1
+    }
+
+    /// Records the response to an operation
+    pub fn complete(&mut self, op: usize, output: M::Output) {
+        self.clock += 1;
+        let operation = &mut self.operations[op];
+        assert!(operation.completed.is_none(), "operation {} completed twice", op);
+        operation.output = Some(output);
+        operation.completed = Some(self.clock);
+    }
+
+    /// Number of operations in the history
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Flag indicating whether the history is empty
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Flag indicating whether the history is linearizable from the initial
+    /// state of the object
+    pub fn is_linearizable(&self, init: M) -> bool {
+        self.linearize(init).is_some()
+    }
+
+    /// Searches for a linearization of the history from the initial state of
+    /// the object, returning the indices of the operations in the order in
+    /// which they take effect. Operations that did not complete are only
+    /// included if they took effect.
+    ///
+    /// The search follows Wing & Gong, extending a linearized prefix with
+    /// operations that were invoked before every remaining operation
+    /// completed. Prefixes are memoized by the set of linearized operations
+    /// and the resulting state (Lowe), such that each is explored once.
+    pub fn linearize(&self, init: M) -> Option<Vec<usize>> {
+        let remaining = self.operations.iter().filter(|op| op.completed.is_some()).count();
+        let mut search = Search {
+            operations: &self.operations,
+            linearized: vec![0; self.operations.len() / 64 + 1],
+            order: Vec::new(),
+            visited: HashSet::new(),
+        };
+        if search.extend(&init, remaining) { Some(search.order) } else { None }
+    }
+}
+
+/// State of the search for a linearization
+struct Search<'a, M: Model> {
+    operations: &'a [Operation<M>],
+    /// Set of linearized operations
+    linearized: Vec<u64>,
+    /// Linearized operations in the order in which they take effect
+    order: Vec<usize>,
+    /// Linearized sets and states from which no linearization was found
+    visited: HashSet<(Vec<u64>, M)>,
+}
+
+impl<'a, M: Model> Search<'a, M> {
+    /// Extends the linearization until the remaining completed operations are
+    /// linearized, returning false if no linearization exists
+    fn extend(&mut self, state: &M, remaining: usize) -> bool {
+        if remaining == 0 {
+            return true;
+        }
+        if !self.visited.insert((self.linearized.clone(), state.clone())) {
+            return false;
+        }
+
+        // an operation cannot take effect after an operation that completed
+        // before it was invoked
+        let deadline = self
+            .operations
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.is_linearized(*i))
+            .filter_map(|(_, op)| op.completed)
+            .min()
+            .unwrap_or(u64::MAX);
+
+        for i in 0..self.operations.len() {
+            let op = &self.operations[i];
+            if op.invoked > deadline {
+                // operations are ordered by invocation
+                break;
+            }
+            if self.is_linearized(i) {
+                continue;
+            }
+
+            let (next, output) = state.step(&op.input);
+            if op.output.as_ref().map(|o| *o == output).unwrap_or(true) {
+                let completed = op.completed.is_some() as usize;
+                self.set_linearized(i, true);
+                self.order.push(i);
+                if self.extend(&next, remaining - completed) {
+                    return true;
+                }
+                self.order.pop();
+                self.set_linearized(i, false);
+            }
+        }
+        false
+    }
+
+    fn is_linearized(&self, i: usize) -> bool {
+        self.linearized[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set_linearized(&mut self, i: usize, linearized: bool) {
+        if linearized {
+            self.linearized[i / 64] |= 1 << (i % 64);
+        } else {
+            self.linearized[i / 64] &= !(1 << (i % 64));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{timer::XorShift, NodeId, ProposalId, ReplicatedState, Simulator, Slot};
+    use std::collections::HashMap;
+
+    #[test]
+    fn register_sequential() {
+        let mut history = History::new();
+        let write = history.invoke(RegisterOp::Write("1".into()));
+        history.complete(write, None);
+        let read = history.invoke(RegisterOp::Read);
+        history.complete(read, Some("1".into()));
+        assert_eq!(Some(vec![0, 1]), history.linearize(Register::default()));
+
+        // reads after a completed write observe the write
+        let read = history.invoke(RegisterOp::Read);
+        history.complete(read, None);
+        assert!(!history.is_linearizable(Register::default()));
+    }
+
+    #[test]
+    fn register_concurrent() {
+        let mut history = History::new();
+        let write1 = history.invoke(RegisterOp::Write("1".into()));
+        let write2 = history.invoke(RegisterOp::Write("2".into()));
+        let read1 = history.invoke(RegisterOp::Read);
+        history.complete(read1, Some("2".into()));
+        history.complete(write1, None);
+        history.complete(write2, None);
+
+        // concurrent writes take effect in either order
+        let read2 = history.invoke(RegisterOp::Read);
+        history.complete(read2, Some("1".into()));
+        assert_eq!(Some(vec![1, 2, 0, 3]), history.linearize(Register::default()));
+
+        // yet the order is the same for all reads
+        let read3 = history.invoke(RegisterOp::Read);
+        history.complete(read3, Some("2".into()));
+        assert!(!history.is_linearizable(Register::default()));
+    }
+
+    #[test]
+    fn register_pending() {
+        // operations that do not complete may take effect
+        let mut history = History::new();
+        history.invoke(RegisterOp::Write("1".into()));
+        let read = history.invoke(RegisterOp::Read);
+        history.complete(read, Some("1".into()));
+        assert!(history.is_linearizable(Register::default()));
+
+        // or not take effect
+        let mut history = History::new();
+        history.invoke(RegisterOp::Write("1".into()));
+        let read = history.invoke(RegisterOp::Read);
+        history.complete(read, None);
+        assert_eq!(Some(vec![1]), history.linearize(Register::default()));
+
+        // yet not before they are invoked
+        let mut history = History::new();
+        let read = history.invoke(RegisterOp::Read);
+        history.complete(read, Some("1".into()));
+        history.invoke(RegisterOp::Write("1".into()));
+        assert!(!history.is_linearizable(Register::default()));
+    }
+
+    #[test]
+    fn key_value() {
+        let mut history = History::new();
+        let put = history.invoke(KvOp::Put("a".into(), "1".into()));
+        let get = history.invoke(KvOp::Get("b".into()));
+        history.complete(put, None);
+        history.complete(get, None);
+        let get = history.invoke(KvOp::Get("a".into()));
+        history.complete(get, Some("1".into()));
+        assert!(history.is_linearizable(KeyValue::default()));
+
+        // stale reads are not linearizable
+        let put = history.invoke(KvOp::Put("a".into(), "2".into()));
+        history.complete(put, None);
+        let get = history.invoke(KvOp::Get("a".into()));
+        history.complete(get, Some("1".into()));
+        assert!(!history.is_linearizable(KeyValue::default()));
+    }
+
+    /// Key-value store replicated by the simulated cluster. Commands carry
+    /// the index of the operation in the history, which identifies the
+    /// operation when the command is decided more than once, such as when
+    /// the network duplicates a forwarded proposal.
+    #[derive(Default)]
+    struct Store {
+        data: BTreeMap<Bytes, Bytes>,
+        /// Response to each executed operation
+        outputs: HashMap<usize, Option<Bytes>>,
+    }
+
+    fn encode(op: usize, input: &KvOp) -> Bytes {
+        let command = match input {
+            KvOp::Get(key) => format!("{} get {}", op, std::str::from_utf8(key).unwrap()),
+            KvOp::Put(key, val) => format!(
+                "{} put {} {}",
+                op,
+                std::str::from_utf8(key).unwrap(),
+                std::str::from_utf8(val).unwrap()
+            ),
+        };
+        command.into()
+    }
+
+    impl ReplicatedState for Store {
+        fn execute(&mut self, _slot: Slot, command: Bytes) {
+            let command = std::str::from_utf8(&command).unwrap().to_string();
+            let parts = command.split(' ').collect::<Vec<&str>>();
+            let op = parts[0].parse::<usize>().unwrap();
+            if self.outputs.contains_key(&op) {
+                return;
+            }
+
+            let output = match parts[1] {
+                "get" => self.data.get(parts[2].as_bytes()).cloned(),
+                _ => {
+                    self.data.insert(parts[2].to_string().into(), parts[3].to_string().into());
+                    None
+                }
+            };
+            self.outputs.insert(op, output);
+        }
+
+        fn proposal_decided(&mut self, _id: ProposalId, _slot: Slot) {}
+    }
+
+    /// Runs clients against the simulated cluster while injecting faults,
+    /// recording the history of operations
+    fn simulated_history(seed: u64) -> History<KeyValue> {
+        let mut sim = Simulator::new(seed, 5, |_| Store::default())
+            .with_configuration(|config| config.with_read_lease(8, 2));
+        sim.set_delay(0, 4);
+        sim.set_drop_rate(0.05);
+        sim.set_duplicate_rate(0.05);
+
+        let mut rng = XorShift::from_seed(seed);
+        let mut history = History::new();
+        // operation awaited by each client, along with the node and the tick
+        // at which the operation was submitted
+        let mut clients: Vec<Option<(usize, NodeId, u64)>> = vec![None; 4];
+
+        for round in 0..400u64 {
+            match round {
+                100 => sim.partition(&[0, 1]),
+                150 => sim.crash(3),
+                200 => sim.heal(),
+                250 => sim.restart(3),
+                300 => sim.set_drop_rate(0.0),
+                _ => {}
+            }
+
+            for client in clients.iter_mut().filter(|client| client.is_none()) {
+                let node = (rng.next() % 5) as NodeId;
+                let key = Bytes::from(format!("{}", rng.next() % 3));
+                let input = if rng.next() & 1 == 0 {
+                    KvOp::Get(key)
+                } else {
+                    KvOp::Put(key, format!("{}", history.len()).into())
+                };
+
+                // reads are served locally while the leader holds the lease
+                if let KvOp::Get(ref key) = input {
+                    let read =
+                        sim.replica_mut(node).and_then(|r| r.read(|s| s.data.get(key).cloned()));
+                    if let Some(output) = read {
+                        let op = history.invoke(input);
+                        history.complete(op, output);
+                        continue;
+                    }
+                }
+
+                let command = encode(history.len(), &input);
+                let op = history.invoke(input);
+                if sim.propose(node, command).is_ok() {
+                    *client = Some((op, node, sim.now()));
+                }
+            }
+
+            sim.step();
+
+            for client in clients.iter_mut() {
+                let (op, node, submitted) = match *client {
+                    Some(awaited) => awaited,
+                    None => continue,
+                };
+                match sim.state_machine(node) {
+                    Some(store) if store.outputs.contains_key(&op) => {
+                        history.complete(op, store.outputs[&op].clone());
+                        *client = None;
+                    }
+                    // operations submitted to crashed nodes, or that are not
+                    // decided in time, remain pending
+                    Some(_) if sim.now() - submitted < 50 => {}
+                    _ => *client = None,
+                }
+            }
+        }
+        history
+    }
+
+    #[test]
+    fn replica_linearizable() {
+        for seed in 0..5 {
+            let history = simulated_history(seed);
+            assert!(history.is_linearizable(KeyValue::default()), "seed {}", seed);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{Ballot, NodeId, QuorumSystem, Slot};
+use std::collections::{BTreeMap, HashMap};
+
+/// Time-based read lease held by the distinguished proposer.
+///
+/// Acceptors grant a lease to a ballot each time they send a PROMISE,
+/// ACCEPTED or HEARTBEAT_ACK for the ballot, and will not promise any other
+/// ballot until `duration` ticks have passed on their own clock. Once a Phase 2
+/// quorum of acceptors (which intersects every Phase 1 quorum) has granted the
+/// lease, no other proposer can become the leader until the lease expires, so
+/// the leader may serve reads from its state machine.
+///
+/// The leader measures the lease from the tick at which it sent the message
+/// rather than when the response arrives, and subtracts the bound on clock
+/// drift between nodes from the duration.
+#[derive(Debug)]
+pub struct ReadLease {
+    /// Number of ticks for which a lease is granted
+    duration: u64,
+    /// Bound on the drift between clocks of nodes over a lease, in ticks
+    drift: u64,
+    /// Ballot granted a lease by the current node, along with the tick at
+    /// which the grant expires
+    granted: Option<(Ballot, u64)>,
+    /// Tick until which no ballot is promised after a restart
+    restored: u64,
+
+    /// Ballot of the current node for which grants are tracked
+    ballot: Option<Ballot>,
+    /// Tick at which PREPARE messages were sent for the ballot
+    prepared: u64,
+    /// Tick at which ACCEPT messages were first sent for unresolved slots
+    accepts: BTreeMap<Slot, u64>,
+    /// Tick at which recent HEARTBEAT messages were sent, by sequence number
+    heartbeats: BTreeMap<u64, u64>,
+    /// Tick at which the latest message granted by each peer was sent
+    grants: HashMap<NodeId, u64>,
+    /// Slot that must be executed before reads are served, which covers the
+    /// values that may have been decided in prior ballots
+    ready: Slot,
+}
+
+impl ReadLease {
+    /// Creates a lease of a given duration and drift bound, in ticks
+    pub fn new(duration: u64, drift: u64) -> ReadLease {
+        assert!(duration > drift);
+        ReadLease {
+            duration,
+            drift,
+            granted: None,
+            restored: 0,
+            ballot: None,
+            prepared: 0,
+            accepts: BTreeMap::new(),
+            heartbeats: BTreeMap::new(),
+            grants: HashMap::new(),
+            ready: 0,
+        }
+    }
+
+    /// Grants the lease to a ballot as an acceptor
+    pub fn grant(&mut self, bal: Ballot, now: u64) {
+        self.granted = Some((bal, now + self.duration));
+    }
+
+    /// Holds the lease for any ballot after a restart. Grants are not
+    /// persisted, so no ballot is promised for the duration plus the drift
+    /// bound from `now`.
+    pub fn restore(&mut self, now: u64) {
+        self.restored = now + self.duration + self.drift;
+    }
+
+    /// Flag indicating whether the lease has been granted to a node other
+    /// than `node` and has not expired. Ballots of `node` must not be promised
+    /// while the lease is held by another node.
+    pub fn is_held_by_other(&self, node: NodeId, now: u64) -> bool {
+        if now < self.restored {
+            return true;
+        }
+        match self.granted {
+            Some((Ballot(_, holder), expires)) => holder != node && now < expires,
+            None => false,
+        }
+    }
+
+    /// Starts tracking grants for a new ballot of the current node once
+    /// PREPARE messages have been sent
+    pub fn prepare(&mut self, bal: Ballot, now: u64) {
+        self.ballot = Some(bal);
+        self.prepared = now;
+        self.accepts.clear();
+        self.heartbeats.clear();
+        self.grants.clear();
+        self.ready = Slot::MAX;
+    }
+
+    /// Notes the slot through which the state machine must be executed before
+    /// reads are served
+    pub fn ready_at(&mut self, slot: Slot) {
+        self.ready = slot;
+    }
+
+    /// Notes a PROMISE from a peer
+    pub fn promised(&mut self, node: NodeId, bal: Ballot) {
+        if self.ballot == Some(bal) {
+            self.insert_grant(node, self.prepared);
+        }
+    }
+
+    /// Notes the tick at which ACCEPT messages were sent for a slot
+    pub fn accept_sent(&mut self, slot: Slot, now: u64) {
+        self.accepts.entry(slot).or_insert(now);
+    }
+
+    /// Notes an ACCEPTED from a peer
+    pub fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
+        if self.ballot != Some(bal) {
+            return;
+        }
+        if let Some(sent) = self.accepts.get(&slot).cloned() {
+            self.insert_grant(node, sent);
+        }
+    }
+
+    /// Notes the tick at which a HEARTBEAT was sent, forgetting heartbeats
+    /// that can no longer extend the lease
+    pub fn heartbeat_sent(&mut self, seq: u64, now: u64) {
+        let duration = self.duration;
+        self.heartbeats.retain(|_, sent| *sent + duration > now);
+        self.heartbeats.insert(seq, now);
+    }
+
+    /// Notes a HEARTBEAT_ACK from a peer
+    pub fn heartbeat_acked(&mut self, node: NodeId, bal: Ballot, seq: u64) {
+        if self.ballot != Some(bal) {
+            return;
+        }
+        if let Some(sent) = self.heartbeats.get(&seq).cloned() {
+            self.insert_grant(node, sent);
+        }
+    }
+
+    /// Stops tracking a slot once it has been resolved
+    pub fn resolved(&mut self, slot: Slot) {
+        self.accepts.remove(&slot);
+    }
+
+    /// Tick at which the lease of the current node expires, if the lease has
+    /// been granted by a Phase 2 quorum
+    pub fn expires(&self, current: NodeId, now: u64, quorum: &dyn QuorumSystem) -> Option<u64> {
+        let mut grants = self.grants.iter().map(|(n, t)| (*t, *n)).collect::<Vec<_>>();
+        grants.sort_by(|a, b| b.cmp(a));
+
+        let mut nodes = vec![current];
+        if quorum.is_phase2_quorum(&nodes) {
+            return Some(now + self.duration - self.drift);
+        }
+        for (sent, node) in grants {
+            if let Err(loc) = nodes.binary_search(&node) {
+                nodes.insert(loc, node);
+            }
+            if quorum.is_phase2_quorum(&nodes) {
+                return Some(sent + self.duration - self.drift);
+            }
+        }
+        None
+    }
+
+    /// Flag indicating whether reads may be served at the current tick, given
+    /// the slots executed by the state machine
+    pub fn is_valid(
+        &self,
+        current: NodeId,
+        now: u64,
+        executed: Option<Slot>,
+        quorum: &dyn QuorumSystem,
+    ) -> bool {
+        let executed = executed.map(|s| s + 1).unwrap_or(0);
+        executed >= self.ready
+            && self.expires(current, now, quorum).map(|expires| now < expires).unwrap_or(false)
+    }
+
+    fn insert_grant(&mut self, node: NodeId, sent: u64) {
+        let grant = self.grants.entry(node).or_insert(sent);
+        *grant = (*grant).max(sent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MajorityQuorum;
+
+    #[test]
+    fn lease_grant() {
+        let mut lease = ReadLease::new(10, 2);
+        assert!(!lease.is_held_by_other(0, 0));
+
+        lease.grant(Ballot(1, 0), 5);
+        assert!(!lease.is_held_by_other(0, 6));
+        assert!(lease.is_held_by_other(1, 14));
+        assert!(!lease.is_held_by_other(1, 15));
+    }
+
+    #[test]
+    fn lease_expires() {
+        let quorum = MajorityQuorum::new(5);
+        let mut lease = ReadLease::new(10, 2);
+        lease.prepare(Ballot(1, 0), 3);
+        lease.ready_at(0);
+        lease.promised(1, Ballot(1, 0));
+        assert_eq!(None, lease.expires(0, 4, &quorum));
+
+        // promises for other ballots are ignored
+        lease.promised(2, Ballot(0, 2));
+        assert_eq!(None, lease.expires(0, 4, &quorum));
+
+        lease.promised(2, Ballot(1, 0));
+        assert_eq!(Some(11), lease.expires(0, 4, &quorum));
+        assert!(lease.is_valid(0, 10, None, &quorum));
+        assert!(!lease.is_valid(0, 11, None, &quorum));
+
+        // renewed from the tick the ACCEPT was sent
+        lease.accept_sent(0, 8);
+        lease.accept_sent(0, 9);
+        lease.accepted(1, 0, Ballot(1, 0));
+        assert_eq!(Some(11), lease.expires(0, 12, &quorum));
+        lease.accepted(3, 0, Ballot(1, 0));
+        assert_eq!(Some(16), lease.expires(0, 12, &quorum));
+
+        // resolved slots no longer renew the lease
+        lease.resolved(0);
+        lease.accepted(2, 0, Ballot(1, 0));
+        assert_eq!(Some(16), lease.expires(0, 12, &quorum));
+
+        // renewed from the tick the HEARTBEAT was sent
+        lease.heartbeat_sent(1, 12);
+        lease.heartbeat_sent(2, 14);
+        lease.heartbeat_acked(1, Ballot(1, 0), 2);
+        lease.heartbeat_acked(2, Ballot(0, 2), 2);
+        assert_eq!(Some(16), lease.expires(0, 15, &quorum));
+        lease.heartbeat_acked(3, Ballot(1, 0), 1);
+        assert_eq!(Some(20), lease.expires(0, 15, &quorum));
+
+        // heartbeats that can no longer extend the lease are forgotten
+        lease.heartbeat_sent(3, 22);
+        lease.heartbeat_acked(2, Ballot(1, 0), 1);
+        assert_eq!(Some(20), lease.expires(0, 22, &quorum));
+    }
+
+    #[test]
+    fn lease_restore() {
+        let mut lease = ReadLease::new(10, 2);
+        lease.restore(0);
+        assert!(lease.is_held_by_other(0, 11));
+        assert!(lease.is_held_by_other(1, 11));
+        assert!(!lease.is_held_by_other(1, 12));
+    }
+
+    #[test]
+    fn lease_ready() {
+        let quorum = MajorityQuorum::new(3);
+        let mut lease = ReadLease::new(10, 2);
+        lease.prepare(Ballot(1, 0), 0);
+        lease.promised(1, Ballot(1, 0));
+        assert!(!lease.is_valid(0, 1, Some(5), &quorum));
+
+        lease.ready_at(3);
+        assert!(!lease.is_valid(0, 1, Some(1), &quorum));
+        assert!(lease.is_valid(0, 1, Some(2), &quorum));
+    }
+}
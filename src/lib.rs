@@ -30,19 +30,51 @@ extern crate lazy_static;
 extern crate test;
 
 mod acceptor;
+mod batch;
+mod codec;
 mod commands;
 mod config;
+#[cfg(feature = "futures")]
+mod driver;
+mod entry;
+#[cfg(any(test, feature = "simulator"))]
+mod history;
+mod lease;
+mod message;
 mod proposer;
+mod queue;
+mod quorum;
 mod replica;
+#[cfg(any(test, feature = "simulator"))]
+mod simulator;
 mod statemachine;
+mod storage;
+mod timer;
+mod wal;
 mod window;
 
 use std::cmp;
 
+pub use batch::BatchSender;
+pub use codec::DecodeError;
 pub use commands::{Commander, Sender};
-pub use config::{Configuration, PeerIntoIter, PeerIter};
+pub use config::{
+    BatchLimits, ConfigError, Configuration, LeaseConfig, PeerIntoIter, PeerIter, QueueLimits,
+    Reconfiguration,
+};
+#[cfg(feature = "futures")]
+pub use driver::{Driver, Handle};
+#[cfg(any(test, feature = "simulator"))]
+pub use history::{History, KeyValue, KvOp, Model, Register, RegisterOp};
+pub use message::Message;
+pub use quorum::{FlexibleQuorum, GridQuorum, MajorityQuorum, QuorumSystem, WeightedQuorum};
+pub use queue::ProposeError;
 pub use replica::Replica;
+#[cfg(any(test, feature = "simulator"))]
+pub use simulator::{SimReplica, SimSender, SimStorage, Simulator};
 pub use statemachine::ReplicatedState;
+pub use storage::{MemoryStorage, PersistedState, Storage};
+pub use wal::{SyncPolicy, Wal};
 
 /// Increasing sequence number of Paxos instances.
 pub type Slot = u64;
@@ -51,10 +83,16 @@ pub type Slot = u64;
 /// within the configuration.
 pub type NodeId = u32;
 
+/// Identifier of a proposal submitted with `Replica::propose`, unique to
+/// the proposing replica. The upper 32 bits hold the incarnation of a
+/// recovered replica (see `Replica::recover`).
+pub type ProposalId = u64;
+
 /// Ballot numbering is an increasing number in order to order proposals
 /// across multiple nodes. Ballots are unique in that ballot numbers between
 /// nodes are unique and it is algorithmically increasing per node.
 #[derive(PartialEq, Hash, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ballot(pub u32, pub NodeId);
 
 /// Tuple containing the the slot number, ballot and value
@@ -0,0 +1,174 @@
+use crate::{Ballot, Commander, NodeId, Slot, SlottedValue};
+use bytes::Bytes;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Message of the protocol, mirroring each method of `Commander`.
+///
+/// Messages allow the commands sent by a replica to be queued or sent over
+/// the network, then delivered to the receiving replica with `dispatch`. With
+/// the `serde` feature enabled, messages can be serialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Message {
+    /// See `Commander::proposal`
+    Proposal(Bytes),
+    /// See `Commander::forward`
+    Forward(Bytes),
+    /// See `Commander::prepare`
+    Prepare(Ballot),
+    /// See `Commander::promise`
+    Promise(NodeId, Ballot, Slot, Vec<SlottedValue>),
+    /// See `Commander::accept`
+    Accept(Slot, Ballot, Bytes),
+    /// See `Commander::reject`
+    Reject(NodeId, Ballot, Ballot),
+    /// See `Commander::accepted`
+    Accepted(NodeId, Slot, Ballot),
+    /// See `Commander::resolution`
+    Resolution(Slot, Ballot, Bytes),
+    /// See `Commander::snapshot_request`
+    SnapshotRequest(NodeId, Slot),
+    /// See `Commander::install_snapshot`
+    InstallSnapshot(Slot, Bytes),
+    /// See `Commander::catchup`
+    Catchup(NodeId, Slot, Slot),
+    /// See `Commander::heartbeat`
+    Heartbeat(Ballot, u64),
+    /// See `Commander::heartbeat_ack`
+    HeartbeatAck(NodeId, Ballot, u64),
+    /// See `Commander::batch`
+    Batch(Vec<Message>),
+}
+
+impl Message {
+    /// Delivers the message to a commander, such as a `Replica`. Batches
+    /// nested within a batch are not delivered, and the outer batch is
+    /// dropped.
+    pub fn dispatch<C: Commander>(self, commander: &mut C) {
+        match self {
+            Message::Proposal(val) => commander.proposal(val),
+            Message::Forward(entry) => commander.forward(entry),
+            Message::Prepare(bal) => commander.prepare(bal),
+            Message::Promise(node, bal, open, accepted) => {
+                commander.promise(node, bal, open, accepted)
+            }
+            Message::Accept(slot, bal, val) => commander.accept(slot, bal, val),
+            Message::Reject(node, proposed, preempted) => {
+                commander.reject(node, proposed, preempted)
+            }
+            Message::Accepted(node, slot, bal) => commander.accepted(node, slot, bal),
+            Message::Resolution(slot, bal, val) => commander.resolution(slot, bal, val),
+            Message::SnapshotRequest(node, slot) => commander.snapshot_request(node, slot),
+            Message::InstallSnapshot(slot, snapshot) => commander.install_snapshot(slot, snapshot),
+            Message::Catchup(node, start, end) => commander.catchup(node, start, end),
+            Message::Heartbeat(bal, seq) => commander.heartbeat(bal, seq),
+            Message::HeartbeatAck(node, bal, seq) => commander.heartbeat_ack(node, bal, seq),
+            Message::Batch(messages) => {
+                if messages.iter().any(|m| matches!(m, Message::Batch(_))) {
+                    warn!("Dropping batch nested within a batch");
+                    return;
+                }
+                commander.batch(messages)
+            }
+        }
+    }
+}
+
+/// Collects the commands as messages
+impl Commander for Vec<Message> {
+    fn proposal(&mut self, val: Bytes) {
+        self.push(Message::Proposal(val));
+    }
+
+    fn forward(&mut self, entry: Bytes) {
+        self.push(Message::Forward(entry));
+    }
+
+    fn prepare(&mut self, bal: Ballot) {
+        self.push(Message::Prepare(bal));
+    }
+
+    fn promise(&mut self, node: NodeId, bal: Ballot, open: Slot, accepted: Vec<SlottedValue>) {
+        self.push(Message::Promise(node, bal, open, accepted));
+    }
+
+    fn accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.push(Message::Accept(slot, bal, val));
+    }
+
+    fn reject(&mut self, node: NodeId, proposed: Ballot, preempted: Ballot) {
+        self.push(Message::Reject(node, proposed, preempted));
+    }
+
+    fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
+        self.push(Message::Accepted(node, slot, bal));
+    }
+
+    fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.push(Message::Resolution(slot, bal, val));
+    }
+
+    fn snapshot_request(&mut self, node: NodeId, slot: Slot) {
+        self.push(Message::SnapshotRequest(node, slot));
+    }
+
+    fn install_snapshot(&mut self, slot: Slot, snapshot: Bytes) {
+        self.push(Message::InstallSnapshot(slot, snapshot));
+    }
+
+    fn catchup(&mut self, node: NodeId, start: Slot, end: Slot) {
+        self.push(Message::Catchup(node, start, end));
+    }
+
+    fn heartbeat(&mut self, bal: Ballot, seq: u64) {
+        self.push(Message::Heartbeat(bal, seq));
+    }
+
+    fn heartbeat_ack(&mut self, node: NodeId, bal: Ballot, seq: u64) {
+        self.push(Message::HeartbeatAck(node, bal, seq));
+    }
+
+    fn batch(&mut self, messages: Vec<Message>) {
+        self.push(Message::Batch(messages));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message::Proposal("123".into()),
+            Message::Forward("123".into()),
+            Message::Prepare(Ballot(1, 2)),
+            Message::Promise(2, Ballot(1, 2), 4, vec![(4, Ballot(0, 1), "4".into())]),
+            Message::Accept(4, Ballot(1, 2), "4".into()),
+            Message::Reject(3, Ballot(1, 2), Ballot(2, 0)),
+            Message::Accepted(3, 4, Ballot(1, 2)),
+            Message::Resolution(4, Ballot(1, 2), "4".into()),
+            Message::SnapshotRequest(1, 5),
+            Message::InstallSnapshot(5, "snapshot".into()),
+            Message::Catchup(1, 2, 6),
+            Message::Heartbeat(Ballot(1, 2), 7),
+            Message::HeartbeatAck(0, Ballot(1, 2), 7),
+            Message::Batch(vec![Message::Prepare(Ballot(1, 2)), Message::Catchup(1, 2, 6)]),
+        ]
+    }
+
+    #[test]
+    fn dispatch() {
+        let mut commander = Vec::new();
+        for message in messages() {
+            message.dispatch(&mut commander);
+        }
+        assert_eq!(messages(), commander);
+
+        // batches nested within a batch are dropped
+        let mut commander = Vec::new();
+        let nested = Message::Batch(vec![Message::Prepare(Ballot(1, 2))]);
+        Message::Batch(vec![Message::Prepare(Ballot(1, 2)), nested]).dispatch(&mut commander);
+        assert!(commander.is_empty());
+    }
+}
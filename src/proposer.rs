@@ -1,5 +1,8 @@
-use crate::{config::QuorumSet, Ballot, NodeId};
-use std::cmp::max;
+use crate::{
+    config::{Phase, QuorumSet},
+    Ballot, NodeId, QuorumSystem,
+};
+use std::{cmp::max, sync::Arc};
 
 /// The proposer is a role within paxos that acts as a coordinator for the
 /// instance in that it attempts to elect itself the proposer (leader) for the
@@ -14,14 +17,14 @@ pub struct Proposer {
     highest: Option<Ballot>,
     /// Node ID of the current node (used to construct ballots)
     current: NodeId,
-    /// Number of nodes for quorum
-    quorum: usize,
+    /// Quorum system used to detect Phase 1 quorum
+    quorum: Arc<dyn QuorumSystem>,
 }
 
 impl Proposer {
     /// Creates new proposer state with the node identifier and the Phase 1
-    /// quorum size
-    pub fn new(node: NodeId, quorum: usize) -> Proposer {
+    /// quorum system
+    pub fn new(node: NodeId, quorum: Arc<dyn QuorumSystem>) -> Proposer {
         Proposer { state: ProposerState::Follower, highest: None, current: node, quorum }
     }
 
@@ -52,6 +55,29 @@ impl Proposer {
         }
     }
 
+    /// Replaces the quorum system used to detect Phase 1 quorum
+    pub fn reconfigure(&mut self, quorum: Arc<dyn QuorumSystem>) {
+        if let ProposerState::Candidate { ref mut promises, .. } = self.state {
+            *promises = promises.reconfigure(quorum.clone());
+        }
+        self.quorum = quorum;
+    }
+
+    /// Gives up candidacy or leadership, e.g. once the node has been removed
+    /// from the configuration.
+    pub fn step_down(&mut self) {
+        self.state = ProposerState::Follower;
+    }
+
+    /// Ballot and the set of nodes that have promised the ballot while the
+    /// proposer is a candidate.
+    pub fn promises(&self) -> Option<(Ballot, &QuorumSet)> {
+        match self.state {
+            ProposerState::Candidate { proposal, ref promises } => Some((proposal, promises)),
+            _ => None,
+        }
+    }
+
     /// Highest ballot that the proposer has seen
     pub fn highest_observed_ballot(&self) -> Option<Ballot> {
         self.highest
@@ -67,13 +93,18 @@ impl Proposer {
         self.highest = Some(new_ballot);
 
         // this current node accepts itself as proposer
-        let mut promises = QuorumSet::with_size(self.quorum);
+        let mut promises = QuorumSet::new(self.quorum.clone(), Phase::One);
         promises.insert(self.current);
 
-        self.state = ProposerState::Candidate { proposal: new_ballot, promises };
-
         debug!("Starting prepare with {:?}", new_ballot);
 
+        self.state = if promises.has_quorum() {
+            debug!("Quorum reached for Phase 1 of {:?}", new_ballot);
+            ProposerState::Leader { proposal: new_ballot }
+        } else {
+            ProposerState::Candidate { proposal: new_ballot, promises }
+        };
+
         new_ballot
     }
 
@@ -161,10 +192,11 @@ pub enum ProposerStatus {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::FlexibleQuorum;
 
     #[test]
     fn proposer_prepare() {
-        let mut proposer = Proposer::new(1, 2);
+        let mut proposer = Proposer::new(1, Arc::new(FlexibleQuorum::new(2, 2)));
         assert!(proposer.status() != ProposerStatus::Leader);
         proposer.observe_ballot(Ballot(100, 1));
 
@@ -179,16 +211,22 @@ mod tests {
         });
 
         assert!(proposer.status() != ProposerStatus::Leader);
+
+        // the proposer's own promise may form a quorum
+        let mut proposer = Proposer::new(1, Arc::new(FlexibleQuorum::new(1, 1)));
+        assert_eq!(Ballot(0, 1), proposer.prepare());
+        assert!(proposer.status() == ProposerStatus::Leader);
     }
 
     #[test]
     fn proposer_receive_promise() {
-        let mut proposer = Proposer::new(1, 2);
+        let mut proposer = Proposer::new(1, Arc::new(FlexibleQuorum::new(2, 2)));
         proposer.observe_ballot(Ballot(100, 1));
 
         proposer.prepare();
         assert!(proposer.status() != ProposerStatus::Leader);
         assert_eq!(Some(Ballot(101, 1)), proposer.highest_observed_ballot());
+        assert!(proposer.promises().map(|(b, p)| b == Ballot(101, 1) && p.contains(1)).unwrap());
 
         assert!(match proposer.state {
             ProposerState::Candidate { proposal: Ballot(101, 1), ref promises, .. }
@@ -199,6 +237,7 @@ mod tests {
 
         proposer.receive_promise(2, Ballot(101, 1));
         assert!(proposer.status() == ProposerStatus::Leader);
+        assert!(proposer.promises().is_none());
         assert_eq!(Some(Ballot(101, 1)), proposer.highest_observed_ballot());
         assert!(match proposer.state {
             ProposerState::Leader { proposal: Ballot(101, 1) } => true,
@@ -209,7 +248,7 @@ mod tests {
     #[test]
     fn proposer_receive_reject() {
         // start a producer that receives rejections during Phase 1
-        let mut proposer = Proposer::new(1, 2);
+        let mut proposer = Proposer::new(1, Arc::new(FlexibleQuorum::new(2, 2)));
         // fake observing high ballot
         proposer.observe_ballot(Ballot(100, 1));
 
@@ -0,0 +1,192 @@
+use crate::{entry, ConfigError, QueueLimits};
+use bytes::Bytes;
+use std::{collections::VecDeque, error, fmt};
+
+/// Error returned for a proposal that was not decided
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProposeError {
+    /// The proposal queue is full (see `Configuration::with_proposal_queue`).
+    /// The value may be proposed again once queued values have been
+    /// proposed.
+    WouldBlock,
+    /// The proposal was abandoned after the proposal timeout (see
+    /// `Configuration::with_proposal_timeout`). The value may still be
+    /// decided later.
+    TimedOut,
+    /// The replica stopped before the proposal was decided
+    Stopped,
+    /// The reconfiguration cannot be applied to the configuration
+    InvalidReconfiguration(ConfigError),
+    /// The storage failed to persist the state required for the proposal
+    Storage,
+}
+
+impl fmt::Display for ProposeError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProposeError::WouldBlock => write!(fmt, "proposal queue is full"),
+            ProposeError::TimedOut => write!(fmt, "proposal was not decided before the timeout"),
+            ProposeError::Stopped => write!(fmt, "replica stopped before the proposal was decided"),
+            ProposeError::InvalidReconfiguration(e) => {
+                write!(fmt, "invalid reconfiguration: {}", e)
+            }
+            ProposeError::Storage => write!(fmt, "storage failed to persist the proposal"),
+        }
+    }
+}
+
+impl error::Error for ProposeError {}
+
+/// Queue of proposed values awaiting a slot.
+///
+/// The queue is optionally bounded by a number of values and a budget of
+/// bytes across the queued values. A value is always accepted into an empty
+/// queue such that values larger than the budget are not rejected forever.
+#[derive(Debug, Default)]
+pub struct ProposalQueue {
+    /// Queued values along with the tick at which each was queued
+    values: VecDeque<(Bytes, u64)>,
+    /// Total length of the queued values
+    bytes: usize,
+    /// Maximum number of values and bytes of the queue
+    limits: Option<QueueLimits>,
+}
+
+impl ProposalQueue {
+    /// Creates a queue with optional limits on the number of values and bytes
+    pub fn new(limits: Option<QueueLimits>) -> ProposalQueue {
+        ProposalQueue { values: VecDeque::new(), bytes: 0, limits }
+    }
+
+    /// Flag indicating whether the value fits within the limits of the queue
+    pub fn has_capacity(&self, val: &Bytes) -> bool {
+        match self.limits {
+            Some(_) if self.values.is_empty() => true,
+            Some(limits) => {
+                self.values.len() < limits.capacity && self.bytes + val.len() <= limits.bytes
+            }
+            None => true,
+        }
+    }
+
+    /// Adds a value to the back of the queue at the current tick
+    pub fn push_back(&mut self, val: Bytes, now: u64) {
+        self.bytes += val.len();
+        self.values.push_back((val, now));
+    }
+
+    /// Removes the value at the front of the queue
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let (val, _) = self.values.pop_front()?;
+        self.bytes -= val.len();
+        Some(val)
+    }
+
+    /// Removes values from the front of the queue, up to `count` values and
+    /// `bytes` in total. The value at the front is always removed, even if
+    /// it is larger than `bytes`. Reconfigurations are not batched with
+    /// other values.
+    pub fn pop_batch(&mut self, count: usize, bytes: usize) -> Vec<Bytes> {
+        let mut batch = Vec::new();
+        let mut len = 0;
+        while let Some((val, _)) = self.values.front() {
+            let full = batch.len() == count || len + val.len() > bytes;
+            let single = entry::is_reconfiguration(val)
+                || batch.first().map(entry::is_reconfiguration).unwrap_or(false);
+            if !batch.is_empty() && (full || single) {
+                break;
+            }
+            len += val.len();
+            batch.extend(self.pop_front());
+        }
+        batch
+    }
+
+    /// Flag indicating whether the queued values fill a batch of `count`
+    /// values or `bytes` in total
+    pub fn is_batch_full(&self, count: usize, bytes: usize) -> bool {
+        self.values.len() >= count || self.bytes >= bytes
+    }
+
+    /// Tick at which the value at the front of the queue was queued
+    pub fn oldest(&self) -> Option<u64> {
+        self.values.front().map(|(_, queued)| *queued)
+    }
+
+    /// Removes all values from the queue
+    pub fn take(&mut self) -> Vec<Bytes> {
+        self.bytes = 0;
+        self.values.drain(..).map(|(val, _)| val).collect()
+    }
+
+    /// Flag indicating whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unbounded() {
+        let mut queue = ProposalQueue::new(None);
+        for _ in 0..100 {
+            assert!(queue.has_capacity(&"123".into()));
+            queue.push_back("123".into(), 0);
+        }
+        assert_eq!(100, queue.take().len());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn capacity() {
+        let mut queue = ProposalQueue::new(Some(QueueLimits { capacity: 2, bytes: 100 }));
+        queue.push_back("123".into(), 0);
+        assert!(queue.has_capacity(&"456".into()));
+        queue.push_back("456".into(), 0);
+        assert!(!queue.has_capacity(&"789".into()));
+
+        assert_eq!(Some("123".into()), queue.pop_front());
+        assert!(queue.has_capacity(&"789".into()));
+    }
+
+    #[test]
+    fn budget() {
+        let mut queue = ProposalQueue::new(Some(QueueLimits { capacity: 10, bytes: 6 }));
+        // values larger than the budget are accepted into an empty queue
+        assert!(queue.has_capacity(&"1234567".into()));
+        queue.push_back("1234567".into(), 0);
+        assert!(!queue.has_capacity(&"1".into()));
+
+        queue.pop_front();
+        queue.push_back("123".into(), 0);
+        assert!(queue.has_capacity(&"456".into()));
+        assert!(!queue.has_capacity(&"4567".into()));
+
+        queue.take();
+        assert!(queue.has_capacity(&"123456".into()));
+    }
+
+    #[test]
+    fn batch() {
+        let mut queue = ProposalQueue::new(None);
+        assert_eq!(None, queue.oldest());
+        queue.push_back("1".into(), 2);
+        queue.push_back("23".into(), 3);
+        queue.push_back("456".into(), 3);
+        queue.push_back("7890".into(), 5);
+        assert_eq!(Some(2), queue.oldest());
+        assert!(queue.is_batch_full(4, 100));
+        assert!(!queue.is_batch_full(5, 100));
+        assert!(queue.is_batch_full(5, 10));
+
+        // batches are bounded by the count and bytes
+        assert_eq!(vec![Bytes::from("1"), Bytes::from("23")], queue.pop_batch(2, 100));
+        assert_eq!(Some(3), queue.oldest());
+        assert_eq!(vec![Bytes::from("456")], queue.pop_batch(10, 6));
+        assert_eq!(vec![Bytes::from("7890")], queue.pop_batch(10, 2));
+        assert!(queue.pop_batch(10, 2).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::NodeId;
+use std::{collections::HashMap, fmt};
+
+/// A quorum system decides whether a set of nodes forms a quorum for either
+/// phase of the Paxos algorithm.
+///
+/// For safety, every Phase 1 quorum must intersect every Phase 2 quorum.
+/// Phase 1 quorums need not intersect each other, nor do Phase 2 quorums
+/// (see [Flexible Paxos](https://fpaxos.github.io/)).
+///
+/// The nodes passed to the quorum system are distinct and in sorted order.
+/// The set includes the current node when it participates in the phase, such
+/// as the leader accepting its own proposal.
+pub trait QuorumSystem: fmt::Debug + Send + Sync {
+    /// Flag indicating whether the nodes form a Phase 1 (PREPARE) quorum
+    fn is_phase1_quorum(&self, nodes: &[NodeId]) -> bool;
+
+    /// Flag indicating whether the nodes form a Phase 2 (ACCEPT) quorum
+    fn is_phase2_quorum(&self, nodes: &[NodeId]) -> bool;
+
+    /// Number of nodes in the smallest Phase 1 and Phase 2 quorums
+    fn quorum_size(&self) -> (usize, usize);
+}
+
+/// Quorums consisting of a simple majority of nodes for both phases.
+#[derive(Debug, Clone)]
+pub struct MajorityQuorum {
+    size: usize,
+    /// Sorted members, if only members count towards quorum
+    members: Option<Vec<NodeId>>,
+}
+
+impl MajorityQuorum {
+    /// Creates majority quorums for a number of nodes
+    pub fn new(nodes: usize) -> MajorityQuorum {
+        assert!(nodes > 0);
+        MajorityQuorum { size: 1 + nodes / 2, members: None }
+    }
+
+    /// Creates majority quorums of the members, in which other nodes do not
+    /// count towards quorum
+    pub fn with_members<I>(members: I) -> MajorityQuorum
+    where
+        I: IntoIterator<Item = NodeId>,
+    {
+        let mut members = members.into_iter().collect::<Vec<NodeId>>();
+        members.sort();
+        members.dedup();
+        MajorityQuorum { size: 1 + members.len() / 2, members: Some(members) }
+    }
+
+    fn has_majority(&self, nodes: &[NodeId]) -> bool {
+        match self.members {
+            Some(ref members) => {
+                nodes.iter().filter(|n| members.binary_search(n).is_ok()).count() >= self.size
+            }
+            None => nodes.len() >= self.size,
+        }
+    }
+}
+
+impl QuorumSystem for MajorityQuorum {
+    fn is_phase1_quorum(&self, nodes: &[NodeId]) -> bool {
+        self.has_majority(nodes)
+    }
+
+    fn is_phase2_quorum(&self, nodes: &[NodeId]) -> bool {
+        self.has_majority(nodes)
+    }
+
+    fn quorum_size(&self) -> (usize, usize) {
+        (self.size, self.size)
+    }
+}
+
+/// Quorums consisting of a number of nodes that may differ between phases.
+///
+/// The sizes must satisfy `phase1 + phase2 > n` for `n` nodes, see
+/// `Configuration::with_quorum_size`.
+#[derive(Debug, Clone)]
+pub struct FlexibleQuorum {
+    phase1: usize,
+    phase2: usize,
+}
+
+impl FlexibleQuorum {
+    /// Creates quorums of the given sizes
+    pub fn new(phase1: usize, phase2: usize) -> FlexibleQuorum {
+        assert!(phase1 > 0 && phase2 > 0);
+        FlexibleQuorum { phase1, phase2 }
+    }
+}
+
+impl QuorumSystem for FlexibleQuorum {
+    fn is_phase1_quorum(&self, nodes: &[NodeId]) -> bool {
+        nodes.len() >= self.phase1
+    }
+
+    fn is_phase2_quorum(&self, nodes: &[NodeId]) -> bool {
+        nodes.len() >= self.phase2
+    }
+
+    fn quorum_size(&self) -> (usize, usize) {
+        (self.phase1, self.phase2)
+    }
+}
+
+/// Quorums formed by arranging the nodes in a grid.
+///
+/// A Phase 1 quorum contains every node of any row while a Phase 2 quorum
+/// contains at least one node from each row (e.g. a column). Every row
+/// therefore intersects every Phase 2 quorum.
+#[derive(Debug, Clone)]
+pub struct GridQuorum {
+    rows: Vec<Vec<NodeId>>,
+}
+
+impl GridQuorum {
+    /// Creates a grid from rows of nodes
+    pub fn new(rows: Vec<Vec<NodeId>>) -> GridQuorum {
+        assert!(!rows.is_empty() && rows.iter().all(|row| !row.is_empty()));
+        GridQuorum { rows }
+    }
+}
+
+impl QuorumSystem for GridQuorum {
+    fn is_phase1_quorum(&self, nodes: &[NodeId]) -> bool {
+        self.rows.iter().any(|row| row.iter().all(|n| nodes.binary_search(n).is_ok()))
+    }
+
+    fn is_phase2_quorum(&self, nodes: &[NodeId]) -> bool {
+        self.rows.iter().all(|row| row.iter().any(|n| nodes.binary_search(n).is_ok()))
+    }
+
+    fn quorum_size(&self) -> (usize, usize) {
+        let shortest = self.rows.iter().map(Vec::len).min().unwrap_or(0);
+        (shortest, self.rows.len())
+    }
+}
+
+/// Quorums formed by nodes holding a majority of votes, where each node is
+/// assigned a number of votes.
+#[derive(Debug, Clone)]
+pub struct WeightedQuorum {
+    weights: HashMap<NodeId, u64>,
+    total: u64,
+}
+
+impl WeightedQuorum {
+    /// Creates quorums from the votes of each node
+    pub fn new<I>(weights: I) -> WeightedQuorum
+    where
+        I: IntoIterator<Item = (NodeId, u64)>,
+    {
+        let weights: HashMap<NodeId, u64> = weights.into_iter().collect();
+        let total = weights.values().sum();
+        assert!(total > 0);
+        WeightedQuorum { weights, total }
+    }
+
+    fn votes(&self, nodes: &[NodeId]) -> u64 {
+        nodes.iter().filter_map(|n| self.weights.get(n)).sum()
+    }
+}
+
+impl QuorumSystem for WeightedQuorum {
+    fn is_phase1_quorum(&self, nodes: &[NodeId]) -> bool {
+        2 * self.votes(nodes) > self.total
+    }
+
+    fn is_phase2_quorum(&self, nodes: &[NodeId]) -> bool {
+        2 * self.votes(nodes) > self.total
+    }
+
+    fn quorum_size(&self) -> (usize, usize) {
+        // the nodes with the most votes form the smallest quorum
+        let mut weights = self.weights.values().cloned().collect::<Vec<u64>>();
+        weights.sort_unstable_by(|a, b| b.cmp(a));
+        let mut votes = 0;
+        let size = weights
+            .iter()
+            .position(|w| {
+                votes += w;
+                2 * votes > self.total
+            })
+            .map_or(weights.len(), |i| i + 1);
+        (size, size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn majority_quorum() {
+        let quorum = MajorityQuorum::new(4);
+        assert!(!quorum.is_phase1_quorum(&[0, 1]));
+        assert!(quorum.is_phase1_quorum(&[0, 1, 2]));
+        assert!(!quorum.is_phase2_quorum(&[2, 3]));
+        assert!(quorum.is_phase2_quorum(&[1, 2, 3]));
+        assert_eq!((3, 3), quorum.quorum_size());
+
+        // only members count towards quorum
+        let quorum = MajorityQuorum::with_members(vec![3, 1, 2, 0]);
+        assert!(!quorum.is_phase1_quorum(&[0, 1, 5]));
+        assert!(quorum.is_phase1_quorum(&[0, 1, 3]));
+        assert!(!quorum.is_phase2_quorum(&[2, 3, 5]));
+        assert!(quorum.is_phase2_quorum(&[1, 2, 3, 5]));
+    }
+
+    #[test]
+    fn flexible_quorum() {
+        let quorum = FlexibleQuorum::new(4, 2);
+        assert!(!quorum.is_phase1_quorum(&[0, 1, 2]));
+        assert!(quorum.is_phase1_quorum(&[0, 1, 2, 3]));
+        assert!(!quorum.is_phase2_quorum(&[1]));
+        assert!(quorum.is_phase2_quorum(&[1, 4]));
+        assert_eq!((4, 2), quorum.quorum_size());
+    }
+
+    #[test]
+    fn grid_quorum() {
+        let quorum = GridQuorum::new(vec![vec![0, 1, 2], vec![3, 4, 5]]);
+        assert!(quorum.is_phase1_quorum(&[0, 1, 2]));
+        assert!(quorum.is_phase1_quorum(&[1, 3, 4, 5]));
+        assert!(!quorum.is_phase1_quorum(&[0, 1, 3, 4]));
+
+        assert!(quorum.is_phase2_quorum(&[0, 3]));
+        assert!(quorum.is_phase2_quorum(&[2, 4]));
+        assert!(!quorum.is_phase2_quorum(&[0, 1, 2]));
+        assert_eq!((3, 2), quorum.quorum_size());
+    }
+
+    #[test]
+    fn weighted_quorum() {
+        let quorum = WeightedQuorum::new(vec![(0, 3), (1, 1), (2, 1), (3, 1)]);
+        assert!(quorum.is_phase1_quorum(&[0, 1]));
+        assert!(!quorum.is_phase1_quorum(&[0]));
+        assert!(!quorum.is_phase2_quorum(&[1, 2, 3]));
+        assert!(quorum.is_phase2_quorum(&[0, 3]));
+
+        // unknown nodes have no votes
+        assert!(!quorum.is_phase2_quorum(&[1, 2, 7]));
+        assert_eq!((2, 2), quorum.quorum_size());
+    }
+}
@@ -1,46 +1,311 @@
 use crate::{
     acceptor::{AcceptResponse, PrepareResponse},
     commands::*,
+    config::Phase,
+    entry::{self, Entry},
+    lease::ReadLease,
     proposer::{Proposer, ProposerStatus},
+    queue::ProposalQueue,
+    timer::ElectionTimer,
     window::{SlotMutRef, SlotWindow},
-    Ballot, Configuration, NodeId, ReplicatedState, Slot, SlottedValue,
+    Ballot, ConfigError, Configuration, MemoryStorage, Message, NodeId, ProposalId, ProposeError,
+    Reconfiguration, ReplicatedState, Slot, SlottedValue, Storage,
 };
 use bytes::Bytes;
-use std::mem;
+use std::{
+    cmp::{self, Reverse},
+    collections::{HashMap, VecDeque},
+    io, mem,
+    ops::Range,
+};
 
 /// State manager for multi-paxos group
-pub struct Replica<S> {
+pub struct Replica<S, T = MemoryStorage> {
     sender: S,
     config: Configuration,
     proposer: Proposer,
     window: SlotWindow,
+    storage: T,
+    /// Slot of the latest snapshot of the state machine
+    last_snapshot: Option<Slot>,
+    /// Start of the last gap of unresolved slots requested from a peer
+    catchup_requested: Option<Slot>,
+    /// Peer that promised with the furthest open window, along with the
+    /// start of its window. The leader must learn the decisions of prior
+    /// slots before proposing values in its open window.
+    leader_catchup: Option<(NodeId, Slot)>,
+
+    /// Logical clock, in ticks
+    ticks: u64,
+    /// Tick of the last retransmission of unanswered messages
+    last_retransmit: u64,
+    /// Tick of the last heartbeat sent while leader
+    last_heartbeat: u64,
+    /// Timeout for hearing from the leader before starting an election
+    election_timer: ElectionTimer,
+    /// Tick of the latest response received from each peer
+    responses: HashMap<NodeId, u64>,
+    /// Executed reconfigurations along with the slot at which each takes
+    /// effect
+    reconfigurations: Vec<(Slot, Reconfiguration)>,
+    /// Number of reconfigurations applied to the configuration
+    applied_reconfigurations: usize,
+    /// Read lease granted to the leader, if enabled
+    lease: Option<ReadLease>,
+    /// Slot from which the leader proposes values of its own ballot. Earlier
+    /// slots may hold values decided in prior ballots.
+    leader_start: Slot,
+    /// Sequence number of the latest heartbeat sent while leader
+    heartbeat_seq: u64,
+    /// Latest heartbeat sequence number acknowledged by each peer
+    heartbeat_acks: HashMap<NodeId, u64>,
+    /// Read barriers awaiting confirmation of leadership or execution
+    read_barriers: VecDeque<ReadBarrier>,
+    /// Identifier of the next proposal submitted with `propose`
+    next_proposal: ProposalId,
+    /// Proposals submitted with `propose` awaiting a decision
+    pending_proposals: HashMap<ProposalId, PendingProposal>,
+    /// Flag indicating whether the messages of a received batch are being
+    /// handled, in which case the sender is flushed once the batch is handled
+    batching: bool,
+    /// Messages held until the writes of the storage preceding them are
+    /// durable (see `Storage::is_durable`)
+    held: Vec<(NodeId, Message)>,
+
+    /// Values awaiting a slot
+    proposal_queue: ProposalQueue,
+}
+
+/// Proposal submitted with `propose` awaiting a decision
+#[derive(Clone, Copy, Debug)]
+enum PendingProposal {
+    /// Awaiting a decision since the tick at which it was submitted
+    Submitted(u64),
+    /// Abandoned after the proposal timeout. A decision is still reported
+    /// until the slot has been executed.
+    Abandoned(Slot),
+}
 
-    // TODO: bound the proposal queue
-    proposal_queue: Vec<Bytes>,
+/// Read barrier awaiting confirmation of leadership by a quorum
+#[derive(Clone, Copy, Debug)]
+struct ReadBarrier {
+    /// Identifier supplied by the caller
+    id: u64,
+    /// Ballot of the leader when the barrier was requested
+    bal: Ballot,
+    /// Highest decided slot when the barrier was requested
+    slot: Option<Slot>,
+    /// Sequence number of the heartbeat that confirms leadership
+    seq: u64,
 }
 
 impl<S: Sender> Replica<S> {
-    /// Replica creation from a sender and starting configuration
+    /// Replica creation from a sender and starting configuration.
+    ///
+    /// Acceptor state is kept in memory, see `with_storage` for
+    /// durable replicas.
     pub fn new(sender: S, config: Configuration) -> Replica<S> {
-        let (p1_quorum, p2_quorum) = config.quorum_size();
+        let quorum = config.quorum_system();
         let node = config.current();
+        let election_timer = ElectionTimer::new(node, config.election_timeout());
+        let lease = config.read_lease().map(|l| ReadLease::new(l.lease_ticks, l.drift_ticks));
+        let proposal_queue = ProposalQueue::new(config.proposal_queue());
         Replica {
             sender,
             config,
-            proposer: Proposer::new(node, p1_quorum),
-            proposal_queue: Vec::new(),
-            window: SlotWindow::new(p2_quorum),
+            proposer: Proposer::new(node, quorum.clone()),
+            proposal_queue,
+            window: SlotWindow::new(quorum),
+            storage: MemoryStorage::new(),
+            last_snapshot: None,
+            catchup_requested: None,
+            leader_catchup: None,
+            ticks: 0,
+            last_retransmit: 0,
+            last_heartbeat: 0,
+            election_timer,
+            responses: HashMap::new(),
+            reconfigurations: Vec::new(),
+            applied_reconfigurations: 0,
+            lease,
+            leader_start: 0,
+            heartbeat_seq: 0,
+            heartbeat_acks: HashMap::new(),
+            read_barriers: VecDeque::new(),
+            next_proposal: 0,
+            pending_proposals: HashMap::new(),
+            batching: false,
+            held: Vec::new(),
+        }
+    }
+}
+
+impl<S: Sender, T: Storage> Replica<S, T> {
+    /// Recovers a replica from acceptor state persisted in the storage.
+    ///
+    /// The latest snapshot is restored into the state machine, then promises,
+    /// accepted values and resolutions are replayed into the replica such
+    /// that the replica will not break promises made prior to a restart nor
+    /// reuse a ballot. Recovered decisions are executed against the state
+    /// machine, skipping slots the state machine reports as already applied.
+    ///
+    /// Each recovery persists a new incarnation of the replica, which forms
+    /// the upper 32 bits of subsequent proposal identifiers such that
+    /// proposals made before the restart are not mistaken for new proposals.
+    /// A new incarnation is also persisted once the identifiers of an
+    /// incarnation are exhausted.
+    pub fn recover(
+        mut sender: S,
+        config: Configuration,
+        mut storage: T,
+    ) -> io::Result<Replica<S, T>> {
+        let state = storage.load()?;
+        let incarnation = state.incarnation + 1;
+        storage.store_incarnation(incarnation)?;
+        let quorum = config.quorum_system();
+        let node = config.current();
+
+        let mut proposer = Proposer::new(node, quorum.clone());
+        if let Some(bal) = state.highest_ballot() {
+            proposer.observe_ballot(bal);
+        }
+
+        let mut window = SlotWindow::new(quorum);
+        let mut reconfigurations = Vec::new();
+        let last_snapshot = state.snapshot.map(|(slot, snapshot)| {
+            let (executed, snapshot) = entry::decode_snapshot(snapshot);
+            reconfigurations = executed;
+            let state_machine = sender.state_machine();
+            if state_machine.applied().map(|s| s < slot).unwrap_or(true) {
+                state_machine.restore(slot, snapshot);
+            }
+            window.advance_to(slot);
+            slot
+        });
+        if let Some(bal) = state.promised {
+            window.restore_promise(bal);
+        }
+        for (slot, bal, val) in state.accepted {
+            match window.slot_mut(slot) {
+                SlotMutRef::Empty(empty_slot) => {
+                    empty_slot.fill().acceptor().notice_value(bal, val);
+                }
+                SlotMutRef::Open(ref mut open_slot) => {
+                    open_slot.acceptor().notice_value(bal, val);
+                }
+                _ => {}
+            }
+        }
+        for (slot, bal, val) in state.resolved {
+            match window.slot_mut(slot) {
+                SlotMutRef::Empty(empty_slot) => empty_slot.fill().acceptor().resolve(bal, val),
+                SlotMutRef::Open(ref mut open_slot) => open_slot.acceptor().resolve(bal, val),
+                _ => {}
+            }
+        }
+
+        debug!("Recovered replica with open window {:?}", window.open_range());
+
+        let election_timer = ElectionTimer::new(node, config.election_timeout());
+        let mut lease = config.read_lease().map(|l| ReadLease::new(l.lease_ticks, l.drift_ticks));
+        if let Some(ref mut lease) = lease {
+            // a lease granted before the restart may still be held
+            lease.restore(0);
         }
+        let proposal_queue = ProposalQueue::new(config.proposal_queue());
+
+        let mut replica = Replica {
+            sender,
+            config,
+            proposer,
+            proposal_queue,
+            window,
+            storage,
+            last_snapshot,
+            catchup_requested: None,
+            leader_catchup: None,
+            ticks: 0,
+            last_retransmit: 0,
+            last_heartbeat: 0,
+            election_timer,
+            responses: HashMap::new(),
+            reconfigurations: Vec::new(),
+            applied_reconfigurations: 0,
+            lease,
+            leader_start: 0,
+            heartbeat_seq: 0,
+            heartbeat_acks: HashMap::new(),
+            read_barriers: VecDeque::new(),
+            next_proposal: incarnation << 32,
+            pending_proposals: HashMap::new(),
+            batching: false,
+            held: Vec::new(),
+        };
+        replica.restore_reconfigurations(reconfigurations);
+        replica.execute_decisions();
+        Ok(replica)
     }
 
     /// Replace the sender with an alertnate implementation
-    pub fn with_sender<A>(self, sender: A) -> Replica<A> {
+    pub fn with_sender<A>(self, sender: A) -> Replica<A, T> {
         Replica {
             sender: sender,
             config: self.config,
             proposer: self.proposer,
             proposal_queue: self.proposal_queue,
             window: self.window,
+            storage: self.storage,
+            last_snapshot: self.last_snapshot,
+            catchup_requested: self.catchup_requested,
+            leader_catchup: self.leader_catchup,
+            ticks: self.ticks,
+            last_retransmit: self.last_retransmit,
+            last_heartbeat: self.last_heartbeat,
+            election_timer: self.election_timer,
+            responses: self.responses,
+            reconfigurations: self.reconfigurations,
+            applied_reconfigurations: self.applied_reconfigurations,
+            lease: self.lease,
+            leader_start: self.leader_start,
+            heartbeat_seq: self.heartbeat_seq,
+            heartbeat_acks: self.heartbeat_acks,
+            read_barriers: self.read_barriers,
+            next_proposal: self.next_proposal,
+            pending_proposals: self.pending_proposals,
+            batching: self.batching,
+            held: self.held,
+        }
+    }
+
+    /// Replace the storage of acceptor state with an alternate
+    /// implementation
+    pub fn with_storage<A: Storage>(self, storage: A) -> Replica<S, A> {
+        Replica {
+            sender: self.sender,
+            config: self.config,
+            proposer: self.proposer,
+            proposal_queue: self.proposal_queue,
+            window: self.window,
+            storage,
+            last_snapshot: self.last_snapshot,
+            catchup_requested: self.catchup_requested,
+            leader_catchup: self.leader_catchup,
+            ticks: self.ticks,
+            last_retransmit: self.last_retransmit,
+            last_heartbeat: self.last_heartbeat,
+            election_timer: self.election_timer,
+            responses: self.responses,
+            reconfigurations: self.reconfigurations,
+            applied_reconfigurations: self.applied_reconfigurations,
+            lease: self.lease,
+            leader_start: self.leader_start,
+            heartbeat_seq: self.heartbeat_seq,
+            heartbeat_acks: self.heartbeat_acks,
+            read_barriers: self.read_barriers,
+            next_proposal: self.next_proposal,
+            pending_proposals: self.pending_proposals,
+            batching: self.batching,
+            held: self.held,
         }
     }
 
@@ -54,6 +319,333 @@ impl<S: Sender> Replica<S> {
         &self.sender
     }
 
+    /// Configuration currently in effect
+    pub fn config(&self) -> &Configuration {
+        &self.config
+    }
+
+    /// Proposes a value, returning an identifier of the proposal.
+    ///
+    /// Once the value has been decided and executed, the state machine of
+    /// this replica is notified with `ReplicatedState::proposal_decided`
+    /// along with the slot of the decision. Proposals that are not decided
+    /// within the proposal timeout are abandoned (see
+    /// `Configuration::with_proposal_timeout`). The value is rejected with
+    /// `ProposeError::WouldBlock` if the proposal queue is full.
+    pub fn propose(&mut self, val: Bytes) -> Result<ProposalId, ProposeError> {
+        if self.next_proposal as u32 == u32::MAX {
+            // identifiers must not run into those of the next incarnation
+            let incarnation = (self.next_proposal >> 32) + 1;
+            if let Err(e) = self.storage.store_incarnation(incarnation) {
+                error!("Error persisting incarnation {}: {}", incarnation, e);
+                return Err(ProposeError::Storage);
+            }
+            self.next_proposal = incarnation << 32;
+        }
+
+        let id = self.next_proposal;
+        self.submit(Entry::Proposal(self.config.current(), id, val).encode())?;
+        self.next_proposal += 1;
+        self.pending_proposals.insert(id, PendingProposal::Submitted(self.ticks));
+        self.flush();
+        Ok(id)
+    }
+
+    /// Proposes a change to the membership of the cluster.
+    ///
+    /// Once the change is decided at slot `s`, it takes effect for slots
+    /// `>= s + α` (see `Configuration::with_alpha`).
+    pub fn reconfigure(&mut self, change: Reconfiguration) -> Result<(), ProposeError> {
+        self.check_reconfiguration(&change).map_err(ProposeError::InvalidReconfiguration)?;
+        self.submit(Entry::Reconfiguration(change).encode())?;
+        self.flush();
+        Ok(())
+    }
+
+    /// Flag indicating whether the replica is the leader and holds a valid
+    /// read lease (see `Configuration::with_read_lease`), in which case reads
+    /// from the state machine are linearizable.
+    pub fn has_read_lease(&self) -> bool {
+        let lease = match self.lease {
+            Some(ref lease) if self.proposer.status() == ProposerStatus::Leader => lease,
+            _ => return false,
+        };
+        let quorum = self.config.quorum_system();
+        lease.is_valid(
+            self.config.current(),
+            self.ticks,
+            self.window.executed_through(),
+            quorum.as_ref(),
+        )
+    }
+
+    /// Reads from the state machine while the replica holds a read lease,
+    /// without proposing the read to the log. Returns `None` if the lease is
+    /// not held, in which case the read should be proposed instead.
+    pub fn read<F, R>(&mut self, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut S::StateMachine) -> R,
+    {
+        if !self.has_read_lease() {
+            return None;
+        }
+        Some(f(self.sender.state_machine()))
+    }
+
+    /// Requests a read barrier at the leader, returning false if the replica
+    /// is not the leader.
+    ///
+    /// The barrier records the highest decided slot and confirms leadership
+    /// with a round of heartbeats acknowledged by a quorum. Once confirmed and
+    /// the state machine has executed through the recorded slot, the state
+    /// machine is notified with `ReplicatedState::read_ready`, after which
+    /// reads from the state machine are linearizable without proposing the
+    /// read to the log.
+    pub fn read_barrier(&mut self, id: u64) -> bool {
+        if self.proposer.status() != ProposerStatus::Leader {
+            return false;
+        }
+
+        let bal = self.proposer.highest_observed_ballot().unwrap();
+        let slot = cmp::max(self.window.last_resolved(), self.leader_start.checked_sub(1));
+        self.send_heartbeat();
+        self.read_barriers.push_back(ReadBarrier { id, bal, slot, seq: self.heartbeat_seq });
+        self.complete_reads();
+        self.flush();
+        true
+    }
+
+    /// Mutable reference to the storage
+    pub fn storage_mut(&mut self) -> &mut T {
+        &mut self.storage
+    }
+
+    /// Reference to the storage
+    pub fn storage(&self) -> &T {
+        &self.storage
+    }
+
+    /// Advances the logical clock of the replica by a single tick.
+    ///
+    /// The embedder calls `tick` periodically to drive timeouts within the
+    /// replica. PREPARE and ACCEPT messages that have not been answered by a
+    /// peer, as well as requests for missing resolutions, are re-sent every
+    /// `Configuration::retransmit_ticks` ticks.
+    ///
+    /// The leader sends a HEARTBEAT to its followers every
+    /// `Configuration::heartbeat_ticks` ticks. A replica that has not heard
+    /// from the leader within the randomized election timeout starts Phase 1
+    /// with a higher ballot in an attempt to become the leader.
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+        match self.proposer.status() {
+            ProposerStatus::Leader => {
+                if self.ticks - self.last_heartbeat >= self.config.heartbeat_ticks() {
+                    self.send_heartbeat();
+                }
+                // batches that have waited for the batching window
+                self.propose_queued();
+            }
+            _ if self.config.is_member() && self.election_timer.is_expired(self.ticks) => {
+                debug!("Election timeout expired at tick {}", self.ticks);
+                self.start_election();
+            }
+            _ => {}
+        }
+
+        if self.ticks - self.last_retransmit >= self.config.retransmit_ticks() {
+            self.last_retransmit = self.ticks;
+            self.retransmit();
+        }
+
+        // abort read barriers of a former leader
+        self.complete_reads();
+        self.abandon_proposals();
+
+        if let Err(e) = self.storage.flush() {
+            error!("Error flushing storage at tick {}: {}", self.ticks, e);
+        }
+        self.flush();
+    }
+
+    /// Notifies the state machine of proposals that have not been decided
+    /// within the proposal timeout. Abandoned proposals remain pending until
+    /// α slots past the known slots have been executed, such that a decision
+    /// shortly after the timeout is still reported.
+    fn abandon_proposals(&mut self) {
+        let timeout = match self.config.proposal_timeout() {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let now = self.ticks;
+        let executed = self.window.executed_through();
+        self.pending_proposals.retain(|_, pending| match *pending {
+            PendingProposal::Abandoned(slot) => executed.map(|e| e < slot).unwrap_or(true),
+            PendingProposal::Submitted(_) => true,
+        });
+
+        let mut abandoned = self
+            .pending_proposals
+            .iter()
+            .filter(|(_, pending)| match **pending {
+                PendingProposal::Submitted(submitted) => now - submitted >= timeout,
+                PendingProposal::Abandoned(_) => false,
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<ProposalId>>();
+        abandoned.sort();
+        let watermark = self.window.open_range().end + self.config.alpha();
+        for id in abandoned {
+            debug!("Abandoning proposal {} at tick {}", id, now);
+            self.pending_proposals.insert(id, PendingProposal::Abandoned(watermark));
+            self.sender.state_machine().proposal_abandoned(id);
+        }
+    }
+
+    /// Sends a HEARTBEAT with the next sequence number to the followers
+    fn send_heartbeat(&mut self) {
+        self.last_heartbeat = self.ticks;
+        self.heartbeat_seq += 1;
+        let bal = self.proposer.highest_observed_ballot().unwrap();
+        let seq = self.heartbeat_seq;
+        if let Some(ref mut lease) = self.lease {
+            lease.heartbeat_sent(seq, self.ticks);
+        }
+        self.broadcast_all(|c| c.heartbeat(bal, seq));
+    }
+
+    /// Notifies the state machine of read barriers that have been confirmed
+    /// by a quorum and executed, in order, and aborts read barriers once the
+    /// replica is no longer the leader of the ballot.
+    fn complete_reads(&mut self) {
+        let leader = match self.proposer.status() {
+            ProposerStatus::Leader => self.proposer.highest_observed_ballot(),
+            _ => None,
+        };
+        let executed = self.window.executed_through();
+        while let Some(&barrier) = self.read_barriers.front() {
+            if leader != Some(barrier.bal) {
+                debug!("Aborting read barrier {} for {:?}", barrier.id, barrier.bal);
+                self.read_barriers.pop_front();
+                self.sender.state_machine().read_aborted(barrier.id);
+                continue;
+            }
+            if barrier.slot > executed || !self.is_confirmed(barrier.seq) {
+                return;
+            }
+
+            trace!("Completed read barrier {} at slot {:?}", barrier.id, barrier.slot);
+            self.read_barriers.pop_front();
+            self.sender.state_machine().read_ready(barrier.id);
+        }
+    }
+
+    /// Flag indicating whether a quorum has acknowledged the heartbeat with
+    /// the sequence number, or a later heartbeat
+    fn is_confirmed(&self, seq: u64) -> bool {
+        let mut nodes = self
+            .heartbeat_acks
+            .iter()
+            .filter(|(_, acked)| **acked >= seq)
+            .map(|(node, _)| *node)
+            .chain(Some(self.config.current()))
+            .collect::<Vec<NodeId>>();
+        nodes.sort();
+        nodes.dedup();
+        self.config.quorum_system().is_phase2_quorum(&nodes)
+    }
+
+    /// Starts Phase 1 with a new ballot
+    fn start_election(&mut self) {
+        self.election_timer.reset(self.ticks);
+        let current = self.config.current();
+        if self.lease.as_ref().map(|l| l.is_held_by_other(current, self.ticks)).unwrap_or(false) {
+            debug!("Delaying election while the read lease is held by another node");
+            return;
+        }
+
+        let bal = self.proposer.prepare();
+        // the proposer promises its own ballot
+        if !self.persist_promise(bal) {
+            return;
+        }
+        if let Some(ref mut lease) = self.lease {
+            lease.grant(bal, self.ticks);
+            lease.prepare(bal, self.ticks);
+        }
+        self.broadcast_quorum(Phase::One, |c| c.prepare(bal));
+        // the proposer's own promise may already form a Phase 1 quorum
+        self.drive_accept();
+    }
+
+    /// Notes that ACCEPT messages have been sent for a slot, which grants the
+    /// read lease to the ballot at the current node
+    fn lease_accept_sent(&mut self, slot: Slot, bal: Ballot) {
+        if let Some(ref mut lease) = self.lease {
+            lease.grant(bal, self.ticks);
+            lease.accept_sent(slot, self.ticks);
+        }
+    }
+
+    /// Restarts the election timeout when a message is received from the
+    /// node holding the highest ballot
+    fn leader_contact(&mut self, bal: Ballot) {
+        if self.proposer.highest_observed_ballot() == Some(bal) {
+            self.election_timer.reset(self.ticks);
+        }
+    }
+
+    /// Re-sends messages that have not been answered by peers
+    fn retransmit(&mut self) {
+        match self.proposer.status() {
+            ProposerStatus::Candidate => {
+                let (bal, promises) = self.proposer.promises().unwrap();
+                let peers = self
+                    .config
+                    .peers()
+                    .into_iter()
+                    .filter(|node| !promises.contains(*node))
+                    .collect::<Vec<NodeId>>();
+                trace!("Re-sending PREPARE for {:?} to {:?}", bal, peers);
+                for node in peers {
+                    self.send_to(node, |c| c.prepare(bal));
+                }
+            }
+            ProposerStatus::Leader => {
+                let bal = self.proposer.highest_observed_ballot().unwrap();
+                let mut accepts = Vec::new();
+                for slot in self.window.open_range() {
+                    if let SlotMutRef::Open(ref mut open_slot) = self.window.slot_mut(slot) {
+                        match open_slot.acceptor().pending_quorum() {
+                            Some((proposed, val, quorum)) if proposed == bal => {
+                                for node in self.config.peers().into_iter() {
+                                    if !quorum.contains(node) {
+                                        accepts.push((node, slot, val.clone()));
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                for (node, slot, val) in accepts {
+                    trace!("Re-sending ACCEPT for slot {} to node {}", slot, node);
+                    self.send_to(node, |c| c.accept(slot, bal, val));
+                }
+            }
+            ProposerStatus::Follower => {}
+        }
+
+        // allow requests for missing state to be re-sent
+        self.catchup_requested = None;
+        if let Some(Ballot(_, leader)) = self.proposer.highest_observed_ballot() {
+            self.request_missing(leader);
+        }
+        if self.leader_catchup.is_some() {
+            self.drive_accept();
+        }
+    }
+
     /// Broadcast ACCEPT messages once the proposer has phase 1 quorum
     fn drive_accept(&mut self) {
         if self.proposer.status() != ProposerStatus::Leader {
@@ -62,11 +654,18 @@ impl<S: Sender> Replica<S> {
 
         let bal = self.proposer.highest_observed_ballot().unwrap();
         assert!(bal.1 == self.config.current());
+        if self.catching_up() {
+            return;
+        }
 
         // add queued proposals to new slots
-        for value in self.proposal_queue.drain(..) {
+        while self.window.peek_next_slot() < self.proposal_limit() {
+            let value = match self.next_value() {
+                Some(value) => value,
+                None => break,
+            };
             let mut slot = self.window.next_slot();
-            slot.acceptor().notice_value(bal, value.clone());
+            slot.acceptor().notice_value(bal, value);
         }
 
         // queue up all accepts
@@ -97,490 +696,2383 @@ impl<S: Sender> Replica<S> {
             .collect::<Vec<SlottedValue>>();
 
         // send out the accepts
+        let mut resolved = false;
         for (slot, bal, val) in accepts {
-            self.broadcast(|c| c.accept(slot, bal, val.clone()));
+            self.apply_reconfigurations(slot);
+            if !self.persist_accepted(slot, bal, val.clone()) {
+                continue;
+            }
+            self.lease_accept_sent(slot, bal);
+            self.broadcast_quorum(Phase::Two, |c| c.accept(slot, bal, val.clone()));
+            // the leader's own acceptance may already form a Phase 2 quorum
+            resolved |= self.accepted_own(slot, bal);
         }
-    }
 
-    /// Forwards pending proposals to the new leader
-    fn forward(&mut self) {
-        if self.proposer.status() != ProposerStatus::Follower || self.proposal_queue.is_empty() {
-            return;
+        // later slots cannot hold values decided in prior ballots
+        self.leader_start = self.window.peek_next_slot();
+
+        // values of prior ballots must be executed before serving reads
+        if let Some(ref mut lease) = self.lease {
+            lease.ready_at(self.leader_start);
         }
 
-        if let Some(Ballot(_, node)) = self.proposer.highest_observed_ballot() {
-            let mut proposals = Vec::new();
-            mem::swap(&mut self.proposal_queue, &mut proposals);
-            self.sender.send_to(node, move |c| {
-                for proposal in proposals.into_iter() {
-                    c.proposal(proposal);
-                }
-            });
+        if resolved {
+            self.execute_decisions();
         }
     }
 
-    /// Executes commands that have been decided.
-    fn execute_decisions(&mut self) {
-        for (slot, val) in self.window.drain_decisions() {
-            if val.len() > 0 {
-                self.sender.state_machine().execute(slot, val);
-            }
+    /// Requests the decisions of slots preceding the open window of a peer
+    /// that promised the leader, returning true while the decisions have
+    /// not yet been learned.
+    fn catching_up(&mut self) -> bool {
+        let (node, slot) = match self.leader_catchup {
+            Some(catchup) => catchup,
+            None => return false,
+        };
+        let start = self.window.open_range().start;
+        if start >= slot {
+            self.leader_catchup = None;
+            return false;
         }
-    }
 
-    fn broadcast<F>(&mut self, f: F)
-    where
-        F: Fn(&mut S::Commander) -> (),
-    {
-        // TODO: thrifty option
-        for node in self.config.peers().into_iter() {
-            self.sender.send_to(node, &f);
+        // a pending request for state covers the missing slots
+        if !self.catchup_requested.map(|s| s <= start).unwrap_or(false) {
+            debug!("Requesting decisions for slots {:?} from node {}", start..slot, node);
+            let current = self.config.current();
+            self.catchup_requested = Some(start);
+            self.send_to(node, |c| c.catchup(current, start, slot));
         }
+        true
     }
-}
 
-impl<S: Sender> Commander for Replica<S> {
-    fn proposal(&mut self, val: Bytes) {
-        // redirect to the distinguished proposer or start PREPARE
-        match self.proposer.status() {
-            ProposerStatus::Follower if self.proposer.highest_observed_ballot().is_none() => {
-                // no known proposers, go through prepare cycle
-                self.proposal_queue.push(val);
-                let bal = self.proposer.prepare();
-                self.broadcast(|c| c.prepare(bal));
-            }
-            ProposerStatus::Follower => {
-                self.sender.send_to(self.proposer.highest_observed_ballot().unwrap().1, |c| {
-                    c.proposal(val)
-                });
-            }
-            ProposerStatus::Candidate => {
-                // still waiting for promises, queue up the value
-                // TODO: should this re-send some PREPARE messages?
-                self.proposal_queue.push(val);
-            }
-            ProposerStatus::Leader => {
-                // node is the distinguished proposer
-                let bal = self.proposer.highest_observed_ballot().unwrap();
-                let slot = {
-                    let mut slot_ref = self.window.next_slot();
-                    slot_ref.acceptor().notice_value(bal, val.clone());
-                    slot_ref.slot()
-                };
-                self.broadcast(|c| c.accept(slot, bal, val.clone()));
+    /// Persists a promise before it is sent to a peer, returning false if
+    /// the promise cannot be made durable.
+    fn persist_promise(&mut self, bal: Ballot) -> bool {
+        match self.storage.store_promise(bal) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Error persisting promise for {:?}: {}", bal, e);
+                false
             }
         }
     }
 
-    fn prepare(&mut self, bal: Ballot) {
-        self.proposer.observe_ballot(bal);
-
-        let node_id = self.config.current();
-
-        let mut accepted = Vec::new();
-        for slot in self.window.open_range() {
-            match self.window.slot_mut(slot) {
-                SlotMutRef::Open(ref mut open_ref) => {
-                    match open_ref.acceptor().receive_prepare(bal) {
-                        PrepareResponse::Promise { value: Some((bal, val)), .. } => {
-                            accepted.push((slot, bal, val));
-                        }
-                        PrepareResponse::Reject { proposed, preempted } => {
-                            // found a slot that accepted a higher ballot, send the reject
-                            self.sender.send_to(bal.1, |c| c.reject(node_id, proposed, preempted));
-                            return;
-                        }
-                        _ => {}
-                    }
-                }
-                SlotMutRef::Resolved(bal, val) => {
-                    // TODO: is this the right thing to do here?????
-                    accepted.push((slot, bal, val));
-                }
-
-                SlotMutRef::Empty(_) => {
-                    warn!("Empty slot {} detected in the middle of the open range", slot);
-                }
-                SlotMutRef::ResolutionTruncated => {
-                    unreachable!("Cannot be resolved in the middle of the open range")
-                }
+    /// Persists an accepted value before it is sent to a peer, returning
+    /// false if the value cannot be made durable.
+    fn persist_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> bool {
+        match self.storage.store_accepted(slot, bal, val) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Error persisting accepted value for slot {} with {:?}: {}", slot, bal, e);
+                false
             }
         }
-        self.sender.send_to(bal.1, move |c| c.promise(node_id, bal, accepted));
     }
 
-    fn promise(&mut self, node: NodeId, bal: Ballot, accepted: Vec<SlottedValue>) {
-        if self.proposer.status() != ProposerStatus::Candidate {
+    /// Assigns queued proposals to new slots and sends ACCEPT messages, as long
+    /// as the slots are within α of the executed slots.
+    fn propose_queued(&mut self) {
+        if self.proposer.status() != ProposerStatus::Leader {
+            return;
+        }
+        if self.leader_catchup.is_some() {
+            // slots are proposed once the leader has caught up
+            self.drive_accept();
             return;
         }
 
-        self.proposer.receive_promise(node, bal);
-
-        // track highest proposals
-        for (slot, bal, val) in accepted.into_iter() {
-            match self.window.slot_mut(slot) {
-                SlotMutRef::Open(ref mut open_slot) => {
-                    open_slot.acceptor().notice_value(bal, val);
-                }
-                SlotMutRef::Empty(empty_slot) => {
-                    empty_slot.fill().acceptor().notice_value(bal, val);
-                }
-                _ => {}
+        let bal = self.proposer.highest_observed_ballot().unwrap();
+        let mut resolved = false;
+        while self.window.peek_next_slot() < self.proposal_limit() {
+            let val = match self.next_value() {
+                Some(val) => val,
+                None => break,
+            };
+            let slot = {
+                let mut slot_ref = self.window.next_slot();
+                slot_ref.acceptor().notice_value(bal, val.clone());
+                slot_ref.slot()
+            };
+            self.apply_reconfigurations(slot);
+            if !self.persist_accepted(slot, bal, val.clone()) {
+                continue;
             }
+            self.lease_accept_sent(slot, bal);
+            self.broadcast_quorum(Phase::Two, |c| c.accept(slot, bal, val.clone()));
+            resolved |= self.accepted_own(slot, bal);
         }
 
-        // if we have phase 1 quorum, we can send out ACCEPT messages
-        self.drive_accept();
+        if resolved {
+            self.execute_decisions();
+        }
     }
 
-    fn accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.proposer.observe_ballot(bal);
-
-        let current_node = self.config.current();
-        let acceptor_res = match self.window.slot_mut(slot) {
-            SlotMutRef::Empty(empty_slot) => {
-                let mut open_slot = empty_slot.fill();
-                open_slot.acceptor().receive_accept(bal, val)
-            }
-            SlotMutRef::Open(ref mut open_slot) => open_slot.acceptor().receive_accept(bal, val),
-            _ => return,
+    /// Takes the next value to propose from the queue. With batching enabled,
+    /// queued values are proposed as a batch once the batch is full or the
+    /// oldest value has waited for the batching window.
+    fn next_value(&mut self) -> Option<Bytes> {
+        let limits = match self.config.batching() {
+            Some(limits) => limits,
+            None => return self.proposal_queue.pop_front(),
         };
+        let oldest = self.proposal_queue.oldest()?;
+        if !self.proposal_queue.is_batch_full(limits.count, limits.bytes)
+            && self.ticks - oldest < limits.ticks
+        {
+            return None;
+        }
 
-        match acceptor_res {
-            AcceptResponse::Accepted { .. } => {
-                // TODO: what do we do w/ the preempted proposal
-                self.sender.send_to(bal.1, |c| c.accepted(current_node, slot, bal));
-            }
-            AcceptResponse::Reject { proposed, preempted } => {
-                self.sender.send_to(bal.1, |c| c.reject(current_node, proposed, preempted));
-            }
-            _ => {}
+        let mut batch = self.proposal_queue.pop_batch(limits.count, limits.bytes);
+        if batch.len() == 1 {
+            return batch.pop();
         }
+        trace!("Proposing a batch of {} values", batch.len());
+        Some(Entry::Batch(batch).encode())
     }
 
-    fn reject(&mut self, node: NodeId, proposed: Ballot, promised: Ballot) {
-        // reject it within the proposer
-        self.proposer.receive_reject(node, proposed, promised);
-        self.forward();
+    /// Slot (exclusive) up to which the leader may propose values. Slots are
+    /// bounded to α beyond the executed slots such that reconfigurations
+    /// are known before the slots in which they take effect are proposed,
+    /// and to the maximum number of slots in flight beyond the first
+    /// unresolved slot.
+    fn proposal_limit(&self) -> Slot {
+        let limit =
+            self.window.executed_through().map(|s| s + 1).unwrap_or(0) + self.config.alpha();
+        match self.config.max_in_flight() {
+            Some(max) => cmp::min(limit, self.window.open_range().start + max),
+            None => limit,
+        }
     }
 
-    fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
-        self.proposer.observe_ballot(bal);
+    /// Schedules a reconfiguration decided at a slot, which takes effect α
+    /// slots later.
+    fn schedule_reconfiguration(&mut self, slot: Slot, change: Reconfiguration) {
+        self.restore_reconfigurations(vec![(slot + self.config.alpha(), change)]);
+    }
 
-        let resolution = match self.window.slot_mut(slot) {
-            SlotMutRef::Open(ref mut open_ref) => {
-                open_ref.acceptor().receive_accepted(node, bal);
-                open_ref.acceptor().resolution()
+    /// Schedules reconfigurations that take effect at the given slots,
+    /// ignoring reconfigurations that are already known.
+    fn restore_reconfigurations(&mut self, reconfigurations: Vec<(Slot, Reconfiguration)>) {
+        for (effective, change) in reconfigurations {
+            if self.reconfigurations.last().map(|r| r.0 >= effective).unwrap_or(false) {
+                continue;
             }
-            SlotMutRef::Empty(_) => {
-                warn!("Received accepted() for slot {} which is unknown", slot);
-                return;
+
+            let mut config = self.config.clone();
+            let pending = &self.reconfigurations[self.applied_reconfigurations..];
+            let reconfigured = pending
+                .iter()
+                .map(|(_, pending)| pending)
+                .chain(Some(&change))
+                .try_for_each(|change| config.reconfigure(change));
+            if let Err(e) = reconfigured {
+                warn!("Ignoring reconfiguration {:?}: {}", change, e);
+                continue;
             }
-            _ => return,
-        };
 
-        if let Some((bal, val)) = resolution {
-            self.broadcast(|c| c.resolution(slot, bal, val.clone()));
+            debug!("Scheduling reconfiguration {:?} at slot {}", change, effective);
+            self.window.reconfigure(effective, config.quorum_system());
+            self.reconfigurations.push((effective, change));
         }
+    }
 
-        // execute resolved decisions
-        self.execute_decisions();
+    /// Checks that a change can be applied to the configuration following
+    /// the scheduled reconfigurations.
+    fn check_reconfiguration(&self, change: &Reconfiguration) -> Result<(), ConfigError> {
+        let mut config = self.config.clone();
+        self.reconfigurations[self.applied_reconfigurations..]
+            .iter()
+            .map(|(_, pending)| pending)
+            .chain(Some(change))
+            .try_for_each(|change| config.reconfigure(change))
     }
 
-    fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
-        self.proposer.observe_ballot(bal);
+    /// Applies the scheduled reconfigurations that take effect at or before
+    /// a slot to the configuration.
+    fn apply_reconfigurations(&mut self, slot: Slot) {
+        while let Some(&(effective, change)) =
+            self.reconfigurations.get(self.applied_reconfigurations)
+        {
+            if effective > slot {
+                return;
+            }
 
-        // resolve the slot
-        match self.window.slot_mut(slot) {
-            SlotMutRef::Empty(empty_slot) => empty_slot.fill().acceptor().resolve(bal, val),
-            SlotMutRef::Open(ref mut open) => open.acceptor().resolve(bal, val),
+            debug!("Applying reconfiguration {:?} at slot {}", change, effective);
+            self.applied_reconfigurations += 1;
+            if let Err(e) = self.config.reconfigure(&change) {
+                warn!("Ignoring reconfiguration {:?}: {}", change, e);
+            }
+            self.proposer.reconfigure(self.config.quorum_system());
+            if !self.config.is_member() {
+                self.proposer.step_down();
+            }
+        }
+    }
+
+    /// Forwards pending proposals to the new leader
+    fn forward_proposals(&mut self) {
+        if self.proposer.status() != ProposerStatus::Follower || self.proposal_queue.is_empty() {
+            return;
+        }
+
+        if let Some(Ballot(_, node)) = self.proposer.highest_observed_ballot() {
+            let proposals = self.proposal_queue.take();
+            self.send_to(node, move |c| {
+                for proposal in proposals.into_iter() {
+                    c.forward(proposal);
+                }
+            });
+        }
+    }
+
+    /// Applies a value to the state machine, unless the value is empty or
+    /// the state machine has already applied the slot
+    fn execute(&mut self, slot: Slot, val: Bytes, applied: bool) {
+        if !val.is_empty() && !applied {
+            self.sender.state_machine().execute(slot, val);
+        }
+    }
+
+    /// Executes a decided entry of the log. Entries of a batch share the
+    /// slot, so whether the slot has been applied is checked once.
+    fn execute_entry(&mut self, slot: Slot, entry: Entry, applied: bool) {
+        match entry {
+            Entry::Value(val) => self.execute(slot, val, applied),
+            Entry::Proposal(node, id, val) => {
+                self.execute(slot, val, applied);
+                if node == self.config.current() && self.pending_proposals.remove(&id).is_some() {
+                    self.sender.state_machine().proposal_decided(id, slot);
+                }
+            }
+            Entry::Reconfiguration(change) => self.schedule_reconfiguration(slot, change),
+            Entry::Batch(entries) => {
+                for entry in entries {
+                    self.execute_entry(slot, Entry::decode(entry), applied);
+                }
+            }
+        }
+    }
+
+    /// Executes commands that have been decided.
+    fn execute_decisions(&mut self) {
+        let decisions = self.window.drain_decisions().collect::<Vec<(Slot, Bytes)>>();
+        for (slot, val) in decisions {
+            let applied = self.sender.state_machine().applied().map(|s| slot <= s).unwrap_or(false);
+            self.execute_entry(slot, Entry::decode(val), applied);
+        }
+        if let Some(executed) = self.window.executed_through() {
+            self.apply_reconfigurations(executed + 1);
+        }
+        self.snapshot();
+        self.complete_reads();
+
+        // proposals held back by α may now be proposed
+        self.propose_queued();
+    }
+
+    /// Takes a snapshot of the state machine at a slot, along with the
+    /// executed reconfigurations.
+    fn snapshot_at(&mut self, slot: Slot) -> Option<Bytes> {
+        let snapshot = self.sender.state_machine().snapshot(slot)?;
+        Some(entry::encode_snapshot(&self.reconfigurations, snapshot))
+    }
+
+    /// Takes a snapshot of the state machine once the snapshot interval has
+    /// elapsed, and compacts the log covered by the snapshot.
+    fn snapshot(&mut self) {
+        let (interval, executed) =
+            match (self.config.snapshot_interval(), self.window.executed_through()) {
+                (Some(interval), Some(executed)) => (interval, executed),
+                _ => return,
+            };
+
+        let next_snapshot = self.last_snapshot.map(|s| s + interval).unwrap_or(interval - 1);
+        if executed < next_snapshot {
+            return;
+        }
+
+        let snapshot = match self.snapshot_at(executed) {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        // the snapshot must be durable before the log is compacted
+        if let Err(e) = self.storage.store_snapshot(executed, snapshot) {
+            error!("Error persisting snapshot at slot {}: {}", executed, e);
+            return;
+        }
+
+        debug!("Took snapshot at slot {}", executed);
+        self.last_snapshot = Some(executed);
+        self.window.compact(executed);
+    }
+
+    /// Requests the resolutions of unresolved slots that precede resolved
+    /// slots in the open window, which block execution of the later slots.
+    /// If the peer has compacted the slots, it responds with its snapshot.
+    fn request_missing(&mut self, node: NodeId) {
+        let current = self.config.current();
+        let missing = match self.window.missing() {
+            Some(missing) => missing,
+            None => return,
+        };
+        if node == current || self.catchup_requested == Some(missing.start) {
+            return;
+        }
+
+        debug!("Requesting missing resolutions for slots {:?} from node {}", missing, node);
+        self.catchup_requested = Some(missing.start);
+        self.send_to(node, |c| c.catchup(current, missing.start, missing.end));
+    }
+
+    /// Sends the decisions within a range of slots to a peer as RESOLUTION
+    /// messages. If the start of the range has been compacted, the stored
+    /// snapshot that covers the compacted slots precedes the decisions.
+    fn send_decisions(&mut self, node: NodeId, slots: Range<Slot>) {
+        let mut start = slots.start;
+        if matches!(self.window.slot_mut(start), SlotMutRef::ResolutionTruncated) {
+            // slots are only compacted once a snapshot covering them is stored
+            let (slot, snapshot) = match self.storage.load_snapshot() {
+                Ok(Some(snapshot)) => snapshot,
+                Ok(None) => {
+                    warn!("Unable to send snapshot to node {} for slot {}", node, start);
+                    return;
+                }
+                Err(e) => {
+                    error!("Error loading snapshot for node {}: {}", node, e);
+                    return;
+                }
+            };
+            self.send_to(node, |c| c.install_snapshot(slot, snapshot));
+            start = slot + 1;
+        }
+
+        let end = slots.end.min(self.window.open_range().end);
+        let decisions = (start..end)
+            .filter_map(|slot| match self.window.slot_mut(slot) {
+                SlotMutRef::Resolved(bal, val) => Some((slot, bal, val)),
+                _ => None,
+            })
+            .collect::<Vec<SlottedValue>>();
+        if !decisions.is_empty() {
+            self.send_to(node, move |c| {
+                for (slot, bal, val) in decisions.into_iter() {
+                    c.resolution(slot, bal, val);
+                }
+            });
+        }
+    }
+
+    /// Persists the resolution of a slot. Resolutions are only used during
+    /// recovery, so failures are not fatal to the replica.
+    fn persist_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        if let Err(e) = self.storage.store_resolution(slot, bal, val) {
+            error!("Error persisting resolution for slot {}: {}", slot, e);
+        }
+    }
+
+    /// Notes the acceptance of a slot by the leader itself, returning whether
+    /// the slot has been resolved. Like an ACCEPTED sent to a peer, the
+    /// acceptance counts once the accepted value is durable.
+    fn accepted_own(&mut self, slot: Slot, bal: Ballot) -> bool {
+        let current = self.config.current();
+        if self.held.is_empty() && self.storage.is_durable() {
+            self.receive_accepted(current, slot, bal)
+        } else {
+            self.held.push((current, Message::Accepted(current, slot, bal)));
+            false
+        }
+    }
+
+    /// Sends a message to a node. While writes of the storage are not yet
+    /// durable, the message is held until a flush of the storage, such that
+    /// no PROMISE or ACCEPTED leaves before the state it acknowledges.
+    fn send_to<F>(&mut self, node: NodeId, f: F)
+    where
+        F: FnOnce(&mut dyn Commander),
+    {
+        if self.held.is_empty() && self.storage.is_durable() {
+            self.sender.send_to(node, |c| f(c));
+        } else {
+            let mut messages = Vec::new();
+            f(&mut messages);
+            self.held.extend(messages.into_iter().map(|m| (node, m)));
+        }
+    }
+
+    /// Sends the held messages once the writes of the storage are durable
+    fn release_held(&mut self) {
+        if self.held.is_empty() || !self.storage.is_durable() {
+            return;
+        }
+
+        let current = self.config.current();
+        let mut resolved = false;
+        for (node, message) in mem::take(&mut self.held) {
+            match message {
+                Message::Accepted(_, slot, bal) if node == current => {
+                    resolved |= self.receive_accepted(current, slot, bal);
+                }
+                message => self.sender.send_to(node, |c| message.dispatch(c)),
+            }
+        }
+        if resolved {
+            self.execute_decisions();
+        }
+    }
+
+    fn broadcast<F>(&mut self, f: F)
+    where
+        F: Fn(&mut dyn Commander),
+    {
+        let peers = self.config.peers().into_iter().collect::<Vec<_>>();
+        for node in peers {
+            self.send_to(node, &f);
+        }
+    }
+
+    /// Sends a message to the peers as well as the learners
+    fn broadcast_all<F>(&mut self, f: F)
+    where
+        F: Fn(&mut dyn Commander),
+    {
+        self.broadcast(&f);
+        let learners = self.config.learners().collect::<Vec<_>>();
+        for node in learners {
+            self.send_to(node, &f);
+        }
+    }
+
+    /// Sends a Phase 1 or Phase 2 message to the peers. In thrifty mode, only
+    /// the peers required for quorum are sent the message.
+    fn broadcast_quorum<F>(&mut self, phase: Phase, f: F)
+    where
+        F: Fn(&mut dyn Commander),
+    {
+        if !self.config.thrifty() {
+            self.broadcast(f);
+            return;
+        }
+
+        for node in self.quorum_peers(phase) {
+            self.send_to(node, &f);
+        }
+    }
+
+    /// Selects peers that form a quorum along with the current node,
+    /// preferring the peers that have responded most recently.
+    fn quorum_peers(&self, phase: Phase) -> Vec<NodeId> {
+        let mut peers = self.config.peers().into_iter().collect::<Vec<NodeId>>();
+        peers.sort_by_key(|node| (Reverse(self.responses.get(node).cloned()), *node));
+
+        let quorum = self.config.quorum_system();
+        let mut nodes = vec![self.config.current()];
+        for (i, node) in peers.iter().enumerate() {
+            let has_quorum = match phase {
+                Phase::One => quorum.is_phase1_quorum(&nodes),
+                Phase::Two => quorum.is_phase2_quorum(&nodes),
+            };
+            if has_quorum {
+                peers.truncate(i);
+                break;
+            }
+            if let Err(loc) = nodes.binary_search(node) {
+                nodes.insert(loc, *node);
+            }
+        }
+        peers
+    }
+
+    /// Submits an entry of the log to the distinguished proposer, queueing
+    /// the entry while there is no leader or the leader is limited by α.
+    /// Values from clients are encoded as entries by the caller, such that
+    /// control entries are only created by the replica.
+    fn submit(&mut self, val: Bytes) -> Result<(), ProposeError> {
+        // redirect to the distinguished proposer or start PREPARE
+        let current = self.config.current();
+        match self.proposer.status() {
+            ProposerStatus::Follower
+                if self
+                    .proposer
+                    .highest_observed_ballot()
+                    .map(|b| b.1 == current)
+                    .unwrap_or(true) =>
+            {
+                // no known proposers (or the ballot is from this node prior
+                // to a restart), go through prepare cycle
+                self.enqueue(val)?;
+                if self.config.is_member() {
+                    self.start_election();
+                }
+            }
+            ProposerStatus::Follower => {
+                self.send_to(self.proposer.highest_observed_ballot().unwrap().1, |c| {
+                    c.forward(val)
+                });
+            }
+            ProposerStatus::Candidate => {
+                // still waiting for promises, queue up the value (PREPARE
+                // messages are re-sent on tick)
+                self.enqueue(val)?;
+            }
+            ProposerStatus::Leader => {
+                // node is the distinguished proposer, the value is queued
+                // until the slot is within α of the executed slots
+                self.enqueue(val)?;
+                self.propose_queued();
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a value to the proposal queue, unless the queue is full
+    fn enqueue(&mut self, val: Bytes) -> Result<(), ProposeError> {
+        if !self.proposal_queue.has_capacity(&val) {
+            return Err(ProposeError::WouldBlock);
+        }
+        self.proposal_queue.push_back(val, self.ticks);
+        Ok(())
+    }
+
+    /// Notes a response from a peer, used to prefer responsive peers
+    /// Notes an ACCEPTED for a slot, broadcasting the decision once the
+    /// slot has Phase 2 quorum. Returns true if the slot has been resolved.
+    fn receive_accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) -> bool {
+        let resolution = match self.window.slot_mut(slot) {
+            SlotMutRef::Open(ref mut open_ref) => {
+                if let Some(ref mut lease) = self.lease {
+                    lease.accepted(node, slot, bal);
+                }
+                open_ref.acceptor().receive_accepted(node, bal);
+                open_ref.acceptor().resolution()
+            }
+            SlotMutRef::Empty(_) => {
+                warn!("Received accepted() for slot {} which is unknown", slot);
+                return false;
+            }
+            _ => return false,
+        };
+
+        match resolution {
+            Some((bal, val)) => {
+                if let Some(ref mut lease) = self.lease {
+                    lease.resolved(slot);
+                }
+                self.persist_resolution(slot, bal, val.clone());
+                self.broadcast_all(|c| c.resolution(slot, bal, val.clone()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn responded(&mut self, node: NodeId) {
+        self.responses.insert(node, self.ticks);
+    }
+
+    /// Flushes the messages buffered by the sender once an event has been
+    /// handled (see `Sender::flush`), along with the held messages if the
+    /// writes of the storage have since become durable
+    fn flush(&mut self) {
+        if !self.batching {
+            self.release_held();
+            self.sender.flush();
+        }
+    }
+}
+
+/// Handlers of the messages received by the replica (see `Commander`)
+impl<S: Sender, T: Storage> Replica<S, T> {
+    fn handle_proposal(&mut self, val: Bytes) {
+        // values that collide with control entries are escaped
+        if let Err(e) = self.submit(Entry::Value(val).encode()) {
+            warn!("Dropping proposal: {}", e);
+        }
+    }
+
+    fn handle_forward(&mut self, entry: Bytes) {
+        // forwarded entries are checked as if proposed at this node
+        let checked = match Entry::decode(entry.clone()) {
+            Entry::Reconfiguration(change) => {
+                self.check_reconfiguration(&change).map_err(ProposeError::InvalidReconfiguration)
+            }
+            Entry::Batch(_) => {
+                // batches are only formed by the leader
+                warn!("Dropping forwarded batch");
+                return;
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = checked.and_then(|()| self.submit(entry)) {
+            warn!("Dropping forwarded proposal: {}", e);
+        }
+    }
+
+    fn handle_prepare(&mut self, bal: Ballot) {
+        if self.lease.as_ref().map(|l| l.is_held_by_other(bal.1, self.ticks)).unwrap_or(false) {
+            trace!("Ignoring PREPARE for {:?} while the read lease is held", bal);
+            return;
+        }
+
+        self.proposer.observe_ballot(bal);
+        if !self.config.is_member() {
+            trace!("Ignoring PREPARE for {:?} as a non-member", bal);
+            return;
+        }
+
+        let node_id = self.config.current();
+
+        let mut accepted = Vec::new();
+        let mut rejected = None;
+        for slot in self.window.open_range() {
+            match self.window.slot_mut(slot) {
+                SlotMutRef::Open(ref mut open_ref) => {
+                    match open_ref.acceptor().receive_prepare(bal) {
+                        PrepareResponse::Promise { value: Some((bal, val)), .. } => {
+                            accepted.push((slot, bal, val));
+                        }
+                        PrepareResponse::Reject { proposed, preempted } => {
+                            // found a slot that accepted a higher ballot
+                            rejected = Some((proposed, preempted));
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+                SlotMutRef::Resolved(bal, val) => {
+                    // TODO: is this the right thing to do here?????
+                    accepted.push((slot, bal, val));
+                }
+
+                SlotMutRef::Empty(_) => {
+                    warn!("Empty slot {} detected in the middle of the open range", slot);
+                }
+                SlotMutRef::ResolutionTruncated => {
+                    unreachable!("Cannot be resolved in the middle of the open range")
+                }
+            }
+        }
+        if let Some((proposed, preempted)) = rejected {
+            self.send_to(bal.1, |c| c.reject(node_id, proposed, preempted));
+            return;
+        }
+
+        if !self.persist_promise(bal) {
+            return;
+        }
+        if let Some(ref mut lease) = self.lease {
+            lease.grant(bal, self.ticks);
+        }
+        self.leader_contact(bal);
+        let open = self.window.open_range().start;
+        self.send_to(bal.1, move |c| c.promise(node_id, bal, open, accepted));
+    }
+
+    fn handle_promise(
+        &mut self,
+        node: NodeId,
+        bal: Ballot,
+        open: Slot,
+        accepted: Vec<SlottedValue>,
+    ) {
+        self.responded(node);
+        if self.proposer.status() != ProposerStatus::Candidate {
+            return;
+        }
+
+        self.proposer.receive_promise(node, bal);
+        if let Some(ref mut lease) = self.lease {
+            lease.promised(node, bal);
+        }
+
+        // slots decided by the peer are not included in the promise, so the
+        // decisions are learned before the slots are proposed
+        if open > self.leader_catchup.map(|(_, slot)| slot).unwrap_or(0) {
+            self.leader_catchup = Some((node, open));
+            self.leader_start = cmp::max(self.leader_start, open);
+        }
+
+        // track highest proposals
+        for (slot, bal, val) in accepted.into_iter() {
+            match self.window.slot_mut(slot) {
+                SlotMutRef::Open(ref mut open_slot) => {
+                    open_slot.acceptor().notice_value(bal, val);
+                }
+                SlotMutRef::Empty(empty_slot) => {
+                    empty_slot.fill().acceptor().notice_value(bal, val);
+                }
+                _ => {}
+            }
+        }
+
+        // if we have phase 1 quorum, we can send out ACCEPT messages
+        self.drive_accept();
+    }
+
+    fn handle_accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.proposer.observe_ballot(bal);
+        if !self.config.is_member() {
+            trace!("Ignoring ACCEPT for slot {} as a non-member", slot);
+            return;
+        }
+
+        let current_node = self.config.current();
+        let acceptor_res = match self.window.slot_mut(slot) {
+            SlotMutRef::Empty(empty_slot) => match empty_slot.promised() {
+                // slots are not opened for ACCEPT messages that are rejected
+                Some(promised) if promised > bal => {
+                    Some(AcceptResponse::Reject { proposed: bal, preempted: promised })
+                }
+                _ => Some(empty_slot.fill().acceptor().receive_accept(bal, val.clone())),
+            },
+            SlotMutRef::Open(ref mut open_slot) => {
+                Some(open_slot.acceptor().receive_accept(bal, val.clone()))
+            }
+            _ => None,
+        };
+
+        let acceptor_res = match acceptor_res {
+            Some(acceptor_res) => acceptor_res,
+            None => {
+                // the slot is decided, which the leader has not yet learned
+                self.send_decisions(bal.1, slot..slot + 1);
+                return;
+            }
+        };
+
+        match acceptor_res {
+            AcceptResponse::Accepted { .. } => {
+                if !self.persist_accepted(slot, bal, val) {
+                    return;
+                }
+                if let Some(ref mut lease) = self.lease {
+                    lease.grant(bal, self.ticks);
+                }
+                // TODO: what do we do w/ the preempted proposal
+                self.leader_contact(bal);
+                self.send_to(bal.1, |c| c.accepted(current_node, slot, bal));
+            }
+            AcceptResponse::Reject { proposed, preempted } => {
+                self.send_to(bal.1, |c| c.reject(current_node, proposed, preempted));
+            }
             _ => {}
         }
+    }
+
+    fn handle_reject(&mut self, node: NodeId, proposed: Ballot, promised: Ballot) {
+        self.responded(node);
+        // reject it within the proposer
+        self.proposer.receive_reject(node, proposed, promised);
+        self.forward_proposals();
+    }
+
+    fn handle_accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
+        self.responded(node);
+        self.proposer.observe_ballot(bal);
+        self.receive_accepted(node, slot, bal);
+
+        // execute resolved decisions
+        self.execute_decisions();
+    }
+
+    fn handle_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.proposer.observe_ballot(bal);
+        self.leader_contact(bal);
+
+        // resolve the slot
+        let resolved = match self.window.slot_mut(slot) {
+            SlotMutRef::Empty(empty_slot) => {
+                empty_slot.fill().acceptor().resolve(bal, val.clone());
+                true
+            }
+            SlotMutRef::Open(ref mut open) => {
+                open.acceptor().resolve(bal, val.clone());
+                true
+            }
+            _ => false,
+        };
+        if resolved {
+            self.persist_resolution(slot, bal, val);
+        }
+
+        // execute resolved decisions
+        self.execute_decisions();
+        self.request_missing(bal.1);
+    }
+
+    fn handle_snapshot_request(&mut self, node: NodeId, slot: Slot) {
+        let end = self.window.open_range().end;
+        self.send_decisions(node, slot..end);
+    }
+
+    fn handle_catchup(&mut self, node: NodeId, start: Slot, end: Slot) {
+        self.send_decisions(node, start..end);
+    }
+
+    fn handle_install_snapshot(&mut self, slot: Slot, snapshot: Bytes) {
+        if self.window.executed_through().map(|s| s >= slot).unwrap_or(false) {
+            trace!("Ignoring snapshot at slot {} that has already been executed", slot);
+            return;
+        }
+
+        if let Err(e) = self.storage.store_snapshot(slot, snapshot.clone()) {
+            error!("Error persisting installed snapshot at slot {}: {}", slot, e);
+            return;
+        }
+
+        debug!("Installing snapshot at slot {}", slot);
+        let (reconfigurations, snapshot) = entry::decode_snapshot(snapshot);
+        self.sender.state_machine().restore(slot, snapshot);
+        self.restore_reconfigurations(reconfigurations);
+        self.window.advance_to(slot);
+        self.last_snapshot = Some(slot);
+        self.catchup_requested = None;
+
+        // execute decisions received after the snapshot
+        self.execute_decisions();
+    }
+
+    fn handle_heartbeat(&mut self, bal: Ballot, seq: u64) {
+        self.proposer.observe_ballot(bal);
+
+        let node = self.config.current();
+        let highest = self.proposer.highest_observed_ballot().unwrap();
+        if bal < highest {
+            // let the former leader know that it has been preempted
+            self.send_to(bal.1, |c| c.reject(node, bal, highest));
+            return;
+        }
+
+        self.leader_contact(bal);
+        self.forward_proposals();
+        if self.config.is_member() {
+            if let Some(ref mut lease) = self.lease {
+                lease.grant(bal, self.ticks);
+            }
+            self.send_to(bal.1, |c| c.heartbeat_ack(node, bal, seq));
+        }
+    }
+
+    fn handle_heartbeat_ack(&mut self, node: NodeId, bal: Ballot, seq: u64) {
+        self.responded(node);
+        if self.proposer.status() != ProposerStatus::Leader
+            || self.proposer.highest_observed_ballot() != Some(bal)
+        {
+            return;
+        }
+
+        if let Some(ref mut lease) = self.lease {
+            lease.heartbeat_acked(node, bal, seq);
+        }
+        let acked = self.heartbeat_acks.entry(node).or_insert(seq);
+        *acked = cmp::max(*acked, seq);
+        self.complete_reads();
+    }
+}
+
+impl<S: Sender, T: Storage> Commander for Replica<S, T> {
+    fn proposal(&mut self, val: Bytes) {
+        self.handle_proposal(val);
+        self.flush();
+    }
+
+    fn forward(&mut self, entry: Bytes) {
+        self.handle_forward(entry);
+        self.flush();
+    }
+
+    fn prepare(&mut self, bal: Ballot) {
+        self.handle_prepare(bal);
+        self.flush();
+    }
+
+    fn promise(&mut self, node: NodeId, bal: Ballot, open: Slot, accepted: Vec<SlottedValue>) {
+        self.handle_promise(node, bal, open, accepted);
+        self.flush();
+    }
+
+    fn accept(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.handle_accept(slot, bal, val);
+        self.flush();
+    }
+
+    fn reject(&mut self, node: NodeId, proposed: Ballot, promised: Ballot) {
+        self.handle_reject(node, proposed, promised);
+        self.flush();
+    }
+
+    fn accepted(&mut self, node: NodeId, slot: Slot, bal: Ballot) {
+        self.handle_accepted(node, slot, bal);
+        self.flush();
+    }
+
+    fn resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) {
+        self.handle_resolution(slot, bal, val);
+        self.flush();
+    }
+
+    fn snapshot_request(&mut self, node: NodeId, slot: Slot) {
+        self.handle_snapshot_request(node, slot);
+        self.flush();
+    }
+
+    fn catchup(&mut self, node: NodeId, start: Slot, end: Slot) {
+        self.handle_catchup(node, start, end);
+        self.flush();
+    }
+
+    fn install_snapshot(&mut self, slot: Slot, snapshot: Bytes) {
+        self.handle_install_snapshot(slot, snapshot);
+        self.flush();
+    }
+
+    fn heartbeat(&mut self, bal: Ballot, seq: u64) {
+        self.handle_heartbeat(bal, seq);
+        self.flush();
+    }
+
+    fn heartbeat_ack(&mut self, node: NodeId, bal: Ballot, seq: u64) {
+        self.handle_heartbeat_ack(node, bal, seq);
+        self.flush();
+    }
+
+    fn batch(&mut self, messages: Vec<Message>) {
+        // the messages of a batch are handled as a single event
+        self.batching = true;
+        for message in messages {
+            message.dispatch(self);
+        }
+        self.batching = false;
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BatchSender, GridQuorum, PersistedState, ReplicatedState};
+    use lazy_static::lazy_static;
+    use std::{io, iter, ops::Index};
+
+    lazy_static! {
+        static ref CONFIG: Configuration = Configuration::new(
+            4u32,
+            vec![
+                (0, "127.0.0.1:4000".parse().unwrap()),
+                (1, "127.0.0.1:4001".parse().unwrap()),
+                (2, "127.0.0.1:4002".parse().unwrap()),
+                (3, "127.0.0.1:4003".parse().unwrap()),
+            ]
+            .into_iter(),
+        );
+    }
+
+    #[test]
+    fn replica_proposal() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+
+        // sent with no existing proposal, kickstarts phase 1
+        replica.proposal("123".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[0]);
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[1]);
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[2]);
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[3]);
+        replica.sender.clear();
+
+        replica.proposal("456".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        assert!(replica.sender[0].is_empty());
+        assert!(replica.sender[1].is_empty());
+        assert!(replica.sender[2].is_empty());
+        assert!(replica.sender[3].is_empty());
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_proposal_redirection() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.prepare(Ballot(0, 3));
+        assert_eq!(Some(Ballot(0, 3)), replica.proposer.highest_observed_ballot());
+        replica.sender.clear();
+
+        replica.proposal("123".into());
+        assert!(replica.sender[0].is_empty());
+        assert!(replica.sender[1].is_empty());
+        assert!(replica.sender[2].is_empty());
+        assert_eq!(&[Message::Forward("123".into())], &replica.sender[3]);
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_prepare() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+
+        replica.prepare(Ballot(1, 0));
+        assert_eq!(Some(Ballot(1, 0)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[Message::Promise(4, Ballot(1, 0), 0, Vec::new())], &replica.sender[0]);
+        assert!(&replica.sender[1].is_empty());
+        assert!(&replica.sender[2].is_empty());
+        assert!(&replica.sender[3].is_empty());
+        replica.sender.clear();
+
+        replica.prepare(Ballot(0, 2));
+        assert_eq!(Some(Ballot(1, 0)), replica.proposer.highest_observed_ballot());
+        assert!(&replica.sender[0].is_empty());
+        assert!(&replica.sender[1].is_empty());
+        assert_eq!(&[Message::Reject(4, Ballot(0, 2), Ballot(1, 0))], &replica.sender[2]);
+        assert!(&replica.sender[3].is_empty());
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_promise_without_existing_accepted_value() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        replica.sender.clear();
+
+        // replica needs 2 more promises to achieve Phase 1 Quorum
+        replica.promise(0, Ballot(0, 4), 0, Vec::new());
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        replica.promise(2, Ballot(0, 4), 0, Vec::new());
+
+        (0..4).for_each(|i| {
+            assert_eq!(&[Message::Accept(0, Ballot(0, 4), "123".into())], &replica.sender[i])
+        });
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_promise_with_existing_accepted_value() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        replica.sender.clear();
+
+        // replica needs 2 more promises to achieve Phase 1 Quorum
+        replica.promise(1, Ballot(0, 4), 0, vec![(0, Ballot(0, 0), "456".into())]);
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+
+        (0..4).for_each(|i| {
+            assert_eq!(
+                &[
+                    Message::Accept(0, Ballot(0, 4), "456".into()),
+                    Message::Accept(1, Ballot(0, 4), "123".into())
+                ],
+                &replica.sender[i]
+            )
+        });
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_promise_with_slot_holes() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        replica.sender.clear();
+
+        // replica needs 2 more promises to achieve Phase 1 Quorum
+        replica.promise(1, Ballot(0, 4), 0, vec![(2, Ballot(0, 0), "456".into())]);
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+
+        (0..4).for_each(|i| {
+            assert_eq!(
+                &[
+                    Message::Accept(0, Ballot(0, 4), Bytes::default()),
+                    Message::Accept(1, Ballot(0, 4), Bytes::default()),
+                    Message::Accept(2, Ballot(0, 4), "456".into()),
+                    Message::Accept(3, Ballot(0, 4), "123".into())
+                ],
+                &replica.sender[i]
+            );
+        });
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_promise_from_peer_ahead() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
+        replica.sender.clear();
+
+        // node 1 has decided slots 0 and 1, which are not in its promise
+        replica.promise(1, Ballot(0, 4), 2, vec![(2, Ballot(0, 0), "456".into())]);
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
+        assert_eq!(&[Message::Catchup(4, 0, 2)], &replica.sender[1]);
+        (0..4).filter(|i| *i != 1).for_each(|i| assert!(replica.sender[i].is_empty()));
+        replica.sender.clear();
+
+        // slots are proposed once the decisions have been learned
+        replica.resolution(0, Ballot(0, 0), "000".into());
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+        replica.resolution(1, Ballot(0, 0), "111".into());
+        (0..4).for_each(|i| {
+            assert_eq!(
+                &[
+                    Message::Accept(2, Ballot(0, 4), "456".into()),
+                    Message::Accept(3, Ballot(0, 4), "123".into())
+                ],
+                &replica.sender[i]
+            );
+        });
+        assert_eq!(&[(0, "000".into()), (1, "111".into())], replica.sender.resolutions());
+    }
 
-        // execute resolved decisions
-        self.execute_decisions();
+    #[test]
+    fn replica_accept() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.prepare(Ballot(8, 2));
+        assert_eq!(Some(Ballot(8, 2)), replica.proposer.highest_observed_ballot());
+        replica.sender.clear();
+
+        // test rejection first for bal < proposer.highest_observed_ballot
+        replica.accept(0, Ballot(1, 1), "123".into());
+        assert_eq!(&[Message::Reject(4, Ballot(1, 1), Ballot(8, 2))], &replica.sender[1]);
+        replica.sender.clear();
+
+        // test replying with accepted message when bal =
+        // proposer.highest_observed_ballot
+        replica.accept(0, Ballot(8, 2), "456".into());
+        assert_eq!(Some(Ballot(8, 2)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[Message::Accepted(4, 0, Ballot(8, 2))], &replica.sender[2]);
+        replica.sender.clear();
+
+        // test replying with accepted message when bal >
+        // proposer.highest_observed_ballot
+        replica.accept(0, Ballot(9, 2), "789".into());
+        assert_eq!(Some(Ballot(9, 2)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[Message::Accepted(4, 0, Ballot(9, 2))], &replica.sender[2]);
+
+        assert!(replica.sender.resolutions().is_empty());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::ReplicatedState;
-    use lazy_static::lazy_static;
-    use std::ops::Index;
+    #[test]
+    fn replica_accept_stale_ballot() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.prepare(Ballot(8, 2));
+        replica.sender.clear();
 
-    lazy_static! {
-        static ref CONFIG: Configuration = Configuration::new(
-            4u32,
-            vec![
-                (0, "127.0.0.1:4000".parse().unwrap()),
-                (1, "127.0.0.1:4001".parse().unwrap()),
-                (2, "127.0.0.1:4002".parse().unwrap()),
-                (3, "127.0.0.1:4003".parse().unwrap()),
-            ]
-            .into_iter(),
-        );
+        // rejected ACCEPT messages do not open slots
+        replica.accept(5, Ballot(1, 1), "123".into());
+        assert_eq!(&[Message::Reject(4, Ballot(1, 1), Ballot(8, 2))], &replica.sender[1]);
+        assert_eq!((0..1), replica.window.open_range());
     }
 
     #[test]
-    fn replica_proposal() {
+    fn replica_accept_decided() {
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        replica.sender.clear();
 
-        // sent with no existing proposal, kickstarts phase 1
+        // a leader that has not learned the decision receives the resolution
+        replica.accept(0, Ballot(2, 3), "123".into());
+        assert_eq!(&[Message::Resolution(0, Ballot(1, 2), "000".into())], &replica.sender[3]);
+        (0..3).for_each(|i| assert!(replica.sender[i].is_empty()));
+    }
+
+    #[test]
+    fn replica_reject() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
         replica.proposal("123".into());
         assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
-        assert_eq!(&[Command::Prepare(Ballot(0, 4))], &replica.sender[0]);
-        assert_eq!(&[Command::Prepare(Ballot(0, 4))], &replica.sender[1]);
-        assert_eq!(&[Command::Prepare(Ballot(0, 4))], &replica.sender[2]);
-        assert_eq!(&[Command::Prepare(Ballot(0, 4))], &replica.sender[3]);
         replica.sender.clear();
 
-        replica.proposal("456".into());
+        replica.reject(2, Ballot(0, 4), Ballot(5, 3));
+        assert_eq!(Some(Ballot(5, 3)), replica.proposer.highest_observed_ballot());
+        assert_eq!(ProposerStatus::Follower, replica.proposer.status());
+        assert_eq!(&[Message::Forward("123".into())], &replica.sender[3]);
+        (0..3).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        assert!(replica.sender.resolutions().is_empty());
+    }
+
+    #[test]
+    fn replica_accepted() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
         assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        replica.sender.clear();
+
+        // wait for phase 2 quorum (accepted) before sending resolution
+        replica.accepted(0, 0, Ballot(0, 4));
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        replica.accepted(2, 0, Ballot(0, 4));
+        (0..4).for_each(|i| {
+            assert_eq!(&[Message::Resolution(0, Ballot(0, 4), "123".into())], &replica.sender[i])
+        });
+
+        assert_eq!(&[(0, "123".into())], replica.sender.resolutions());
+    }
+
+    #[test]
+    fn replica_resolution() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+
+        replica.resolution(4, Ballot(1, 2), "123".into());
+        assert_eq!((0..5), replica.window.open_range());
+        assert!(match replica.window.slot_mut(4) {
+            SlotMutRef::Resolved(Ballot(1, 2), val) if val == "123" => true,
+            _ => false,
+        });
+
+        replica.resolution(1, Ballot(1, 2), Bytes::default());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        assert_eq!(&[(0, "000".into())], replica.sender.resolutions());
+
+        // fill hole 1,2
+        replica.resolution(1, Ballot(1, 2), Bytes::default());
+        replica.resolution(2, Ballot(1, 2), Bytes::default());
+        replica.resolution(3, Ballot(1, 2), "3".into());
+
+        assert_eq!(
+            &[(0, "000".into()), (3, "3".into()), (4, "123".into())],
+            replica.sender.resolutions()
+        );
+    }
+
+    #[test]
+    fn replica_persists_acceptor_state() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+
+        replica.prepare(Ballot(1, 0));
+        assert_eq!(Some(Ballot(1, 0)), replica.storage().promised());
+
+        replica.accept(0, Ballot(1, 0), "123".into());
+        assert_eq!(Some((Ballot(1, 0), "123".into())), replica.storage().accepted(0));
+
+        // the distinguished proposer persists its own promise and accepted values
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("456".into());
+        assert_eq!(Some(Ballot(0, 4)), replica.storage().promised());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert_eq!(Some((Ballot(0, 4), "456".into())), replica.storage().accepted(0));
+    }
+
+    #[test]
+    fn replica_storage_failure() {
+        let mut replica =
+            Replica::new(VecSender::default(), CONFIG.clone()).with_storage(FailingStorage);
+
+        // PROMISE is not sent without persisting the ballot
+        replica.prepare(Ballot(1, 0));
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        // ACCEPTED is not sent without persisting the value
+        replica.accept(0, Ballot(1, 0), "123".into());
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+    }
+
+    #[test]
+    fn replica_buffered_storage() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone())
+            .with_storage(BufferedStorage::default());
+
+        // PROMISE is held until the ballot is synced
+        replica.prepare(Ballot(1, 0));
         assert!(replica.sender[0].is_empty());
-        assert!(replica.sender[1].is_empty());
-        assert!(replica.sender[2].is_empty());
-        assert!(replica.sender[3].is_empty());
+        replica.tick();
+        assert_eq!(vec![Message::Promise(4, Ballot(1, 0), 0, vec![])], replica.sender[0]);
+
+        // ACCEPTED is held until the value is synced
+        replica.sender.clear();
+        replica.accept(0, Ballot(1, 0), "123".into());
+        assert!(replica.sender[0].is_empty());
+        replica.tick();
+        assert_eq!(vec![Message::Accepted(4, 0, Ballot(1, 0))], replica.sender[0]);
+
+        // the leader's own acceptance counts once the value is synced
+        let config = Configuration::new(0, iter::empty());
+        let mut replica =
+            Replica::new(VecSender::default(), config).with_storage(BufferedStorage::default());
+        replica.proposal("123".into());
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
+        assert!(replica.sender.1.executed.is_empty());
+        replica.tick();
+        assert_eq!(vec![(0, Bytes::from("123"))], replica.sender.1.executed);
+    }
+
+    #[test]
+    fn replica_recover() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        replica.prepare(Ballot(3, 1));
+        replica.accept(2, Ballot(3, 1), "222".into());
+        replica.resolution(4, Ballot(3, 1), "444".into());
+        let storage = replica.storage;
+
+        let mut replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        assert_eq!(Some(Ballot(3, 1)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[(0, "000".into()), (1, "111".into())], replica.sender.resolutions());
+        assert_eq!((2..5), replica.window.open_range());
+        assert_eq!(
+            Some((Ballot(3, 1), "222".into())),
+            replica.window.slot_mut(2).unwrap_open().acceptor().highest_value()
+        );
+        assert_eq!((Ballot(3, 1), "444".into()), replica.window.slot_mut(4).unwrap_resolved());
+
+        // promise is kept after recovery
+        replica.prepare(Ballot(2, 0));
+        assert_eq!(&[Message::Reject(4, Ballot(2, 0), Ballot(3, 1))], &replica.sender[0]);
+    }
+
+    #[test]
+    fn replica_recover_skips_applied() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        let storage = replica.storage;
+
+        let mut sender = VecSender::default();
+        sender.1.applied = Some(0);
+        let replica = Replica::recover(sender, CONFIG.clone(), storage).unwrap();
+        assert_eq!(&[(1, "111".into())], replica.sender.resolutions());
+    }
+
+    #[test]
+    fn replica_recover_own_ballot() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.proposal("123".into());
+        let storage = replica.storage;
+
+        // a ballot from before the restart is not reused
+        let mut replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        replica.proposal("456".into());
+        assert_eq!(Some(Ballot(1, 4)), replica.proposer.highest_observed_ballot());
+        (0..4).for_each(|i| assert_eq!(&[Message::Prepare(Ballot(1, 4))], &replica.sender[i]));
+    }
+
+    #[test]
+    fn replica_recover_proposal_ids() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        assert_eq!(Ok(0), replica.propose("123".into()));
+        let storage = replica.storage;
+
+        // identifiers from before the restart are not reused
+        let mut replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        assert_eq!(Ok(1 << 32), replica.propose("456".into()));
+        replica.resolution(0, Ballot(0, 4), Entry::Proposal(4, 0, "123".into()).encode());
+        assert_eq!(vec![(0, "123".into())], replica.sender.1.executed);
+        assert!(replica.sender.1.proposals.is_empty());
+        let storage = replica.storage;
+
+        let mut replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        assert_eq!(Ok(2 << 32), replica.propose("789".into()));
+
+        // identifiers do not run into those of the next incarnation
+        replica.next_proposal = (3 << 32) - 2;
+        assert_eq!(Ok((3 << 32) - 2), replica.propose("000".into()));
+        assert_eq!(Ok(3 << 32), replica.propose("111".into()));
+        let storage = replica.storage;
+
+        let mut replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        assert_eq!(Ok(4 << 32), replica.propose("222".into()));
+
+        // the next incarnation must be persisted before it is used
+        let mut replica = replica.with_storage(FailingStorage);
+        replica.next_proposal = (5 << 32) - 1;
+        assert_eq!(Err(ProposeError::Storage), replica.propose("333".into()));
+    }
+
+    #[test]
+    fn replica_snapshot() {
+        let config = CONFIG.clone().with_snapshot_interval(2);
+        let mut replica = Replica::new(VecSender::default(), config.clone());
 
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        assert_eq!(None, replica.storage().snapshot());
+
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        assert_eq!(Some((1, "0,1".into())), replica.storage().snapshot());
+        assert!(matches!(replica.window.slot_mut(1), SlotMutRef::ResolutionTruncated));
+
+        replica.resolution(2, Ballot(1, 2), "222".into());
+        assert_eq!(Some((1, "0,1".into())), replica.storage().snapshot());
+        replica.resolution(3, Ballot(1, 2), "333".into());
+        assert_eq!(Some((3, "0,1,2,3".into())), replica.storage().snapshot());
+        replica.resolution(5, Ballot(1, 2), "555".into());
+        let storage = replica.storage;
+
+        // recovery restores the snapshot and replays the remaining log
+        let mut replica = Replica::recover(VecSender::default(), config, storage).unwrap();
+        assert_eq!(Some((3, "0,1,2,3".into())), replica.sender.1.restored);
         assert!(replica.sender.resolutions().is_empty());
+        assert_eq!((4..6), replica.window.open_range());
+
+        replica.resolution(4, Ballot(1, 2), "444".into());
+        assert_eq!(&[(4, "444".into()), (5, "555".into())], replica.sender.resolutions());
     }
 
     #[test]
-    fn replica_proposal_redirection() {
+    fn replica_snapshot_request() {
+        let config = CONFIG.clone().with_snapshot_interval(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        for slot in 0..4 {
+            replica.resolution(slot, Ballot(1, 2), "v".into());
+        }
+        replica.resolution(5, Ballot(1, 2), "555".into());
+        replica.sender.clear();
+
+        // compacted slots are sent as a snapshot followed by the decided suffix
+        replica.snapshot_request(1, 0);
+        assert_eq!(
+            &[
+                Message::InstallSnapshot(3, "0,1,2,3".into()),
+                Message::Resolution(5, Ballot(1, 2), "555".into())
+            ],
+            &replica.sender[1]
+        );
+        replica.sender.clear();
+
+        // slots that are not compacted are sent as resolutions
+        replica.snapshot_request(1, 5);
+        assert_eq!(&[Message::Resolution(5, Ballot(1, 2), "555".into())], &replica.sender[1]);
+    }
+
+    #[test]
+    fn replica_snapshot_request_stored() {
+        let config = CONFIG.clone().with_snapshot_interval(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        for slot in 0..5 {
+            replica.resolution(slot, Ballot(1, 2), "v".into());
+        }
+        replica.sender.clear();
+
+        // the stored snapshot is sent rather than a new snapshot of the
+        // state machine, and repeated requests receive the same snapshot
+        for _ in 0..2 {
+            replica.catchup(1, 0, 5);
+            assert_eq!(
+                &[
+                    Message::InstallSnapshot(3, "0,1,2,3".into()),
+                    Message::Resolution(4, Ballot(1, 2), "v".into())
+                ],
+                &replica.sender[1]
+            );
+            replica.sender.clear();
+        }
+    }
+
+    #[test]
+    fn replica_install_snapshot() {
+        let config = CONFIG.clone().with_snapshot_interval(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.resolution(0, Ballot(1, 2), "000".into());
+
+        // a decided slot beyond the open window requests the missing slots,
+        // which the leader answers with a snapshot once they are compacted
+        replica.resolution(10, Ballot(1, 2), "aaa".into());
+        assert_eq!(&[Message::Catchup(4, 1, 10)], &replica.sender[2]);
+        replica.resolution(11, Ballot(1, 2), "bbb".into());
+        assert_eq!(1, replica.sender[2].len());
+
+        replica.install_snapshot(9, "snapshot".into());
+        assert_eq!(Some((9, "snapshot".into())), replica.sender.1.restored);
+        assert_eq!(
+            &[(0, "000".into()), (10, "aaa".into()), (11, "bbb".into())],
+            replica.sender.resolutions()
+        );
+        assert_eq!((12..13), replica.window.open_range());
+
+        // the installed snapshot is followed by snapshots of later slots
+        assert_eq!(Some(11), replica.storage().snapshot().map(|(slot, _)| slot));
+
+        // older snapshots are ignored
+        replica.install_snapshot(5, "old".into());
+        assert_eq!(Some((9, "snapshot".into())), replica.sender.1.restored);
+    }
+
+    #[test]
+    fn replica_catchup_request() {
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
-        replica.prepare(Ballot(0, 3));
-        assert_eq!(Some(Ballot(0, 3)), replica.proposer.highest_observed_ballot());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        assert!(replica.sender[2].is_empty());
+
+        // resolved slots after a hole request the missing resolutions
+        replica.resolution(3, Ballot(1, 2), "333".into());
+        assert_eq!(&[Message::Catchup(4, 1, 3)], &replica.sender[2]);
+
+        // requests are not repeated for the same hole
+        replica.resolution(4, Ballot(1, 2), "444".into());
+        assert_eq!(1, replica.sender[2].len());
+
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        assert_eq!(&[Message::Catchup(4, 1, 3), Message::Catchup(4, 2, 4)], &replica.sender[2]);
+
+        replica.resolution(2, Ballot(1, 2), "222".into());
+        assert_eq!(2, replica.sender[2].len());
+        assert_eq!(5, replica.sender.resolutions().len());
+    }
+
+    #[test]
+    fn replica_catchup() {
+        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.resolution(0, Ballot(1, 2), "000".into());
+        replica.resolution(2, Ballot(1, 2), "222".into());
+        replica.resolution(3, Ballot(1, 2), "333".into());
         replica.sender.clear();
 
+        replica.catchup(1, 0, 3);
+        assert_eq!(
+            &[
+                Message::Resolution(0, Ballot(1, 2), "000".into()),
+                Message::Resolution(2, Ballot(1, 2), "222".into())
+            ],
+            &replica.sender[1]
+        );
+    }
+
+    #[test]
+    fn replica_tick_retransmits_prepare() {
+        let config = CONFIG.clone().with_retransmit_ticks(3);
+        let mut replica = Replica::new(VecSender::default(), config);
         replica.proposal("123".into());
-        assert!(replica.sender[0].is_empty());
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.sender.clear();
+
+        replica.tick();
+        replica.tick();
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        // peers that have not promised receive the PREPARE again
+        replica.tick();
         assert!(replica.sender[1].is_empty());
-        assert!(replica.sender[2].is_empty());
-        assert_eq!(&[Command::Proposal("123".into())], &replica.sender[3]);
+        for i in &[0, 2, 3] {
+            assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[*i]);
+        }
+    }
+
+    #[test]
+    fn replica_tick_retransmits_accept() {
+        let config = CONFIG.clone().with_retransmit_ticks(1);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("123".into());
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.sender.clear();
+
+        // peers that have not accepted receive the ACCEPT again
+        replica.tick();
+        assert!(replica.sender[0].is_empty());
+        for i in &[1, 2, 3] {
+            assert_eq!(&[Message::Accept(0, Ballot(0, 4), "123".into())], &replica.sender[*i]);
+        }
+        replica.sender.clear();
+
+        // nothing is re-sent once the slot is resolved
+        replica.accepted(1, 0, Ballot(0, 4));
+        replica.sender.clear();
+        replica.tick();
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+    }
+
+    #[test]
+    fn replica_tick_retransmits_catchup() {
+        let config = CONFIG.clone().with_retransmit_ticks(1);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.resolution(2, Ballot(1, 2), "222".into());
+        assert_eq!(&[Message::Catchup(4, 0, 2)], &replica.sender[2]);
+
+        replica.tick();
+        assert_eq!(&[Message::Catchup(4, 0, 2), Message::Catchup(4, 0, 2)], &replica.sender[2]);
+    }
+
+    #[test]
+    fn replica_tick_heartbeat() {
+        let config = CONFIG.clone().with_heartbeat_ticks(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("123".into());
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        replica.sender.clear();
+
+        replica.tick();
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        replica.tick();
+        (0..4).for_each(|i| assert_eq!(&[Message::Heartbeat(Ballot(0, 4), 1)], &replica.sender[i]));
+    }
+
+    #[test]
+    fn replica_tick_election_timeout() {
+        let config = CONFIG.clone().with_election_timeout(5, 5);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.heartbeat(Ballot(0, 2), 1);
+
+        // heartbeats from the leader restart the timeout
+        (0..4).for_each(|_| replica.tick());
+        replica.heartbeat(Ballot(0, 2), 2);
+        replica.sender.clear();
+        (0..4).for_each(|_| replica.tick());
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
 
-        assert!(replica.sender.resolutions().is_empty());
+        // leader stops sending heartbeats
+        replica.tick();
+        (0..4).for_each(|i| assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[i]));
+        assert_eq!(ProposerStatus::Candidate, replica.proposer.status());
     }
 
     #[test]
-    fn replica_prepare() {
+    fn replica_heartbeat() {
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+        replica.heartbeat(Ballot(3, 2), 7);
+        assert_eq!(&[Message::HeartbeatAck(4, Ballot(3, 2), 7)], &replica.sender[2]);
 
-        replica.prepare(Ballot(1, 0));
-        assert_eq!(Some(Ballot(1, 0)), replica.proposer.highest_observed_ballot());
-        assert_eq!(&[Command::Promise(4, Ballot(1, 0), Vec::new())], &replica.sender[0]);
-        assert!(&replica.sender[1].is_empty());
-        assert!(&replica.sender[2].is_empty());
-        assert!(&replica.sender[3].is_empty());
+        // proposals are forwarded to the leader
         replica.sender.clear();
+        replica.proposal("123".into());
+        assert_eq!(&[Message::Forward("123".into())], &replica.sender[2]);
 
-        replica.prepare(Ballot(0, 2));
-        assert_eq!(Some(Ballot(1, 0)), replica.proposer.highest_observed_ballot());
-        assert!(&replica.sender[0].is_empty());
-        assert!(&replica.sender[1].is_empty());
-        assert_eq!(&[Command::Reject(4, Ballot(0, 2), Ballot(1, 0))], &replica.sender[2]);
-        assert!(&replica.sender[3].is_empty());
-
-        assert!(replica.sender.resolutions().is_empty());
+        // stale leaders are rejected
+        replica.heartbeat(Ballot(1, 1), 1);
+        assert_eq!(&[Message::Reject(4, Ballot(1, 1), Ballot(3, 2))], &replica.sender[1]);
     }
 
     #[test]
-    fn replica_promise_without_existing_accepted_value() {
-        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+    fn replica_flexible_quorum() {
+        let config = CONFIG.clone().with_quorum_size(4, 2).unwrap();
+        let mut replica = Replica::new(VecSender::default(), config);
         replica.proposal("123".into());
-        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+
+        // Phase 1 requires promises from 3 peers
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Candidate, replica.proposer.status());
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
         replica.sender.clear();
 
-        // replica needs 2 more promises to achieve Phase 1 Quorum
-        replica.promise(0, Ballot(0, 4), Vec::new());
-        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+        // Phase 2 requires a single ACCEPTED from a peer
+        replica.accepted(3, 0, Ballot(0, 4));
+        assert_eq!(&[(0, "123".into())], replica.sender.resolutions());
+    }
 
-        replica.promise(2, Ballot(0, 4), Vec::new());
+    #[test]
+    fn replica_self_quorum() {
+        // the leader's own acceptance forms a Phase 2 quorum
+        let peers = vec![(1, "127.0.0.1:4000".parse().unwrap())];
+        let config = Configuration::new(0, peers.into_iter()).with_quorum_size(2, 1).unwrap();
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("123".into());
+        replica.promise(1, Ballot(0, 0), 0, vec![]);
+        assert_eq!(&[(0, "123".into())], replica.sender.resolutions());
+        assert_eq!(vec![(0, Bytes::from("123"))], replica.sender.1.executed);
 
-        (0..4).for_each(|i| {
-            assert_eq!(&[Command::Accept(0, Ballot(0, 4), "123".into())], &replica.sender[i])
-        });
+        // a single node is elected by its own promise
+        let config = Configuration::new(0, iter::empty());
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("123".into());
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
+        assert_eq!(vec![(0, Bytes::from("123"))], replica.sender.1.executed);
+    }
+
+    #[test]
+    fn replica_grid_quorum() {
+        let grid = GridQuorum::new(vec![vec![0, 1, 4], vec![2, 3]]);
+        let config = CONFIG.clone().with_quorum_system(move |_: &[NodeId]| grid.clone());
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("123".into());
 
+        // Phase 1 requires a complete row
+        replica.promise(2, Ballot(0, 4), 0, vec![]);
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Candidate, replica.proposer.status());
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
+
+        // Phase 2 requires a node from each row
+        replica.accepted(0, 0, Ballot(0, 4));
         assert!(replica.sender.resolutions().is_empty());
+        replica.accepted(3, 0, Ballot(0, 4));
+        assert_eq!(&[(0, "123".into())], replica.sender.resolutions());
     }
 
     #[test]
-    fn replica_promise_with_existing_accepted_value() {
-        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+    fn replica_thrifty() {
+        let config = CONFIG.clone().with_thrifty(true).with_retransmit_ticks(2);
+        let mut replica = Replica::new(VecSender::default(), config);
         replica.proposal("123".into());
-        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[0]);
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[1]);
+        assert!(replica.sender[2].is_empty());
+        assert!(replica.sender[3].is_empty());
+
+        // peers that have not responded are sent the message on retransmit
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.sender.clear();
+        replica.tick();
+        replica.tick();
+        assert!(replica.sender[0].is_empty());
+        for i in &[1, 2, 3] {
+            assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[*i]);
+        }
         replica.sender.clear();
 
-        // replica needs 2 more promises to achieve Phase 1 Quorum
-        replica.promise(1, Ballot(0, 4), vec![(0, Ballot(0, 0), "456".into())]);
-        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+        // responsive peers are preferred
+        replica.promise(3, Ballot(0, 4), 0, vec![]);
+        assert_eq!(ProposerStatus::Leader, replica.proposer.status());
+        for i in &[0, 3] {
+            assert_eq!(&[Message::Accept(0, Ballot(0, 4), "123".into())], &replica.sender[*i]);
+        }
+        assert!(replica.sender[1].is_empty());
+        assert!(replica.sender[2].is_empty());
+    }
 
-        replica.promise(2, Ballot(0, 4), vec![]);
+    #[test]
+    fn replica_reconfiguration() {
+        let config = CONFIG.clone().with_alpha(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.reconfigure(Reconfiguration::RemoveNode(3)).unwrap();
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(vec![0, 1, 2, 3], peers(&replica));
+        replica.sender.clear();
 
-        (0..4).for_each(|i| {
+        // reconfiguration takes effect at slot 2
+        replica.proposal("111".into());
+        replica.proposal("222".into());
+        for i in 0..3 {
             assert_eq!(
                 &[
-                    Command::Accept(0, Ballot(0, 4), "456".into()),
-                    Command::Accept(1, Ballot(0, 4), "123".into())
+                    Message::Accept(1, Ballot(0, 4), "111".into()),
+                    Message::Accept(2, Ballot(0, 4), "222".into())
                 ],
                 &replica.sender[i]
-            )
+            );
+        }
+        assert_eq!(&[Message::Accept(1, Ballot(0, 4), "111".into())], &replica.sender[3]);
+        assert_eq!(vec![0, 1, 2], peers(&replica));
+
+        // quorum is a majority of the new configuration
+        replica.accepted(0, 2, Ballot(0, 4));
+        replica.accepted(3, 2, Ballot(0, 4));
+        assert!(replica.window.slot_mut(2).unwrap_open().acceptor().resolution().is_none());
+        replica.accepted(3, 1, Ballot(0, 4));
+        replica.accepted(1, 1, Ballot(0, 4));
+        replica.accepted(1, 2, Ballot(0, 4));
+        assert_eq!(&[(1, "111".into()), (2, "222".into())], replica.sender.resolutions());
+    }
+
+    #[test]
+    fn replica_reconfiguration_quorum_system() {
+        let config = CONFIG.clone().with_alpha(2).with_quorum_system(|members: &[NodeId]| {
+            GridQuorum::new(members.chunks(3).map(|row| row.to_vec()).collect())
         });
+        let mut replica = Replica::new(VecSender::default(), config);
+        assert!(replica.config().quorum_system().is_phase1_quorum(&[3, 4]));
+        assert!(!replica.config().quorum_system().is_phase1_quorum(&[4]));
+
+        // the quorum system is rebuilt once the reconfiguration takes effect
+        let change = Reconfiguration::RemoveNode(3);
+        replica.resolution(0, Ballot(1, 2), Entry::Reconfiguration(change).encode());
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        replica.resolution(2, Ballot(1, 2), "222".into());
+        assert_eq!(vec![0, 1, 2], peers(&replica));
+        assert!(replica.config().quorum_system().is_phase1_quorum(&[4]));
+    }
 
-        assert!(replica.sender.resolutions().is_empty());
+    #[test]
+    fn replica_forward_reconfiguration() {
+        let config = Configuration::new(4, iter::once((0, "127.0.0.1:4000".parse().unwrap())));
+        let mut replica = Replica::new(VecSender::default(), config);
+        let remove = |node| Entry::Reconfiguration(Reconfiguration::RemoveNode(node)).encode();
+
+        // forwarded reconfigurations are checked against the scheduled ones
+        replica.forward(remove(0));
+        assert!(replica.sender[0].contains(&Message::Prepare(Ballot(0, 4))));
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.accepted(0, 0, Ballot(0, 4));
+        let change = Reconfiguration::RemoveNode(4);
+        assert_eq!(
+            Err(ProposeError::InvalidReconfiguration(ConfigError::NoMembers)),
+            replica.reconfigure(change)
+        );
+        replica.sender.clear();
+        replica.forward(remove(4));
+        assert!(replica.sender[0].is_empty());
+
+        // batches are only formed by the leader
+        replica.forward(Entry::Batch(vec![remove(4)]).encode());
+        assert!(replica.sender[0].is_empty());
     }
 
     #[test]
-    fn replica_promise_with_slot_holes() {
+    fn replica_proposal_control_entry() {
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
-        replica.proposal("123".into());
-        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        let remove = Entry::Reconfiguration(Reconfiguration::RemoveNode(3)).encode();
+        replica.proposal(remove.clone());
+        assert_eq!(Ok(0), replica.propose(remove.clone()));
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        for slot in 0..2 {
+            replica.accepted(0, slot, Ballot(0, 4));
+            replica.accepted(1, slot, Ballot(0, 4));
+        }
+
+        // values of clients are applied rather than changing the membership
+        assert_eq!(vec![0, 1, 2, 3], peers(&replica));
+        assert_eq!(vec![(0, remove.clone()), (1, remove)], replica.sender.1.executed);
+    }
+
+    #[test]
+    fn replica_alpha() {
+        let config = CONFIG.clone().with_alpha(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("000".into());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.proposal("111".into());
         replica.sender.clear();
 
-        // replica needs 2 more promises to achieve Phase 1 Quorum
-        replica.promise(1, Ballot(0, 4), vec![(2, Ballot(0, 0), "456".into())]);
+        // slot 2 cannot be proposed until slot 0 is executed
+        replica.proposal("222".into());
         (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
 
-        replica.promise(2, Ballot(0, 4), vec![]);
-
-        (0..4).for_each(|i| {
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        for i in 0..4 {
             assert_eq!(
                 &[
-                    Command::Accept(0, Ballot(0, 4), Bytes::default()),
-                    Command::Accept(1, Ballot(0, 4), Bytes::default()),
-                    Command::Accept(2, Ballot(0, 4), "456".into()),
-                    Command::Accept(3, Ballot(0, 4), "123".into())
+                    Message::Resolution(0, Ballot(0, 4), "000".into()),
+                    Message::Accept(2, Ballot(0, 4), "222".into())
                 ],
                 &replica.sender[i]
             );
-        });
-
-        assert!(replica.sender.resolutions().is_empty());
+        }
     }
 
     #[test]
-    fn replica_accept() {
+    fn replica_reconfiguration_snapshot() {
+        let config = CONFIG.clone().with_alpha(2).with_snapshot_interval(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        let add = Reconfiguration::AddNode(5, "127.0.0.1:4005".parse().unwrap());
+        replica.resolution(0, Ballot(1, 2), Entry::Reconfiguration(add).encode());
+        replica.resolution(1, Ballot(1, 2), "111".into());
+        assert_eq!(vec![0, 1, 2, 3, 5], peers(&replica));
+        assert_eq!(&[(1, "111".into())], replica.sender.resolutions());
+
+        // snapshots include the reconfiguration
+        let storage = replica.storage;
+        let replica = Replica::recover(VecSender::default(), CONFIG.clone(), storage).unwrap();
+        assert_eq!(vec![0, 1, 2, 3, 5], peers(&replica));
+
+        let snapshot = replica.storage().snapshot().unwrap();
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
-        replica.prepare(Ballot(8, 2));
-        assert_eq!(Some(Ballot(8, 2)), replica.proposer.highest_observed_ballot());
-        replica.sender.clear();
+        replica.install_snapshot(snapshot.0, snapshot.1);
+        assert_eq!(vec![0, 1, 2, 3, 5], peers(&replica));
+        assert_eq!(Some((1, "0,1".into())), replica.sender.1.restored);
+    }
 
-        // test rejection first for bal < proposer.highest_observed_ballot
-        replica.accept(0, Ballot(1, 1), "123".into());
-        assert_eq!(&[Command::Reject(4, Ballot(1, 1), Ballot(8, 2))], &replica.sender[1]);
+    #[test]
+    fn replica_learner() {
+        let config = CONFIG.clone().as_learner().with_election_timeout(5, 5);
+        let mut replica = Replica::new(VecSender::default(), config);
+
+        // learners neither promise nor accept
+        replica.prepare(Ballot(0, 2));
+        replica.accept(0, Ballot(0, 2), "000".into());
+        (0..10).for_each(|_| replica.tick());
+        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+
+        // decisions are executed
+        replica.resolution(0, Ballot(0, 2), "000".into());
+        assert_eq!(&[(0, "000".into())], replica.sender.resolutions());
+
+        // proposals are forwarded to the leader
+        replica.proposal("111".into());
+        assert_eq!(&[Message::Forward("111".into())], &replica.sender[2]);
+    }
+
+    #[test]
+    fn replica_sends_decisions_to_learners() {
+        let config =
+            CONFIG.clone().with_learners(vec![(5, "127.0.0.1:4005".parse().unwrap())].into_iter());
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("000".into());
+        assert!(replica.sender[5].is_empty());
+
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert!(replica.sender[5].is_empty());
+
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(&[Message::Resolution(0, Ballot(0, 4), "000".into())], &replica.sender[5]);
+
+        // learners are sent heartbeats to discover the leader
         replica.sender.clear();
+        (0..3).for_each(|_| replica.tick());
+        assert_eq!(&[Message::Heartbeat(Ballot(0, 4), 1)], &replica.sender[5]);
+    }
 
-        // test replying with accepted message when bal =
-        // proposer.highest_observed_ballot
-        replica.accept(0, Ballot(8, 2), "456".into());
-        assert_eq!(Some(Ballot(8, 2)), replica.proposer.highest_observed_ballot());
-        assert_eq!(&[Command::Accepted(4, 0, Ballot(8, 2))], &replica.sender[2]);
+    #[test]
+    fn replica_read_lease() {
+        let config = CONFIG.clone().with_read_lease(10, 2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("000".into());
+        assert_eq!(None, replica.read(|sm| sm.executed.len()));
+
+        // values of prior ballots must be executed before reads are served
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert!(!replica.has_read_lease());
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(Some(1), replica.read(|sm| sm.executed.len()));
+
+        // the leader renews the lease with heartbeats
         replica.sender.clear();
+        (0..3).for_each(|_| replica.tick());
+        assert_eq!(&[Message::Heartbeat(Ballot(0, 4), 1)], &replica.sender[0]);
+        replica.heartbeat_ack(0, Ballot(0, 4), 1);
+        replica.heartbeat_ack(1, Ballot(0, 4), 1);
+        (0..7).for_each(|_| replica.tick());
+        assert!(replica.has_read_lease());
+        replica.tick();
+        assert!(!replica.has_read_lease());
+
+        // renewing the lease does not use slots
+        assert_eq!(1, replica.window.peek_next_slot());
+        assert_eq!(&[(0, "000".into())], replica.sender.resolutions());
+    }
 
-        // test replying with accepted message when bal >
-        // proposer.highest_observed_ballot
-        replica.accept(0, Ballot(9, 2), "789".into());
-        assert_eq!(Some(Ballot(9, 2)), replica.proposer.highest_observed_ballot());
-        assert_eq!(&[Command::Accepted(4, 0, Ballot(9, 2))], &replica.sender[2]);
+    #[test]
+    fn replica_grants_read_lease() {
+        let config = CONFIG
+            .clone()
+            .with_read_lease(10, 2)
+            .with_election_timeout(5, 5)
+            .with_retransmit_ticks(20);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.prepare(Ballot(0, 2));
+        assert_eq!(&[Message::Promise(4, Ballot(0, 2), 0, vec![])], &replica.sender[2]);
 
-        assert!(replica.sender.resolutions().is_empty());
+        // no other ballot is promised while the lease is held
+        replica.prepare(Ballot(1, 3));
+        assert!(replica.sender[3].is_empty());
+        (0..5).for_each(|_| replica.tick());
+        assert!(replica.sender[3].is_empty());
+        assert_eq!(ProposerStatus::Follower, replica.proposer.status());
+
+        (0..5).for_each(|_| replica.tick());
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender[3]);
     }
 
     #[test]
-    fn replica_reject() {
+    fn replica_heartbeat_grants_read_lease() {
+        let config = CONFIG.clone().with_read_lease(10, 2).with_election_timeout(100, 100);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.heartbeat(Ballot(0, 2), 1);
+        assert_eq!(&[Message::HeartbeatAck(4, Ballot(0, 2), 1)], &replica.sender[2]);
+
+        (0..9).for_each(|_| replica.tick());
+        replica.prepare(Ballot(1, 3));
+        assert!(replica.sender[3].is_empty());
+        replica.tick();
+        replica.prepare(Ballot(1, 3));
+        assert_eq!(&[Message::Promise(4, Ballot(1, 3), 0, vec![])], &replica.sender[3]);
+    }
+
+    #[test]
+    fn replica_recover_read_lease() {
+        let config = CONFIG.clone().with_read_lease(10, 2).with_election_timeout(100, 100);
+        let mut replica = Replica::new(VecSender::default(), config.clone());
+        replica.accept(0, Ballot(0, 2), "000".into());
+        let storage = replica.storage;
+
+        // the lease granted before the restart may still be held
+        let mut replica = Replica::recover(VecSender::default(), config, storage).unwrap();
+        (0..11).for_each(|_| replica.tick());
+        replica.prepare(Ballot(1, 3));
+        assert!(replica.sender[3].is_empty());
+        replica.tick();
+        replica.prepare(Ballot(1, 3));
+        assert_eq!(
+            &[Message::Promise(4, Ballot(1, 3), 0, vec![(0, Ballot(0, 2), "000".into())])],
+            &replica.sender[3]
+        );
+    }
+
+    #[test]
+    fn replica_read_barrier() {
         let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
-        replica.proposal("123".into());
-        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
+        assert!(!replica.read_barrier(1));
+
+        replica.proposal("000".into());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
         replica.sender.clear();
+        assert!(replica.read_barrier(1));
+        (0..4).for_each(|i| assert_eq!(&[Message::Heartbeat(Ballot(0, 4), 1)], &replica.sender[i]));
 
-        replica.reject(2, Ballot(0, 4), Ballot(5, 3));
-        assert_eq!(Some(Ballot(5, 3)), replica.proposer.highest_observed_ballot());
-        assert_eq!(ProposerStatus::Follower, replica.proposer.status());
-        assert_eq!(&[Command::Proposal("123".into())], &replica.sender[3]);
-        (0..3).for_each(|i| assert!(replica.sender[i].is_empty()));
+        // values of prior ballots must be executed
+        replica.heartbeat_ack(0, Ballot(0, 4), 1);
+        replica.heartbeat_ack(1, Ballot(0, 4), 1);
+        assert!(replica.sender.1.reads.is_empty());
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(vec![(1, true)], replica.sender.1.reads);
+
+        // acknowledgements of earlier heartbeats do not confirm leadership
+        assert!(replica.read_barrier(2));
+        replica.heartbeat_ack(2, Ballot(0, 4), 1);
+        replica.heartbeat_ack(3, Ballot(0, 4), 1);
+        assert_eq!(vec![(1, true)], replica.sender.1.reads);
+        replica.heartbeat_ack(2, Ballot(0, 4), 2);
+        replica.heartbeat_ack(3, Ballot(0, 4), 3);
+        assert_eq!(vec![(1, true), (2, true)], replica.sender.1.reads);
+
+        // read barriers are aborted once preempted
+        assert!(replica.read_barrier(3));
+        replica.prepare(Ballot(1, 2));
+        replica.heartbeat_ack(0, Ballot(0, 4), 4);
+        replica.heartbeat_ack(1, Ballot(0, 4), 4);
+        replica.tick();
+        assert_eq!(vec![(1, true), (2, true), (3, false)], replica.sender.1.reads);
+    }
 
-        assert!(replica.sender.resolutions().is_empty());
+    #[test]
+    fn replica_propose() {
+        let config = CONFIG.clone().with_proposal_timeout(5);
+        let mut replica = Replica::new(VecSender::default(), config);
+        assert_eq!(Ok(0), replica.propose("123".into()));
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        let accept = Entry::Proposal(4, 0, "123".into()).encode();
+        assert_eq!(&[Message::Accept(0, Ballot(0, 4), accept)], &replica.sender[0][1..]);
+
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(vec![(0, "123".into())], replica.sender.1.executed);
+        assert_eq!(vec![(0, Some(0))], replica.sender.1.proposals);
+
+        // proposals of other nodes are executed without notification
+        replica.resolution(1, Ballot(0, 4), Entry::Proposal(2, 1, "456".into()).encode());
+        assert_eq!((1, "456".into()), replica.sender.1.executed[1]);
+        assert_eq!(vec![(0, Some(0))], replica.sender.1.proposals);
+
+        // undecided proposals are abandoned after the timeout
+        assert_eq!(Ok(1), replica.propose("789".into()));
+        (0..4).for_each(|_| replica.tick());
+        assert_eq!(vec![(0, Some(0))], replica.sender.1.proposals);
+        replica.tick();
+        assert_eq!(vec![(0, Some(0)), (1, None)], replica.sender.1.proposals);
+
+        replica.tick();
+        assert_eq!(vec![(0, Some(0)), (1, None)], replica.sender.1.proposals);
+
+        // a later decision of an abandoned proposal is still reported
+        replica.accepted(0, 2, Ballot(0, 4));
+        replica.accepted(1, 2, Ballot(0, 4));
+        assert_eq!((2, "789".into()), replica.sender.1.executed[2]);
+        assert_eq!(vec![(0, Some(0)), (1, None), (1, Some(2))], replica.sender.1.proposals);
     }
 
     #[test]
-    fn replica_accepted() {
-        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+    fn replica_abandoned_proposals() {
+        let config = CONFIG.clone().with_proposal_timeout(2).with_alpha(1);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.heartbeat(Ballot(0, 2), 1);
+        assert_eq!(Ok(0), replica.propose("123".into()));
+        (0..2).for_each(|_| replica.tick());
+        assert_eq!(vec![(0, None)], replica.sender.1.proposals);
+
+        // abandoned proposals are forgotten once α slots have been executed
+        replica.resolution(0, Ballot(0, 2), "000".into());
+        replica.resolution(1, Ballot(0, 2), "111".into());
+        replica.tick();
+        assert_eq!(1, replica.pending_proposals.len());
+        replica.resolution(2, Ballot(0, 2), "222".into());
+        replica.tick();
+        assert!(replica.pending_proposals.is_empty());
+
+        replica.resolution(3, Ballot(0, 2), Entry::Proposal(4, 0, "123".into()).encode());
+        assert_eq!((3, "123".into()), replica.sender.1.executed[3]);
+        assert_eq!(vec![(0, None)], replica.sender.1.proposals);
+    }
+
+    #[test]
+    fn replica_proposal_queue() {
+        let config = CONFIG.clone().with_proposal_queue(2, 1024).with_alpha(1);
+        let mut replica = Replica::new(VecSender::default(), config);
+
+        // values are queued during the election
         replica.proposal("123".into());
-        assert_eq!(Some(Ballot(0, 4)), replica.proposer.highest_observed_ballot());
-        replica.promise(1, Ballot(0, 4), vec![]);
-        replica.promise(0, Ballot(0, 4), vec![]);
-        replica.promise(2, Ballot(0, 4), vec![]);
+        assert_eq!(Ok(0), replica.propose("4".into()));
+        assert_eq!(Err(ProposeError::WouldBlock), replica.propose("5".into()));
+        replica.proposal("6".into());
+
+        // the leader proposes queued values up to α
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        assert_eq!(Ok(1), replica.propose("7".into()));
+        let id = replica.propose("8".into());
+        assert_eq!(Err(ProposeError::WouldBlock), id);
+        let change = Reconfiguration::RemoveNode(3);
+        assert_eq!(Err(ProposeError::WouldBlock), replica.reconfigure(change));
+
+        // executed slots make room in the queue
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(Ok(2), replica.propose("8".into()));
+        assert_eq!(vec![(0, "123".into())], replica.sender.1.executed);
+    }
+
+    #[test]
+    fn replica_batching() {
+        let config = CONFIG.clone().with_batching(3, 1024, 2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("1".into());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        // the open slot is filled while the value waits for the batch
+        assert_eq!(Message::Accept(0, Ballot(0, 4), Bytes::new()), replica.sender[0][1]);
         replica.sender.clear();
 
-        // wait for phase 2 quorum (accepted) before sending resolution
+        // full batches are proposed immediately
+        assert_eq!(Ok(0), replica.propose("2".into()));
+        assert!(replica.sender[0].is_empty());
+        replica.proposal("3".into());
+        let batch = vec!["1".into(), Entry::Proposal(4, 0, "2".into()).encode(), "3".into()];
+        let batch = Entry::Batch(batch).encode();
+        assert_eq!(&[Message::Accept(1, Ballot(0, 4), batch)], &replica.sender[0][..]);
+
+        // partial batches are proposed after the batching window
+        replica.sender.clear();
+        replica.proposal("4".into());
+        replica.tick();
+        assert!(!replica.sender[0].iter().any(|m| matches!(m, Message::Accept(..))));
+        replica.tick();
+        assert!(replica.sender[0].contains(&Message::Accept(2, Ballot(0, 4), "4".into())));
+
+        // values of a batch are executed individually
+        for slot in 0..3 {
+            replica.accepted(0, slot, Ballot(0, 4));
+            replica.accepted(1, slot, Ballot(0, 4));
+        }
+        let executed: Vec<(Slot, Bytes)> =
+            vec![(1, "1".into()), (1, "2".into()), (1, "3".into()), (2, "4".into())];
+        assert_eq!(executed, replica.sender.1.executed);
+        assert_eq!(vec![(0, Some(1))], replica.sender.1.proposals);
+    }
+
+    #[test]
+    fn replica_batch_applied() {
+        let config = CONFIG.clone().with_batching(2, 1024, 2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.sender.1.tracks_applied = true;
+        replica.proposal("1".into());
+        replica.proposal("2".into());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
         replica.accepted(0, 0, Ballot(0, 4));
-        (0..4).for_each(|i| assert!(replica.sender[i].is_empty()));
+        replica.accepted(1, 0, Ballot(0, 4));
 
-        replica.accepted(2, 0, Ballot(0, 4));
-        (0..4).for_each(|i| {
-            assert_eq!(&[Command::Resolution(0, Ballot(0, 4), "123".into())], &replica.sender[i])
-        });
+        // the slot is applied once every value of the batch is executed
+        assert_eq!(vec![(0, "1".into()), (0, "2".into())], replica.sender.1.executed);
+    }
 
-        assert_eq!(&[(0, "123".into())], replica.sender.resolutions());
+    #[test]
+    fn replica_batch_sender() {
+        let sender = BatchSender::new(VecSender::default());
+        let mut replica = Replica::new(sender, CONFIG.clone());
+        replica.proposal("123".into());
+        assert_eq!(&[Message::Prepare(Ballot(0, 4))], &replica.sender.inner()[0]);
+        replica.proposal("456".into());
+        replica.sender.inner_mut().clear();
+
+        // batches of messages are handled as a single event
+        Message::Batch(vec![
+            Message::Promise(0, Ballot(0, 4), 0, vec![]),
+            Message::Promise(1, Ballot(0, 4), 0, vec![]),
+        ])
+        .dispatch(&mut replica);
+        let batch = Message::Batch(vec![
+            Message::Accept(0, Ballot(0, 4), "123".into()),
+            Message::Accept(1, Ballot(0, 4), "456".into()),
+        ]);
+        assert_eq!(&[batch], &replica.sender.inner()[0]);
+
+        // responses to the messages of a batch are sent in a single batch
+        replica.sender.inner_mut().clear();
+        Message::Batch(vec![
+            Message::Accept(0, Ballot(1, 2), "0".into()),
+            Message::Accept(1, Ballot(1, 2), "1".into()),
+        ])
+        .dispatch(&mut replica);
+        let batch = Message::Batch(vec![
+            Message::Accepted(4, 0, Ballot(1, 2)),
+            Message::Accepted(4, 1, Ballot(1, 2)),
+        ]);
+        assert_eq!(&[batch], &replica.sender.inner()[2]);
     }
 
     #[test]
-    fn replica_resolution() {
-        let mut replica = Replica::new(VecSender::default(), CONFIG.clone());
+    fn replica_max_in_flight() {
+        let config = CONFIG.clone().with_max_in_flight(2);
+        let mut replica = Replica::new(VecSender::default(), config);
+        replica.proposal("0".into());
+        replica.promise(0, Ballot(0, 4), 0, vec![]);
+        replica.promise(1, Ballot(0, 4), 0, vec![]);
+        replica.proposal("1".into());
+        replica.proposal("2".into());
+        let accepts = |sender: &VecSender| {
+            sender[0].iter().filter(|m| matches!(m, Message::Accept(..))).count()
+        };
+        assert_eq!(2, accepts(&replica.sender));
 
-        replica.resolution(4, Ballot(1, 2), "123".into());
-        assert_eq!((0..5), replica.window.open_range());
-        assert!(match replica.window.slot_mut(4) {
-            SlotMutRef::Resolved(Ballot(1, 2), val) if val == "123" => true,
-            _ => false,
-        });
+        // resolving a later slot does not open new slots
+        replica.accepted(0, 1, Ballot(0, 4));
+        replica.accepted(1, 1, Ballot(0, 4));
+        assert_eq!(2, accepts(&replica.sender));
 
-        replica.resolution(1, Ballot(1, 2), Bytes::default());
-        replica.resolution(0, Ballot(1, 2), "000".into());
-        assert_eq!(&[(0, "000".into())], replica.sender.resolutions());
+        replica.accepted(0, 0, Ballot(0, 4));
+        replica.accepted(1, 0, Ballot(0, 4));
+        assert_eq!(3, accepts(&replica.sender));
+        assert_eq!(Some(&Message::Accept(2, Ballot(0, 4), "2".into())), replica.sender[0].last());
+    }
 
-        // fill hole 1,2
-        replica.resolution(1, Ballot(1, 2), Bytes::default());
-        replica.resolution(2, Ballot(1, 2), Bytes::default());
-        replica.resolution(3, Ballot(1, 2), "3".into());
+    fn peers<S: Sender, T: Storage>(replica: &Replica<S, T>) -> Vec<NodeId> {
+        let mut peers = replica.config().peers().into_iter().collect::<Vec<NodeId>>();
+        peers.sort();
+        peers
+    }
 
-        assert_eq!(
-            &[(0, "000".into()), (3, "3".into()), (4, "123".into())],
-            replica.sender.resolutions()
-        );
+    struct FailingStorage;
+
+    impl Storage for FailingStorage {
+        fn store_promise(&mut self, _bal: Ballot) -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn store_accepted(&mut self, _slot: Slot, _bal: Ballot, _val: Bytes) -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn store_resolution(&mut self, _slot: Slot, _bal: Ballot, _val: Bytes) -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn store_incarnation(&mut self, _incarnation: u64) -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn store_snapshot(&mut self, _slot: Slot, _snapshot: Bytes) -> io::Result<()> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>> {
+            Err(io::Error::other("failed"))
+        }
+
+        fn load(&mut self) -> io::Result<PersistedState> {
+            Err(io::Error::other("failed"))
+        }
+    }
+
+    /// Storage that buffers writes until the next flush
+    #[derive(Default)]
+    struct BufferedStorage(MemoryStorage, usize);
+
+    impl Storage for BufferedStorage {
+        fn store_promise(&mut self, bal: Ballot) -> io::Result<()> {
+            self.1 += 1;
+            self.0.store_promise(bal)
+        }
+
+        fn store_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+            self.1 += 1;
+            self.0.store_accepted(slot, bal, val)
+        }
+
+        fn store_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+            self.1 += 1;
+            self.0.store_resolution(slot, bal, val)
+        }
+
+        fn store_incarnation(&mut self, incarnation: u64) -> io::Result<()> {
+            self.1 += 1;
+            self.0.store_incarnation(incarnation)
+        }
+
+        fn store_snapshot(&mut self, slot: Slot, snapshot: Bytes) -> io::Result<()> {
+            self.1 += 1;
+            self.0.store_snapshot(slot, snapshot)
+        }
+
+        fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>> {
+            self.0.load_snapshot()
+        }
+
+        fn load(&mut self) -> io::Result<PersistedState> {
+            self.0.load()
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.1 = 0;
+            Ok(())
+        }
+
+        fn is_durable(&self) -> bool {
+            self.1 == 0
+        }
     }
 
     #[derive(Default)]
-    struct VecSender([Vec<Command>; 4], StateMachine);
+    /// Sends commands to peers 0-3 and learner 5
+    struct VecSender([Vec<Message>; 6], StateMachine);
 
     impl VecSender {
         fn clear(&mut self) {
-            for i in 0usize..4 {
+            for i in 0usize..6 {
                 self.0[i].clear();
             }
         }
 
         fn resolutions(&self) -> &[(Slot, Bytes)] {
-            &(&self.1).0
+            &(&self.1).executed
         }
     }
 
     impl Index<usize> for VecSender {
-        type Output = [Command];
-        fn index(&self, n: usize) -> &[Command] {
-            assert!(n < 4);
+        type Output = [Message];
+        fn index(&self, n: usize) -> &[Message] {
+            assert!(n < 6);
             &self.0[n]
         }
     }
 
     impl Sender for VecSender {
-        type Commander = Vec<Command>;
+        type Commander = Vec<Message>;
         type StateMachine = StateMachine;
 
         fn send_to<F>(&mut self, node: NodeId, f: F)
         where
             F: FnOnce(&mut Self::Commander) -> (),
         {
-            assert!(node < 4);
+            assert!(node < 6);
             f(&mut self.0[node as usize]);
         }
 
@@ -590,11 +3082,52 @@ mod tests {
     }
 
     #[derive(Default)]
-    struct StateMachine(Vec<(Slot, Bytes)>);
+    struct StateMachine {
+        executed: Vec<(Slot, Bytes)>,
+        applied: Option<Slot>,
+        /// Reports executed slots as applied
+        tracks_applied: bool,
+        restored: Option<(Slot, Bytes)>,
+        reads: Vec<(u64, bool)>,
+        proposals: Vec<(ProposalId, Option<Slot>)>,
+    }
 
     impl ReplicatedState for StateMachine {
         fn execute(&mut self, slot: Slot, command: Bytes) {
-            self.0.push((slot, command));
+            self.executed.push((slot, command));
+            if self.tracks_applied {
+                self.applied = Some(slot);
+            }
+        }
+
+        fn applied(&self) -> Option<Slot> {
+            self.applied
+        }
+
+        fn snapshot(&mut self, slot: Slot) -> Option<Bytes> {
+            // the snapshot contains the slots up to the snapshot slot
+            let slots = (0..=slot).map(|s| s.to_string()).collect::<Vec<_>>();
+            Some(slots.join(",").into())
+        }
+
+        fn restore(&mut self, slot: Slot, snapshot: Bytes) {
+            self.restored = Some((slot, snapshot));
+        }
+
+        fn read_ready(&mut self, id: u64) {
+            self.reads.push((id, true));
+        }
+
+        fn read_aborted(&mut self, id: u64) {
+            self.reads.push((id, false));
+        }
+
+        fn proposal_decided(&mut self, id: ProposalId, slot: Slot) {
+            self.proposals.push((id, Some(slot)));
+        }
+
+        fn proposal_abandoned(&mut self, id: ProposalId) {
+            self.proposals.push((id, None));
         }
     }
 }
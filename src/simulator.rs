@@ -0,0 +1,497 @@
+use crate::{
+    timer::XorShift, Ballot, Configuration, MemoryStorage, Message, NodeId, PersistedState,
+    ProposalId, ProposeError, Replica, ReplicatedState, Sender, Slot, Storage,
+};
+use bytes::Bytes;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    io,
+    rc::Rc,
+};
+
+/// Replica hosted by a `Simulator`
+pub type SimReplica<R> = Replica<SimSender<R>, SimStorage>;
+
+/// Deterministic in-process cluster for testing.
+///
+/// The simulator hosts a replica for each node of the cluster, connected by
+/// an in-memory network. Time advances in ticks with `step`, which delivers
+/// the messages that are due and ticks every running replica. A seeded
+/// pseudo-random number generator draws the delay of each message, whether
+/// it is dropped or duplicated, and the order of messages delivered within
+/// the same tick, so a run is reproduced exactly from its seed.
+///
+/// Nodes may be crashed and restarted. A crashed node loses its state
+/// machine and volatile state, while acceptor state survives in storage and
+/// is recovered on restart.
+pub struct Simulator<R> {
+    rng: XorShift,
+    /// Simulated time, in ticks
+    now: u64,
+    nodes: BTreeMap<NodeId, SimNode<R>>,
+    /// Messages in flight, keyed by the tick of delivery and a random order
+    /// within the tick, along with the sending and receiving nodes
+    network: BTreeMap<(u64, u64), (NodeId, NodeId, Message)>,
+    /// Minimum and maximum delay of messages, in ticks
+    delay: (u64, u64),
+    drop_rate: f64,
+    duplicate_rate: f64,
+    /// Nodes separated from the rest of the cluster
+    partition: Option<BTreeSet<NodeId>>,
+    state_machine: Box<dyn FnMut(NodeId) -> R>,
+}
+
+struct SimNode<R> {
+    config: Configuration,
+    storage: SimStorage,
+    replica: Option<SimReplica<R>>,
+}
+
+impl<R: ReplicatedState> Simulator<R> {
+    /// Creates a cluster of `size` nodes from a seed. The state machine of
+    /// each node is created with `state_machine`, which is called again
+    /// when a node restarts.
+    ///
+    /// Messages are delayed by 1 to 3 ticks and are neither dropped nor
+    /// duplicated until the faults are changed.
+    pub fn new<F>(seed: u64, size: u32, state_machine: F) -> Simulator<R>
+    where
+        F: FnMut(NodeId) -> R + 'static,
+    {
+        let mut sim = Simulator {
+            rng: XorShift::from_seed(seed),
+            now: 0,
+            nodes: BTreeMap::new(),
+            network: BTreeMap::new(),
+            delay: (1, 3),
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            partition: None,
+            state_machine: Box::new(state_machine),
+        };
+        for node in 0..size {
+            let peers = (0..size)
+                .filter(|peer| *peer != node)
+                .map(|peer| (peer, format!("127.0.0.1:{}", 5000 + peer).parse().unwrap()));
+            let config = Configuration::new(node, peers);
+            let storage = SimStorage::default();
+            sim.nodes.insert(node, SimNode { config, storage, replica: None });
+            sim.start(node);
+        }
+        sim
+    }
+
+    /// Replaces the configuration of each node, such as to change timeouts
+    /// or add learners. The replicas are restarted from empty state.
+    pub fn with_configuration<F>(mut self, f: F) -> Simulator<R>
+    where
+        F: Fn(Configuration) -> Configuration,
+    {
+        let nodes = self.nodes.keys().cloned().collect::<Vec<NodeId>>();
+        for node in nodes {
+            let sim_node = self.nodes.get_mut(&node).unwrap();
+            sim_node.config = f(sim_node.config.clone());
+            sim_node.storage = SimStorage::default();
+            self.start(node);
+        }
+        self.network.clear();
+        self
+    }
+
+    /// Sets the inclusive range of ticks by which messages are delayed.
+    /// Messages with different delays are reordered.
+    pub fn set_delay(&mut self, min: u64, max: u64) {
+        assert!(min <= max);
+        self.delay = (min, max);
+    }
+
+    /// Sets the probability of dropping each message
+    pub fn set_drop_rate(&mut self, rate: f64) {
+        self.drop_rate = rate;
+    }
+
+    /// Sets the probability of delivering a message twice
+    pub fn set_duplicate_rate(&mut self, rate: f64) {
+        self.duplicate_rate = rate;
+    }
+
+    /// Separates the nodes from the rest of the cluster. Messages between
+    /// the two sides are dropped until the partition is healed.
+    pub fn partition(&mut self, nodes: &[NodeId]) {
+        debug!("Partitioning {:?} at tick {}", nodes, self.now);
+        self.partition = Some(nodes.iter().cloned().collect());
+    }
+
+    /// Removes the partition of the network
+    pub fn heal(&mut self) {
+        debug!("Healing partition at tick {}", self.now);
+        self.partition = None;
+    }
+
+    /// Crashes a node, discarding its state machine and volatile state
+    pub fn crash(&mut self, node: NodeId) {
+        debug!("Crashing node {} at tick {}", node, self.now);
+        if let Some(sim_node) = self.nodes.get_mut(&node) {
+            sim_node.replica = None;
+        }
+    }
+
+    /// Restarts a crashed node, recovering acceptor state from its storage
+    pub fn restart(&mut self, node: NodeId) {
+        debug!("Restarting node {} at tick {}", node, self.now);
+        if !self.is_up(node) {
+            self.start(node);
+        }
+    }
+
+    fn start(&mut self, node: NodeId) {
+        let state_machine = (self.state_machine)(node);
+        let sim_node = self.nodes.get_mut(&node).unwrap();
+        let sender = SimSender { outbox: Vec::new(), state_machine };
+        let replica = Replica::recover(sender, sim_node.config.clone(), sim_node.storage.clone())
+            .expect("memory storage does not fail");
+        sim_node.replica = Some(replica);
+    }
+
+    /// Flag indicating whether the node is running
+    pub fn is_up(&self, node: NodeId) -> bool {
+        self.nodes.get(&node).map(|n| n.replica.is_some()).unwrap_or(false)
+    }
+
+    /// Identifiers of the nodes of the cluster
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.nodes.keys().cloned()
+    }
+
+    /// Simulated time, in ticks
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Replica of a running node
+    pub fn replica(&self, node: NodeId) -> Option<&SimReplica<R>> {
+        self.nodes.get(&node)?.replica.as_ref()
+    }
+
+    /// Mutable reference to the replica of a running node. Messages sent
+    /// by the replica are delivered from the next step.
+    pub fn replica_mut(&mut self, node: NodeId) -> Option<&mut SimReplica<R>> {
+        self.nodes.get_mut(&node)?.replica.as_mut()
+    }
+
+    /// State machine of a running node
+    pub fn state_machine(&self, node: NodeId) -> Option<&R> {
+        self.replica(node).map(|replica| &replica.sender().state_machine)
+    }
+
+    /// Proposes a value at a node, see `Replica::propose`
+    pub fn propose(&mut self, node: NodeId, val: Bytes) -> Result<ProposalId, ProposeError> {
+        let id = self.replica_mut(node).ok_or(ProposeError::Stopped)?.propose(val)?;
+        self.send(node);
+        Ok(id)
+    }
+
+    /// Advances time by a single tick. Messages due at the tick are
+    /// delivered, then each running replica is ticked.
+    pub fn step(&mut self) {
+        self.now += 1;
+        self.deliver();
+
+        let nodes = self.nodes.keys().cloned().collect::<Vec<NodeId>>();
+        for node in nodes {
+            if let Some(replica) = self.replica_mut(node) {
+                replica.tick();
+                self.send(node);
+            }
+        }
+        self.deliver();
+    }
+
+    /// Advances time by a number of ticks
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.step();
+        }
+    }
+
+    /// Advances time until the predicate holds, for at most `max_ticks`
+    /// ticks. Returns whether the predicate holds.
+    pub fn run_until<P>(&mut self, max_ticks: u64, mut predicate: P) -> bool
+    where
+        P: FnMut(&Simulator<R>) -> bool,
+    {
+        for _ in 0..max_ticks {
+            if predicate(self) {
+                return true;
+            }
+            self.step();
+        }
+        predicate(self)
+    }
+
+    /// Delivers the messages due at the current tick, including messages
+    /// sent without delay while delivering
+    fn deliver(&mut self) {
+        loop {
+            let key = match self.network.keys().next() {
+                Some(key) if key.0 <= self.now => *key,
+                _ => return,
+            };
+            let (from, to, message) = self.network.remove(&key).unwrap();
+            if self.is_partitioned(from, to) {
+                continue;
+            }
+            if let Some(replica) = self.replica_mut(to) {
+                trace!("Delivering {:?} from {} to {}", message, from, to);
+                message.dispatch(replica);
+                self.send(to);
+            }
+        }
+    }
+
+    /// Moves the messages sent by a node onto the network, applying faults
+    fn send(&mut self, from: NodeId) {
+        let mut outbox = match self.replica_mut(from) {
+            Some(replica) => std::mem::take(&mut replica.sender_mut().outbox),
+            None => return,
+        };
+        // messages sent to peers in iteration order of the configuration
+        // are ordered such that runs are reproducible
+        outbox.sort_by_key(|(to, _)| *to);
+
+        for (to, message) in outbox {
+            if self.is_partitioned(from, to) || self.chance(self.drop_rate) {
+                trace!("Dropping {:?} from {} to {}", message, from, to);
+                continue;
+            }
+            if self.chance(self.duplicate_rate) {
+                self.enqueue(from, to, message.clone());
+            }
+            self.enqueue(from, to, message);
+        }
+    }
+
+    fn enqueue(&mut self, from: NodeId, to: NodeId, message: Message) {
+        let (min, max) = self.delay;
+        let at = self.now + min + self.rng.next() % (max - min + 1);
+        let mut order = self.rng.next();
+        while self.network.contains_key(&(at, order)) {
+            order = order.wrapping_add(1);
+        }
+        self.network.insert((at, order), (from, to, message));
+    }
+
+    fn chance(&mut self, rate: f64) -> bool {
+        rate > 0.0 && (self.rng.next() as f64 / u64::MAX as f64) < rate
+    }
+
+    fn is_partitioned(&self, from: NodeId, to: NodeId) -> bool {
+        match self.partition {
+            Some(ref nodes) => nodes.contains(&from) != nodes.contains(&to),
+            None => false,
+        }
+    }
+}
+
+/// Sender of a replica hosted by a `Simulator`, which collects messages
+/// until they are moved onto the simulated network.
+pub struct SimSender<R> {
+    outbox: Vec<(NodeId, Message)>,
+    state_machine: R,
+}
+
+impl<R: ReplicatedState> Sender for SimSender<R> {
+    type Commander = Vec<Message>;
+    type StateMachine = R;
+
+    fn send_to<F>(&mut self, node: NodeId, command: F)
+    where
+        F: FnOnce(&mut Self::Commander),
+    {
+        let mut messages = Vec::new();
+        command(&mut messages);
+        self.outbox.extend(messages.into_iter().map(|msg| (node, msg)));
+    }
+
+    fn state_machine(&mut self) -> &mut R {
+        &mut self.state_machine
+    }
+}
+
+/// Storage of a node hosted by a `Simulator`, which survives crashes of the
+/// node.
+#[derive(Clone, Default)]
+pub struct SimStorage(Rc<RefCell<MemoryStorage>>);
+
+impl Storage for SimStorage {
+    fn store_promise(&mut self, bal: Ballot) -> io::Result<()> {
+        self.0.borrow_mut().store_promise(bal)
+    }
+
+    fn store_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        self.0.borrow_mut().store_accepted(slot, bal, val)
+    }
+
+    fn store_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        self.0.borrow_mut().store_resolution(slot, bal, val)
+    }
+
+    fn store_incarnation(&mut self, incarnation: u64) -> io::Result<()> {
+        self.0.borrow_mut().store_incarnation(incarnation)
+    }
+
+    fn store_snapshot(&mut self, slot: Slot, snapshot: Bytes) -> io::Result<()> {
+        self.0.borrow_mut().store_snapshot(slot, snapshot)
+    }
+
+    fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>> {
+        self.0.borrow_mut().load_snapshot()
+    }
+
+    fn load(&mut self) -> io::Result<PersistedState> {
+        self.0.borrow_mut().load()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::{Buf, BufMut, BytesMut};
+
+    /// State machine that logs the executed values
+    #[derive(Default)]
+    struct Log {
+        executed: Vec<(Slot, Bytes)>,
+        decided: Vec<ProposalId>,
+    }
+
+    impl ReplicatedState for Log {
+        fn execute(&mut self, slot: Slot, command: Bytes) {
+            self.executed.push((slot, command));
+        }
+
+        fn proposal_decided(&mut self, id: ProposalId, _slot: Slot) {
+            self.decided.push(id);
+        }
+
+        fn applied(&self) -> Option<Slot> {
+            self.executed.last().map(|(slot, _)| *slot)
+        }
+
+        fn snapshot(&mut self, _slot: Slot) -> Option<Bytes> {
+            let mut buf = BytesMut::new();
+            for (slot, val) in &self.executed {
+                buf.put_u64_le(*slot);
+                buf.put_u32_le(val.len() as u32);
+                buf.put_slice(val);
+            }
+            Some(buf.freeze())
+        }
+
+        fn restore(&mut self, _slot: Slot, mut snapshot: Bytes) {
+            self.executed.clear();
+            while snapshot.has_remaining() {
+                let slot = snapshot.get_u64_le();
+                let len = snapshot.get_u32_le() as usize;
+                self.executed.push((slot, snapshot.split_to(len)));
+            }
+        }
+    }
+
+    fn executed(sim: &Simulator<Log>, node: NodeId) -> Vec<(Slot, Bytes)> {
+        sim.state_machine(node).map(|log| log.executed.clone()).unwrap_or_default()
+    }
+
+    /// Asserts that the logs executed by the nodes agree
+    fn assert_agreement(sim: &Simulator<Log>) {
+        let logs = sim.nodes().map(|node| executed(sim, node)).collect::<Vec<_>>();
+        let longest = logs.iter().max_by_key(|log| log.len()).unwrap();
+        for log in &logs {
+            assert_eq!(&longest[..log.len()], &log[..]);
+        }
+    }
+
+    #[test]
+    fn simulator_decides() {
+        let mut sim = Simulator::new(1, 3, |_| Log::default());
+        let id = sim.propose(0, "123".into()).unwrap();
+        assert!(sim.run_until(100, |sim| sim.nodes().all(|n| executed(sim, n).len() == 1)));
+        assert_eq!(vec![id], sim.state_machine(0).unwrap().decided);
+
+        // followers forward proposals to the leader
+        sim.propose(2, "456".into()).unwrap();
+        assert!(sim.run_until(100, |sim| sim.nodes().all(|n| executed(sim, n).len() == 2)));
+        assert_agreement(&sim);
+    }
+
+    /// Proposes values from random nodes while injecting faults, returning
+    /// the logs executed by each node
+    fn faulty_run<F>(seed: u64, config: F) -> Vec<Vec<(Slot, Bytes)>>
+    where
+        F: Fn(Configuration) -> Configuration,
+    {
+        let mut sim = Simulator::new(seed, 5, |_| Log::default()).with_configuration(config);
+        sim.set_delay(0, 5);
+        sim.set_drop_rate(0.1);
+        sim.set_duplicate_rate(0.1);
+
+        let mut rng = XorShift::from_seed(seed);
+        for i in 0..200u64 {
+            let node = (rng.next() % 5) as NodeId;
+            let _ = sim.propose(node, i.to_string().into());
+            match i {
+                50 => sim.partition(&[0, 1]),
+                80 => sim.crash(2),
+                100 => sim.heal(),
+                120 => sim.restart(2),
+                _ => {}
+            }
+            sim.run(2);
+            assert_agreement(&sim);
+        }
+
+        // the cluster makes progress once faults stop
+        sim.set_drop_rate(0.0);
+        sim.propose(3, "final".into()).unwrap();
+        let decided = |sim: &Simulator<Log>| {
+            sim.nodes().all(|n| executed(sim, n).iter().any(|(_, val)| val == "final"))
+        };
+        assert!(sim.run_until(1000, decided));
+        assert_agreement(&sim);
+        sim.nodes().map(|node| executed(&sim, node)).collect()
+    }
+
+    #[test]
+    fn simulator_faults() {
+        for seed in 0..5 {
+            faulty_run(seed, |config| config);
+        }
+    }
+
+    #[test]
+    fn simulator_faults_snapshot_batching() {
+        for seed in 0..5 {
+            faulty_run(seed, |config| config.with_snapshot_interval(7).with_batching(3, 1024, 2));
+        }
+    }
+
+    #[test]
+    fn simulator_deterministic() {
+        assert_eq!(faulty_run(7, |config| config), faulty_run(7, |config| config));
+    }
+
+    #[test]
+    fn simulator_restart() {
+        let mut sim = Simulator::new(3, 3, |_| Log::default());
+        sim.propose(0, "123".into()).unwrap();
+        assert!(sim.run_until(100, |sim| sim.nodes().all(|n| executed(sim, n).len() == 1)));
+
+        // recovered decisions are executed by the new state machine
+        sim.crash(1);
+        assert!(!sim.is_up(1));
+        assert_eq!(Err(ProposeError::Stopped), sim.propose(1, "456".into()));
+        sim.restart(1);
+        assert_eq!(vec![(0, Bytes::from("123"))], executed(&sim, 1));
+    }
+}
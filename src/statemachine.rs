@@ -1,4 +1,4 @@
-use crate::Slot;
+use crate::{ProposalId, Slot};
 use bytes::Bytes;
 
 /// A state machine that executes sequentially applied commands.
@@ -7,6 +7,56 @@ pub trait ReplicatedState {
     ///
     /// Values are applied in increasing _slot_ order. There may be holes
     /// such that there is no guarantee that _slot-1_ has been
-    /// applied before _slot_.
+    /// applied before _slot_. Values proposed within a batch (see
+    /// `Configuration::with_batching`) share a slot and are applied in the
+    /// order they were proposed.
     fn execute(&mut self, slot: Slot, command: Bytes);
+
+    /// Highest slot that has been applied to the state machine.
+    ///
+    /// State machines that survive a restart of the replica should return
+    /// the last applied slot so that recovered decisions are not applied
+    /// twice. The default implementation returns `None`, in which case all
+    /// recovered decisions are re-executed.
+    fn applied(&self) -> Option<Slot> {
+        None
+    }
+
+    /// Takes a snapshot of the state machine containing all commands
+    /// applied up to and including `slot`.
+    ///
+    /// Once the snapshot is persisted, the replica discards decisions for
+    /// slots `<= slot`. The default implementation does not support
+    /// snapshots, in which case the log is never compacted.
+    fn snapshot(&mut self, _slot: Slot) -> Option<Bytes> {
+        None
+    }
+
+    /// Replaces the state of the state machine with a snapshot taken at
+    /// `slot`. Commands for subsequent slots are applied after the restore.
+    fn restore(&mut self, _slot: Slot, _snapshot: Bytes) {}
+
+    /// Notifies the state machine that a read barrier requested with
+    /// `Replica::read_barrier` has completed. The state machine has applied
+    /// every value decided before the barrier was requested, so reads served
+    /// from the state machine are linearizable.
+    fn read_ready(&mut self, _id: u64) {}
+
+    /// Notifies the state machine that a read barrier was aborted because
+    /// the replica lost leadership. The read may be retried at the leader.
+    fn read_aborted(&mut self, _id: u64) {}
+
+    /// Notifies the state machine that a proposal submitted with
+    /// `Replica::propose` was decided at `slot`. The notification follows the
+    /// execution of the value, and is only delivered at the replica that
+    /// submitted the proposal.
+    fn proposal_decided(&mut self, _id: ProposalId, _slot: Slot) {}
+
+    /// Notifies the state machine that a proposal submitted with
+    /// `Replica::propose` was not decided within the proposal timeout (see
+    /// `Configuration::with_proposal_timeout`). The value may still be
+    /// decided, in which case `proposal_decided` follows once it has been
+    /// executed, unless the decision is well past the slots known when the
+    /// proposal was abandoned.
+    fn proposal_abandoned(&mut self, _id: ProposalId) {}
 }
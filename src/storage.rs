@@ -0,0 +1,347 @@
+use crate::{Ballot, Slot, SlottedValue};
+use bytes::Bytes;
+use std::{cmp::max, collections::BTreeMap, io};
+
+/// Durable storage of the acceptor state for a replica.
+///
+/// The replica persists the promised ballot and accepted values through the
+/// storage _before_ sending the corresponding PROMISE or ACCEPTED message to
+/// a peer. Once a call returns successfully, the value must survive a restart
+/// of the replica. If the storage returns an error, the message is not sent.
+///
+/// Storage may instead buffer writes and make them durable on `flush`, such
+/// as a `Wal` with `SyncPolicy::GroupCommit` or `SyncPolicy::Interval`, as
+/// long as `is_durable` returns `false` while writes are buffered. The
+/// replica then holds the messages it sends, including its own acceptance of
+/// a value as the leader, until a flush makes the preceding writes durable.
+pub trait Storage {
+    /// Persists the highest ballot promised by the acceptor.
+    fn store_promise(&mut self, bal: Ballot) -> io::Result<()>;
+
+    /// Persists a value accepted for a slot with the given ballot.
+    fn store_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()>;
+
+    /// Persists the final resolution of a slot.
+    fn store_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()>;
+
+    /// Persists the incarnation of the replica, which increases each time the
+    /// replica is recovered such that proposal identifiers are not reused
+    /// across restarts.
+    fn store_incarnation(&mut self, incarnation: u64) -> io::Result<()>;
+
+    /// Persists a snapshot of the state machine taken at a slot. Snapshots
+    /// that are not newer than the stored snapshot are ignored. Once the
+    /// snapshot is durable, the storage may discard accepted values and
+    /// resolutions for slots `<= slot`.
+    fn store_snapshot(&mut self, slot: Slot, snapshot: Bytes) -> io::Result<()>;
+
+    /// Loads the latest snapshot along with the slot at which it was taken,
+    /// used to send the snapshot to peers that are missing compacted slots.
+    fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>>;
+
+    /// Loads all persisted state, used to recover a replica after a restart.
+    fn load(&mut self) -> io::Result<PersistedState>;
+
+    /// Flushes writes that have not yet been made durable. Called on every
+    /// tick of the replica, bounding how long writes remain buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Flag indicating whether every successful write is durable. Messages
+    /// of the replica are held while this returns `false`.
+    fn is_durable(&self) -> bool {
+        true
+    }
+}
+
+/// Acceptor state loaded from storage
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct PersistedState {
+    /// Highest ballot that has been promised
+    pub promised: Option<Ballot>,
+    /// Highest accepted ballot and value for slots that are not resolved
+    pub accepted: Vec<SlottedValue>,
+    /// Resolved slots
+    pub resolved: Vec<SlottedValue>,
+    /// Latest snapshot of the state machine along with the slot at which
+    /// the snapshot was taken
+    pub snapshot: Option<(Slot, Bytes)>,
+    /// Highest incarnation of the replica
+    pub incarnation: u64,
+}
+
+impl PersistedState {
+    /// Builds the state from persisted records. Records may be in any order and
+    /// may contain duplicates. Only the highest accepted ballot is kept for
+    /// each slot, accepted values are omitted for resolved slots and slots
+    /// covered by the snapshot are omitted.
+    pub(crate) fn from_records<I, J>(
+        promised: Option<Ballot>,
+        accepted: I,
+        resolved: J,
+        snapshot: Option<(Slot, Bytes)>,
+        incarnation: u64,
+    ) -> Self
+    where
+        I: IntoIterator<Item = SlottedValue>,
+        J: IntoIterator<Item = SlottedValue>,
+    {
+        let min_slot = snapshot.as_ref().map(|(slot, _)| slot.saturating_add(1)).unwrap_or(0);
+        let resolved: BTreeMap<Slot, (Ballot, Bytes)> = resolved
+            .into_iter()
+            .filter(|(slot, ..)| *slot >= min_slot)
+            .map(|(slot, bal, val)| (slot, (bal, val)))
+            .collect();
+
+        let mut highest: BTreeMap<Slot, (Ballot, Bytes)> = BTreeMap::new();
+        for (slot, bal, val) in accepted {
+            if slot < min_slot || resolved.contains_key(&slot) {
+                continue;
+            }
+            match highest.get(&slot) {
+                Some((existing, _)) if *existing > bal => {}
+                _ => {
+                    highest.insert(slot, (bal, val));
+                }
+            }
+        }
+
+        PersistedState {
+            promised,
+            accepted: highest.into_iter().map(|(slot, (bal, val))| (slot, bal, val)).collect(),
+            resolved: resolved.into_iter().map(|(slot, (bal, val))| (slot, bal, val)).collect(),
+            snapshot,
+            incarnation,
+        }
+    }
+
+    /// Highest ballot contained in the state
+    pub fn highest_ballot(&self) -> Option<Ballot> {
+        self.accepted
+            .iter()
+            .chain(self.resolved.iter())
+            .map(|(_, bal, _)| Some(*bal))
+            .fold(self.promised, max)
+    }
+}
+
+/// Storage that keeps acceptor state in memory.
+///
+/// State is lost when the process exits, so this implementation is only
+/// useful for tests and replicas that do not need to survive a restart.
+#[derive(Default, Debug)]
+pub struct MemoryStorage {
+    promised: Option<Ballot>,
+    accepted: BTreeMap<Slot, (Ballot, Bytes)>,
+    resolved: BTreeMap<Slot, (Ballot, Bytes)>,
+    snapshot: Option<(Slot, Bytes)>,
+    incarnation: u64,
+}
+
+impl MemoryStorage {
+    /// Creates empty storage
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+
+    /// Highest ballot that has been promised
+    pub fn promised(&self) -> Option<Ballot> {
+        self.promised
+    }
+
+    /// Highest accepted ballot and value for a slot
+    pub fn accepted(&self, slot: Slot) -> Option<(Ballot, Bytes)> {
+        self.accepted.get(&slot).cloned()
+    }
+
+    /// Resolved ballot and value for a slot
+    pub fn resolved(&self, slot: Slot) -> Option<(Ballot, Bytes)> {
+        self.resolved.get(&slot).cloned()
+    }
+
+    /// Latest snapshot and the slot at which it was taken
+    pub fn snapshot(&self) -> Option<(Slot, Bytes)> {
+        self.snapshot.clone()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn store_promise(&mut self, bal: Ballot) -> io::Result<()> {
+        self.promised = self.promised.max(Some(bal));
+        Ok(())
+    }
+
+    fn store_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        match self.accepted.get(&slot) {
+            Some((existing, _)) if *existing > bal => {}
+            _ => {
+                self.accepted.insert(slot, (bal, val));
+            }
+        }
+        Ok(())
+    }
+
+    fn store_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        self.resolved.insert(slot, (bal, val));
+        Ok(())
+    }
+
+    fn store_incarnation(&mut self, incarnation: u64) -> io::Result<()> {
+        self.incarnation = self.incarnation.max(incarnation);
+        Ok(())
+    }
+
+    fn store_snapshot(&mut self, slot: Slot, snapshot: Bytes) -> io::Result<()> {
+        if self.snapshot.as_ref().map(|(stored, _)| *stored >= slot).unwrap_or(false) {
+            return Ok(());
+        }
+        match slot.checked_add(1) {
+            Some(next) => {
+                self.accepted = self.accepted.split_off(&next);
+                self.resolved = self.resolved.split_off(&next);
+            }
+            None => {
+                self.accepted.clear();
+                self.resolved.clear();
+            }
+        }
+        self.snapshot = Some((slot, snapshot));
+        Ok(())
+    }
+
+    fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>> {
+        Ok(self.snapshot.clone())
+    }
+
+    fn load(&mut self) -> io::Result<PersistedState> {
+        Ok(PersistedState::from_records(
+            self.promised,
+            self.accepted.iter().map(|(slot, (bal, val))| (*slot, *bal, val.clone())),
+            self.resolved.iter().map(|(slot, (bal, val))| (*slot, *bal, val.clone())),
+            self.snapshot.clone(),
+            self.incarnation,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_storage_promise() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(None, storage.promised());
+
+        storage.store_promise(Ballot(5, 1)).unwrap();
+        assert_eq!(Some(Ballot(5, 1)), storage.promised());
+
+        // lower ballots do not override the promise
+        storage.store_promise(Ballot(4, 2)).unwrap();
+        assert_eq!(Some(Ballot(5, 1)), storage.promised());
+
+        storage.store_promise(Ballot(5, 2)).unwrap();
+        assert_eq!(Some(Ballot(5, 2)), storage.promised());
+    }
+
+    #[test]
+    fn memory_storage_accepted() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(None, storage.accepted(0));
+
+        storage.store_accepted(0, Ballot(1, 1), "abc".into()).unwrap();
+        assert_eq!(Some((Ballot(1, 1), "abc".into())), storage.accepted(0));
+
+        // higher ballots replace the accepted value
+        storage.store_accepted(0, Ballot(2, 1), "def".into()).unwrap();
+        assert_eq!(Some((Ballot(2, 1), "def".into())), storage.accepted(0));
+
+        // lower ballots are ignored
+        storage.store_accepted(0, Ballot(1, 3), "ghi".into()).unwrap();
+        assert_eq!(Some((Ballot(2, 1), "def".into())), storage.accepted(0));
+
+        assert_eq!(None, storage.accepted(1));
+    }
+
+    #[test]
+    fn memory_storage_load() {
+        let mut storage = MemoryStorage::new();
+        assert_eq!(PersistedState::default(), storage.load().unwrap());
+
+        storage.store_promise(Ballot(3, 1)).unwrap();
+        storage.store_accepted(0, Ballot(1, 1), "abc".into()).unwrap();
+        storage.store_accepted(1, Ballot(2, 1), "def".into()).unwrap();
+        storage.store_resolution(0, Ballot(1, 1), "abc".into()).unwrap();
+        storage.store_incarnation(2).unwrap();
+        storage.store_incarnation(1).unwrap();
+
+        let state = storage.load().unwrap();
+        assert_eq!(2, state.incarnation);
+        assert_eq!(Some(Ballot(3, 1)), state.promised);
+        assert_eq!(vec![(1, Ballot(2, 1), "def".into())], state.accepted);
+        assert_eq!(vec![(0, Ballot(1, 1), "abc".into())], state.resolved);
+        assert_eq!(Some(Ballot(3, 1)), state.highest_ballot());
+    }
+
+    #[test]
+    fn persisted_state_from_records() {
+        let state = PersistedState::from_records(
+            None,
+            vec![
+                (2, Ballot(1, 0), "a".into()),
+                (1, Ballot(3, 0), "b".into()),
+                (2, Ballot(4, 0), "c".into()),
+                (2, Ballot(2, 0), "d".into()),
+                (5, Ballot(0, 0), "e".into()),
+            ],
+            vec![(5, Ballot(1, 1), "f".into())],
+            None,
+            0,
+        );
+        assert_eq!(None, state.promised);
+        assert_eq!(
+            vec![(1, Ballot(3, 0), "b".into()), (2, Ballot(4, 0), "c".into())],
+            state.accepted
+        );
+        assert_eq!(vec![(5, Ballot(1, 1), "f".into())], state.resolved);
+        assert_eq!(Some(Ballot(4, 0)), state.highest_ballot());
+
+        // slots covered by the snapshot are omitted
+        let state = PersistedState::from_records(
+            None,
+            vec![(1, Ballot(3, 0), "b".into()), (2, Ballot(4, 0), "c".into())],
+            vec![(0, Ballot(1, 1), "a".into())],
+            Some((1, "snapshot".into())),
+            0,
+        );
+        assert_eq!(vec![(2, Ballot(4, 0), "c".into())], state.accepted);
+        assert!(state.resolved.is_empty());
+        assert_eq!(Some((1, "snapshot".into())), state.snapshot);
+    }
+
+    #[test]
+    fn memory_storage_snapshot() {
+        let mut storage = MemoryStorage::new();
+        storage.store_accepted(0, Ballot(1, 1), "abc".into()).unwrap();
+        storage.store_accepted(2, Ballot(1, 1), "def".into()).unwrap();
+        storage.store_resolution(0, Ballot(1, 1), "abc".into()).unwrap();
+        storage.store_resolution(1, Ballot(1, 1), "ghi".into()).unwrap();
+
+        storage.store_snapshot(1, "snapshot".into()).unwrap();
+        assert_eq!(Some((1, "snapshot".into())), storage.snapshot());
+        assert_eq!(None, storage.accepted(0));
+        assert_eq!(None, storage.resolved(1));
+        assert_eq!(Some((Ballot(1, 1), "def".into())), storage.accepted(2));
+
+        // older snapshots do not replace the stored snapshot
+        storage.store_snapshot(0, "older".into()).unwrap();
+        assert_eq!(Some((1, "snapshot".into())), storage.snapshot());
+        assert_eq!(Some((Ballot(1, 1), "def".into())), storage.accepted(2));
+
+        // a snapshot at the last slot covers every record
+        storage.store_snapshot(Slot::MAX, "last".into()).unwrap();
+        assert_eq!(Some((Slot::MAX, "last".into())), storage.snapshot());
+        assert_eq!(None, storage.accepted(2));
+    }
+}
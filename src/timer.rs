@@ -0,0 +1,100 @@
+use crate::NodeId;
+
+/// Randomized timeout used by followers to detect failure of the
+/// distinguished proposer.
+///
+/// Each time the timer is reset, a new deadline is drawn uniformly from the
+/// configured range of ticks. Randomizing the deadline spreads out the
+/// elections started by followers such that a single candidate is likely to
+/// win before the others time out.
+#[derive(Debug)]
+pub struct ElectionTimer {
+    /// State of the pseudo-random number generator
+    rng: XorShift,
+    /// Minimum and maximum number of ticks before the timer expires
+    timeout: (u64, u64),
+    /// Tick at which the timer expires
+    deadline: u64,
+}
+
+impl ElectionTimer {
+    /// Creates a timer for a node, drawing timeouts within the inclusive
+    /// range `min..=max` of ticks.
+    pub fn new(node: NodeId, (min, max): (u64, u64)) -> ElectionTimer {
+        assert!(min > 0 && min <= max);
+        let mut timer =
+            ElectionTimer { rng: XorShift::new(node), timeout: (min, max), deadline: 0 };
+        timer.reset(0);
+        timer
+    }
+
+    /// Restarts the timer from the current tick.
+    pub fn reset(&mut self, now: u64) {
+        let (min, max) = self.timeout;
+        self.deadline = now + min + self.rng.next() % (max - min + 1);
+    }
+
+    /// Flag indicating whether the timer has expired at the current tick.
+    pub fn is_expired(&self, now: u64) -> bool {
+        now >= self.deadline
+    }
+}
+
+/// Small xorshift* generator, which is plenty for spreading out timeouts
+/// without pulling in a dependency.
+#[derive(Debug)]
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+    fn new(node: NodeId) -> XorShift {
+        XorShift::from_seed(u64::from(node))
+    }
+
+    /// Creates a generator from a seed
+    pub fn from_seed(seed: u64) -> XorShift {
+        // the state must never be zero
+        XorShift(seed.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15).max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn election_timer() {
+        let mut timer = ElectionTimer::new(1, (5, 10));
+        for now in 0..100 {
+            timer.reset(now);
+            assert!(!timer.is_expired(now + 4));
+            assert!(timer.is_expired(now + 10));
+        }
+    }
+
+    #[test]
+    fn election_timer_randomized() {
+        let mut timer = ElectionTimer::new(1, (5, 10));
+        let mut deadlines = Vec::new();
+        for _ in 0..50 {
+            timer.reset(0);
+            deadlines.push(timer.deadline);
+        }
+        deadlines.sort();
+        deadlines.dedup();
+        assert!(deadlines.len() > 1);
+
+        // timers for different nodes are independent
+        let timers =
+            (0..5).map(|node| ElectionTimer::new(node, (5, 100)).deadline).collect::<Vec<u64>>();
+        assert!(timers.iter().any(|d| *d != timers[0]));
+    }
+}
@@ -0,0 +1,732 @@
+use crate::{Ballot, PersistedState, Slot, Storage};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{
+    cmp::max,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Default maximum size of a segment before the log rotates to a new one.
+const DEFAULT_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Size of the record header: 4 byte length followed by a 4 byte CRC
+const HEADER_SIZE: usize = 8;
+
+const SEGMENT_EXTENSION: &str = "wal";
+const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TMP_FILE: &str = "snapshot.tmp";
+
+const PROMISE_RECORD: u8 = 1;
+const ACCEPTED_RECORD: u8 = 2;
+const RESOLUTION_RECORD: u8 = 3;
+const SNAPSHOT_RECORD: u8 = 4;
+const INCARNATION_RECORD: u8 = 5;
+
+/// Policy that controls when the write-ahead log is flushed to disk.
+///
+/// With `SyncPolicy::GroupCommit` or `SyncPolicy::Interval`, records are
+/// buffered and the log is not durable until the next sync (see
+/// `Storage::is_durable`). The replica holds the PROMISE and ACCEPTED
+/// messages covered by buffered records until the sync, which happens once
+/// enough records are buffered or when the replica ticks (see
+/// `Storage::flush`). These policies trade the latency of responses for
+/// fewer syncs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Sync after every record
+    Always,
+    /// Sync once the given number of records have been written since the
+    /// last sync, or on the next tick
+    GroupCommit(usize),
+    /// Sync on the first write or tick after the interval has elapsed since
+    /// the last sync
+    Interval(Duration),
+}
+
+/// Segmented, append-only write-ahead log on the local filesystem that
+/// stores acceptor state.
+///
+/// Each record is framed with its length and a CRC32 checksum of the payload.
+/// When the log is opened, a partially written (torn) record at the tail of
+/// the most recent segment is truncated away. Segments are rotated once they
+/// reach the configured size.
+///
+/// Snapshots are written to a separate file in the directory. Once a snapshot
+/// is stored, the log is rewritten without the records covered by the
+/// snapshot and the old segments are removed.
+pub struct Wal {
+    dir: PathBuf,
+    policy: SyncPolicy,
+    segment_size: u64,
+
+    /// Identifiers of the segments in the log, in order
+    segments: Vec<u64>,
+    active: File,
+    active_len: u64,
+    /// Slot of the stored snapshot, if any
+    snapshot_slot: Option<Slot>,
+
+    unsynced: usize,
+    last_sync: Instant,
+}
+
+impl Wal {
+    /// Opens the write-ahead log contained in a directory, creating the
+    /// directory if it does not exist.
+    pub fn open<P: AsRef<Path>>(dir: P, policy: SyncPolicy) -> io::Result<Wal> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok())
+            {
+                segments.push(id);
+            }
+        }
+        segments.sort();
+
+        let (active, active_len) = match segments.last() {
+            Some(&id) => {
+                let path = segment_path(&dir, id);
+                let (_, valid_len) = read_segment(&path)?;
+                let file = OpenOptions::new().append(true).open(&path)?;
+                let len = file.metadata()?.len();
+                if len > valid_len {
+                    warn!(
+                        "Truncating torn write in segment {:?} from {} to {} bytes",
+                        path, len, valid_len
+                    );
+                    file.set_len(valid_len)?;
+                    file.sync_all()?;
+                }
+                (file, valid_len)
+            }
+            None => {
+                segments.push(0);
+                (create_segment(&dir, 0)?, 0)
+            }
+        };
+
+        let mut wal = Wal {
+            dir,
+            policy,
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            segments,
+            active,
+            active_len,
+            snapshot_slot: None,
+            unsynced: 0,
+            last_sync: Instant::now(),
+        };
+        wal.snapshot_slot = wal.read_snapshot()?.map(|(slot, _)| slot);
+        Ok(wal)
+    }
+
+    /// Sets the size at which segments are rotated
+    pub fn with_segment_size(mut self, segment_size: u64) -> Wal {
+        assert!(segment_size > 0);
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Directory containing the segments
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Number of segments in the log
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Flushes all written records to disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.active.sync_data()?;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    fn append(&mut self, record: Record) -> io::Result<()> {
+        self.write_record(&record)?;
+
+        let sync = match self.policy {
+            SyncPolicy::Always => true,
+            SyncPolicy::GroupCommit(records) => self.unsynced >= records,
+            SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+        };
+        if sync { self.sync() } else { Ok(()) }
+    }
+
+    /// Writes a record to the active segment without syncing
+    fn write_record(&mut self, record: &Record) -> io::Result<()> {
+        let buf = frame(record);
+        if self.active_len > 0 && self.active_len + buf.len() as u64 > self.segment_size {
+            self.rotate()?;
+        }
+
+        self.active.write_all(&buf)?;
+        self.active_len += buf.len() as u64;
+        self.unsynced += 1;
+        Ok(())
+    }
+
+    /// Rewrites the log without the records for slots `<= slot`
+    fn compact(&mut self, slot: Slot) -> io::Result<()> {
+        let records = self.records()?;
+        let sealed = self.segments.clone();
+        self.rotate()?;
+
+        let promised = records
+            .iter()
+            .filter_map(|r| if let Record::Promise(bal) = r { Some(*bal) } else { None })
+            .max();
+        if let Some(bal) = promised {
+            self.write_record(&Record::Promise(bal))?;
+        }
+        let incarnation = records
+            .iter()
+            .filter_map(|r| if let Record::Incarnation(i) = r { Some(*i) } else { None })
+            .max();
+        if let Some(incarnation) = incarnation {
+            self.write_record(&Record::Incarnation(incarnation))?;
+        }
+        for record in records {
+            match record {
+                Record::Accepted(s, ..) | Record::Resolution(s, ..) if s > slot => {
+                    self.write_record(&record)?;
+                }
+                _ => {}
+            }
+        }
+        self.sync()?;
+
+        // the retained records are durable, remove the old segments
+        let active = *self.segments.last().unwrap();
+        for id in sealed.into_iter().filter(|id| *id < active) {
+            fs::remove_file(segment_path(&self.dir, id))?;
+        }
+        self.segments.retain(|id| *id >= active);
+        File::open(&self.dir)?.sync_all()?;
+
+        debug!("Compacted write-ahead log through slot {}", slot);
+        Ok(())
+    }
+
+    /// Reads the latest snapshot, if one exists
+    fn read_snapshot(&self) -> io::Result<Option<(Slot, Bytes)>> {
+        let path = self.dir.join(SNAPSHOT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        match read_segment(&path)?.0.pop() {
+            Some(Record::Snapshot(slot, snapshot)) => Ok(Some((slot, snapshot))),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt snapshot {:?}", path),
+            )),
+        }
+    }
+
+    /// Seals the active segment and starts writing to a new segment
+    fn rotate(&mut self) -> io::Result<()> {
+        self.sync()?;
+        let id = self.segments.last().map(|id| id + 1).unwrap_or(0);
+        self.active = create_segment(&self.dir, id)?;
+        self.active_len = 0;
+        self.segments.push(id);
+        debug!("Rotated write-ahead log to segment {}", id);
+        Ok(())
+    }
+
+    /// Reads all valid records in the log
+    fn records(&self) -> io::Result<Vec<Record>> {
+        let mut records = Vec::new();
+        for (i, id) in self.segments.iter().enumerate() {
+            let path = segment_path(&self.dir, *id);
+            let (segment_records, valid_len) = read_segment(&path)?;
+
+            // only the active segment may contain a torn write, sealed
+            // segments have been synced before rotation
+            if i + 1 < self.segments.len() && valid_len < fs::metadata(&path)?.len() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Corrupt record in sealed segment {:?}", path),
+                ));
+            }
+            records.extend(segment_records);
+        }
+        Ok(records)
+    }
+}
+
+impl Storage for Wal {
+    fn store_promise(&mut self, bal: Ballot) -> io::Result<()> {
+        self.append(Record::Promise(bal))
+    }
+
+    fn store_accepted(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        self.append(Record::Accepted(slot, bal, val))
+    }
+
+    fn store_resolution(&mut self, slot: Slot, bal: Ballot, val: Bytes) -> io::Result<()> {
+        self.append(Record::Resolution(slot, bal, val))
+    }
+
+    fn store_incarnation(&mut self, incarnation: u64) -> io::Result<()> {
+        // recoveries are rare, so the incarnation is synced immediately
+        self.write_record(&Record::Incarnation(incarnation))?;
+        self.sync()
+    }
+
+    fn store_snapshot(&mut self, slot: Slot, snapshot: Bytes) -> io::Result<()> {
+        if self.snapshot_slot.map(|stored| stored >= slot).unwrap_or(false) {
+            return Ok(());
+        }
+
+        // write the snapshot to a temporary file and atomically replace the
+        // previous snapshot
+        let tmp = self.dir.join(SNAPSHOT_TMP_FILE);
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&frame(&Record::Snapshot(slot, snapshot)))?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, self.dir.join(SNAPSHOT_FILE))?;
+        File::open(&self.dir)?.sync_all()?;
+        self.snapshot_slot = Some(slot);
+
+        self.compact(slot)
+    }
+
+    fn load_snapshot(&mut self) -> io::Result<Option<(Slot, Bytes)>> {
+        self.read_snapshot()
+    }
+
+    fn load(&mut self) -> io::Result<PersistedState> {
+        let mut promised = None;
+        let mut accepted = Vec::new();
+        let mut resolved = Vec::new();
+        let mut incarnation = 0;
+        for record in self.records()? {
+            match record {
+                Record::Promise(bal) => promised = max(promised, Some(bal)),
+                Record::Accepted(slot, bal, val) => accepted.push((slot, bal, val)),
+                Record::Resolution(slot, bal, val) => resolved.push((slot, bal, val)),
+                Record::Incarnation(i) => incarnation = max(incarnation, i),
+                Record::Snapshot(..) => {}
+            }
+        }
+        let snapshot = self.read_snapshot()?;
+        Ok(PersistedState::from_records(promised, accepted, resolved, snapshot, incarnation))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let sync = self.unsynced > 0
+            && match self.policy {
+                SyncPolicy::Always | SyncPolicy::GroupCommit(_) => true,
+                SyncPolicy::Interval(interval) => self.last_sync.elapsed() >= interval,
+            };
+        if sync { self.sync() } else { Ok(()) }
+    }
+
+    fn is_durable(&self) -> bool {
+        self.unsynced == 0
+    }
+}
+
+/// Entry within the write-ahead log
+#[derive(Debug, PartialEq, Eq)]
+enum Record {
+    Promise(Ballot),
+    Accepted(Slot, Ballot, Bytes),
+    Resolution(Slot, Ballot, Bytes),
+    Snapshot(Slot, Bytes),
+    Incarnation(u64),
+}
+
+impl Record {
+    fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        match self {
+            Record::Promise(bal) => {
+                buf.put_u8(PROMISE_RECORD);
+                put_ballot(&mut buf, *bal);
+            }
+            Record::Accepted(slot, bal, val) => {
+                put_slotted_value(&mut buf, ACCEPTED_RECORD, *slot, *bal, val);
+            }
+            Record::Resolution(slot, bal, val) => {
+                put_slotted_value(&mut buf, RESOLUTION_RECORD, *slot, *bal, val);
+            }
+            Record::Snapshot(slot, snapshot) => {
+                buf.reserve(9 + snapshot.len());
+                buf.put_u8(SNAPSHOT_RECORD);
+                buf.put_u64_le(*slot);
+                buf.put_slice(snapshot);
+            }
+            Record::Incarnation(incarnation) => {
+                buf.put_u8(INCARNATION_RECORD);
+                buf.put_u64_le(*incarnation);
+            }
+        }
+        buf.freeze()
+    }
+
+    fn decode(mut payload: Bytes) -> Option<Record> {
+        if !payload.has_remaining() {
+            return None;
+        }
+        match payload.get_u8() {
+            PROMISE_RECORD if payload.remaining() == 8 => {
+                Some(Record::Promise(get_ballot(&mut payload)))
+            }
+            record_type @ ACCEPTED_RECORD | record_type @ RESOLUTION_RECORD
+                if payload.remaining() >= 16 =>
+            {
+                let slot = payload.get_u64_le();
+                let bal = get_ballot(&mut payload);
+                if record_type == ACCEPTED_RECORD {
+                    Some(Record::Accepted(slot, bal, payload))
+                } else {
+                    Some(Record::Resolution(slot, bal, payload))
+                }
+            }
+            SNAPSHOT_RECORD if payload.remaining() >= 8 => {
+                let slot = payload.get_u64_le();
+                Some(Record::Snapshot(slot, payload))
+            }
+            INCARNATION_RECORD if payload.remaining() == 8 => {
+                Some(Record::Incarnation(payload.get_u64_le()))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn put_slotted_value(buf: &mut BytesMut, record_type: u8, slot: Slot, bal: Ballot, val: &Bytes) {
+    buf.reserve(21 + val.len());
+    buf.put_u8(record_type);
+    buf.put_u64_le(slot);
+    put_ballot(buf, bal);
+    buf.put_slice(val);
+}
+
+fn put_ballot(buf: &mut BytesMut, bal: Ballot) {
+    buf.put_u32_le(bal.0);
+    buf.put_u32_le(bal.1);
+}
+
+fn get_ballot(buf: &mut Bytes) -> Ballot {
+    let n = buf.get_u32_le();
+    let node = buf.get_u32_le();
+    Ballot(n, node)
+}
+
+/// Frames an encoded record with the length and checksum header
+fn frame(record: &Record) -> Bytes {
+    let payload = record.encode();
+    let mut buf = BytesMut::with_capacity(HEADER_SIZE + payload.len());
+    buf.put_u32_le(payload.len() as u32);
+    buf.put_u32_le(crc32(&payload));
+    buf.put_slice(&payload);
+    buf.freeze()
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+}
+
+fn create_segment(dir: &Path, id: u64) -> io::Result<File> {
+    let file = OpenOptions::new().create_new(true).append(true).open(segment_path(dir, id))?;
+    // sync the directory so that the new segment survives a crash
+    File::open(dir)?.sync_all()?;
+    Ok(file)
+}
+
+/// Reads the valid records from a segment. The length of the valid prefix
+/// of the segment is returned along with the records.
+fn read_segment(path: &Path) -> io::Result<(Vec<Record>, u64)> {
+    let mut contents = Vec::new();
+    File::open(path)?.read_to_end(&mut contents)?;
+    let contents = Bytes::from(contents);
+
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while contents.len() - pos >= HEADER_SIZE {
+        let mut header = &contents[pos..pos + HEADER_SIZE];
+        let len = header.get_u32_le() as usize;
+        let crc = header.get_u32_le();
+
+        let start = pos + HEADER_SIZE;
+        if contents.len() - start < len {
+            break;
+        }
+
+        let payload = contents.slice(start..start + len);
+        if crc32(&payload) != crc {
+            break;
+        }
+
+        match Record::decode(payload) {
+            Some(record) => records.push(record),
+            None => break,
+        }
+        pos = start + len;
+    }
+
+    Ok((records, pos as u64))
+}
+
+/// CRC32 (IEEE) checksum
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static TEST_DIR_ID: AtomicUsize = AtomicUsize::new(0);
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new() -> TestDir {
+            let id = TEST_DIR_ID.fetch_add(1, Ordering::SeqCst);
+            let dir =
+                std::env::temp_dir().join(format!("paxos-wal-test-{}-{}", std::process::id(), id));
+            let _ = fs::remove_dir_all(&dir);
+            TestDir(dir)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn append_and_reopen() {
+        let dir = TestDir::new();
+        {
+            let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+            wal.store_promise(Ballot(1, 2)).unwrap();
+            wal.store_accepted(0, Ballot(1, 2), "abc".into()).unwrap();
+            wal.store_accepted(1, Ballot(1, 2), Bytes::default()).unwrap();
+        }
+
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        assert_eq!(
+            vec![
+                Record::Promise(Ballot(1, 2)),
+                Record::Accepted(0, Ballot(1, 2), "abc".into()),
+                Record::Accepted(1, Ballot(1, 2), Bytes::default()),
+            ],
+            wal.records().unwrap()
+        );
+
+        // appends continue after the existing records
+        wal.store_promise(Ballot(3, 0)).unwrap();
+        assert_eq!(4, wal.records().unwrap().len());
+    }
+
+    #[test]
+    fn segment_rotation() {
+        let dir = TestDir::new();
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap().with_segment_size(64);
+        for slot in 0..10 {
+            wal.store_accepted(slot, Ballot(0, 1), "0123456789".into()).unwrap();
+        }
+        assert!(wal.segment_count() > 1);
+        drop(wal);
+
+        let wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        let slots = wal
+            .records()
+            .unwrap()
+            .into_iter()
+            .map(|r| match r {
+                Record::Accepted(slot, ..) => slot,
+                _ => panic!("Unexpected record"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!((0..10).collect::<Vec<_>>(), slots);
+    }
+
+    #[test]
+    fn torn_write_truncated() {
+        let dir = TestDir::new();
+        {
+            let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+            wal.store_accepted(0, Ballot(0, 1), "abc".into()).unwrap();
+            wal.store_accepted(1, Ballot(0, 1), "def".into()).unwrap();
+        }
+
+        // simulate a partially written record
+        let path = segment_path(&dir.0, 0);
+        let full_len = fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(full_len - 2).unwrap();
+        drop(file);
+
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        assert_eq!(vec![Record::Accepted(0, Ballot(0, 1), "abc".into())], wal.records().unwrap());
+
+        // new records are readable after the truncated record
+        wal.store_accepted(1, Ballot(1, 1), "ghi".into()).unwrap();
+        assert_eq!(
+            vec![
+                Record::Accepted(0, Ballot(0, 1), "abc".into()),
+                Record::Accepted(1, Ballot(1, 1), "ghi".into()),
+            ],
+            wal.records().unwrap()
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch_truncated() {
+        let dir = TestDir::new();
+        {
+            let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+            wal.store_promise(Ballot(0, 1)).unwrap();
+            wal.store_accepted(0, Ballot(0, 1), "abc".into()).unwrap();
+        }
+
+        // flip the last byte of the value
+        let path = segment_path(&dir.0, 0);
+        let mut contents = fs::read(&path).unwrap();
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        fs::write(&path, contents).unwrap();
+
+        let wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        assert_eq!(vec![Record::Promise(Ballot(0, 1))], wal.records().unwrap());
+    }
+
+    #[test]
+    fn load() {
+        let dir = TestDir::new();
+        {
+            let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+            wal.store_promise(Ballot(1, 2)).unwrap();
+            wal.store_accepted(0, Ballot(1, 2), "abc".into()).unwrap();
+            wal.store_accepted(1, Ballot(1, 2), "def".into()).unwrap();
+            wal.store_promise(Ballot(0, 1)).unwrap();
+            wal.store_accepted(1, Ballot(2, 0), "ghi".into()).unwrap();
+            wal.store_resolution(0, Ballot(1, 2), "abc".into()).unwrap();
+            wal.store_incarnation(2).unwrap();
+            wal.store_incarnation(1).unwrap();
+        }
+
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        let state = wal.load().unwrap();
+        assert_eq!(Some(Ballot(1, 2)), state.promised);
+        assert_eq!(vec![(1, Ballot(2, 0), "ghi".into())], state.accepted);
+        assert_eq!(vec![(0, Ballot(1, 2), "abc".into())], state.resolved);
+        assert_eq!(2, state.incarnation);
+    }
+
+    #[test]
+    fn snapshot_compaction() {
+        let dir = TestDir::new();
+        {
+            let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap().with_segment_size(64);
+            wal.store_promise(Ballot(1, 2)).unwrap();
+            wal.store_incarnation(3).unwrap();
+            for slot in 0..6 {
+                wal.store_accepted(slot, Ballot(1, 2), "abc".into()).unwrap();
+                wal.store_resolution(slot, Ballot(1, 2), "abc".into()).unwrap();
+            }
+            wal.store_accepted(6, Ballot(1, 2), "def".into()).unwrap();
+            let segments = wal.segment_count();
+            assert!(segments > 1);
+
+            wal.store_snapshot(4, "snapshot".into()).unwrap();
+            assert!(wal.segment_count() < segments);
+        }
+
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Always).unwrap();
+        let state = wal.load().unwrap();
+        assert_eq!(Some(Ballot(1, 2)), state.promised);
+        assert_eq!(Some((4, "snapshot".into())), state.snapshot);
+        assert_eq!(vec![(6, Ballot(1, 2), "def".into())], state.accepted);
+        assert_eq!(vec![(5, Ballot(1, 2), "abc".into())], state.resolved);
+
+        // only the records after the snapshot are retained in the segments
+        assert_eq!(
+            vec![
+                Record::Promise(Ballot(1, 2)),
+                Record::Incarnation(3),
+                Record::Accepted(5, Ballot(1, 2), "abc".into()),
+                Record::Resolution(5, Ballot(1, 2), "abc".into()),
+                Record::Accepted(6, Ballot(1, 2), "def".into()),
+            ],
+            wal.records().unwrap()
+        );
+
+        // older snapshots do not replace the stored snapshot after reopening
+        wal.store_snapshot(2, "older".into()).unwrap();
+        assert_eq!(Some((4, "snapshot".into())), wal.load().unwrap().snapshot);
+    }
+
+    #[test]
+    fn group_commit() {
+        let dir = TestDir::new();
+        let mut wal = Wal::open(&dir.0, SyncPolicy::GroupCommit(3)).unwrap();
+        wal.store_promise(Ballot(0, 1)).unwrap();
+        wal.store_promise(Ballot(1, 1)).unwrap();
+        assert_eq!(2, wal.unsynced);
+        assert!(!wal.is_durable());
+        wal.store_promise(Ballot(2, 1)).unwrap();
+        assert_eq!(0, wal.unsynced);
+        assert!(wal.is_durable());
+
+        // buffered records are synced on the next tick
+        wal.store_promise(Ballot(3, 1)).unwrap();
+        assert_eq!(1, wal.unsynced);
+        assert!(!wal.is_durable());
+        wal.flush().unwrap();
+        assert_eq!(0, wal.unsynced);
+        assert!(wal.is_durable());
+    }
+
+    #[test]
+    fn interval_flush() {
+        let dir = TestDir::new();
+        let interval = Duration::from_secs(60);
+        let mut wal = Wal::open(&dir.0, SyncPolicy::Interval(interval)).unwrap();
+        wal.store_promise(Ballot(0, 1)).unwrap();
+        wal.flush().unwrap();
+        assert_eq!(1, wal.unsynced);
+
+        // once the interval has elapsed, the tick syncs without a new write
+        wal.last_sync -= interval;
+        wal.flush().unwrap();
+        assert_eq!(0, wal.unsynced);
+    }
+}
@@ -1,7 +1,7 @@
 use super::Slot;
-use crate::{acceptor::Acceptor, Ballot};
+use crate::{acceptor::Acceptor, Ballot, QuorumSystem};
 use bytes::Bytes;
-use std::{cmp::max, ops::Range};
+use std::{cmp::max, ops::Range, sync::Arc};
 
 struct ResolvedSlot(Ballot, Bytes);
 
@@ -15,20 +15,24 @@ pub struct SlotWindow {
     open_min_slot: Slot,
     max_promised: Option<Ballot>,
 
-    /// Slots that have been decided.
+    /// Slots that have been decided and not yet truncated. The last decided
+    /// slot is `open_min_slot - 1`.
     decided: Vec<ResolvedSlot>,
+    /// Index of the first decision in `decided` that has not been executed
     execute_start: usize,
 
-    /// Size of the phase 2 quorum
-    quorum: usize,
+    /// Quorum system for phase 2 quorums of new slots
+    quorum: Arc<dyn QuorumSystem>,
+    /// Quorum systems that take effect for slots `>=` the slot
+    reconfigurations: Vec<(Slot, Arc<dyn QuorumSystem>)>,
 }
 
 impl SlotWindow {
     /// New tracker for slots
-    pub fn new(quorum: usize) -> SlotWindow {
+    pub fn new(quorum: Arc<dyn QuorumSystem>) -> SlotWindow {
         let mut open = Vec::new();
         // add the first slot
-        open.push(Acceptor::new(None, quorum));
+        open.push(Acceptor::new(None, quorum.clone()));
 
         SlotWindow {
             open,
@@ -36,21 +40,110 @@ impl SlotWindow {
             max_promised: None,
             decided: Vec::new(),
             quorum,
+            reconfigurations: Vec::new(),
             execute_start: 0,
         }
     }
 
+    /// Restores the highest promised ballot across the window, used when
+    /// recovering acceptor state from storage.
+    pub fn restore_promise(&mut self, bal: Ballot) {
+        self.max_promised = max(self.max_promised, Some(bal));
+        for acceptor in self.open.iter_mut() {
+            acceptor.receive_prepare(bal);
+        }
+    }
+
+    /// Changes the quorum system for slots `>= slot`, including slots that
+    /// are already open.
+    pub fn reconfigure(&mut self, slot: Slot, quorum: Arc<dyn QuorumSystem>) {
+        let start = slot.saturating_sub(self.open_min_slot) as usize;
+        for acceptor in self.open.iter_mut().skip(start) {
+            acceptor.reconfigure(quorum.clone());
+        }
+        self.reconfigurations.push((slot, quorum));
+    }
+
+    /// Quorum system in effect for a slot
+    fn quorum(&self, slot: Slot) -> Arc<dyn QuorumSystem> {
+        match self.reconfigurations.iter().rev().find(|(start, _)| *start <= slot) {
+            Some((_, quorum)) => quorum.clone(),
+            None => self.quorum.clone(),
+        }
+    }
+
+    /// Slot that will be opened by `next_slot`
+    pub fn peek_next_slot(&self) -> Slot {
+        match self.open.last() {
+            Some(last) if last.highest_value().is_none() => self.open_range().end - 1,
+            _ => self.open_range().end,
+        }
+    }
+
+    /// Minimum slot that has not been truncated from the window
+    fn min_slot(&self) -> Slot {
+        assert!(self.open_min_slot as usize >= self.decided.len());
+        self.open_min_slot - self.decided.len() as Slot
+    }
+
+    /// Highest slot for which the decision has been drained for execution
+    pub fn executed_through(&self) -> Option<Slot> {
+        let next = self.min_slot() + self.execute_start as Slot;
+        if next > 0 { Some(next - 1) } else { None }
+    }
+
+    /// Highest slot that has been resolved
+    pub fn last_resolved(&self) -> Option<Slot> {
+        match self.open.iter().rposition(|slot| slot.resolved()) {
+            Some(i) => Some(self.open_min_slot + i as Slot),
+            None => self.open_min_slot.checked_sub(1),
+        }
+    }
+
+    /// Discards executed decisions for slots `<= slot`. Truncated slots
+    /// are referenced as `SlotMutRef::ResolutionTruncated`.
+    pub fn compact(&mut self, slot: Slot) {
+        let min_slot = self.min_slot();
+        if slot < min_slot {
+            return;
+        }
+
+        let len = ((slot - min_slot + 1) as usize).min(self.execute_start);
+        self.decided.drain(0..len);
+        self.execute_start -= len;
+        trace!("Compacted {} decisions through slot {}", len, slot);
+    }
+
+    /// Advances the window past a slot, discarding all decided and open
+    /// slots `<= slot`. This is used once a snapshot of the state machine
+    /// at the slot has been installed.
+    pub fn advance_to(&mut self, slot: Slot) {
+        if slot < self.open_min_slot {
+            self.compact(slot);
+            return;
+        }
+
+        let open_len = ((slot - self.open_min_slot + 1) as usize).min(self.open.len());
+        self.open.drain(0..open_len);
+        self.decided.clear();
+        self.execute_start = 0;
+        self.open_min_slot = slot + 1;
+        if self.open.is_empty() {
+            self.fill_open_slots(self.open_min_slot);
+        }
+        self.fill_decisions();
+    }
+
     /// Mutable reference to a slot
     pub fn slot_mut(&mut self, slot: Slot) -> SlotMutRef {
-        assert!(self.open_min_slot as usize >= self.decided.len());
-        let min_slot = self.open_min_slot - self.decided.len() as Slot;
+        let min_slot = self.min_slot();
 
         if slot < min_slot {
             // we've already executed this slot
             SlotMutRef::ResolutionTruncated
         } else if slot < self.open_min_slot {
             // slot is decided, and we have that decision as non-executed
-            let ResolvedSlot(ballot, value) = &self.decided[slot as usize];
+            let ResolvedSlot(ballot, value) = &self.decided[(slot - min_slot) as usize];
             SlotMutRef::Resolved(*ballot, value.clone())
         } else if slot < self.open_min_slot + self.open.len() as Slot {
             // slot is in the already opened range
@@ -80,7 +173,8 @@ impl SlotWindow {
         }
 
         let i = self.open.len();
-        self.open.push(Acceptor::new(self.max_promised, self.quorum));
+        let quorum = self.quorum(self.open_min_slot + i as Slot);
+        self.open.push(Acceptor::new(self.max_promised, quorum));
         OpenSlotMutRef { i, window: self }
     }
 
@@ -92,6 +186,17 @@ impl SlotWindow {
         Range { start: self.open_min_slot, end: self.open_min_slot + self.open.len() as Slot }
     }
 
+    /// Range of unresolved slots at the start of the open window that
+    /// precede the last resolved slot. Decisions for the later slots cannot
+    /// be executed until the range is resolved.
+    pub fn missing(&self) -> Option<Range<Slot>> {
+        let last_resolved = self.open.iter().rposition(|slot| slot.resolved())?;
+        if last_resolved == 0 {
+            return None;
+        }
+        Some(self.open_min_slot..self.open_min_slot + last_resolved as Slot)
+    }
+
     /// Removes decisions for application in the state machine
     pub fn drain_decisions<'a>(&'a mut self) -> impl Iterator<Item = (Slot, Bytes)> + 'a {
         DecisionIter(self)
@@ -118,6 +223,13 @@ impl SlotWindow {
             });
             self.decided.extend(resolutions);
             self.fill_open_slots(self.open_min_slot);
+
+            // fold quorum systems in effect for all open slots
+            while !self.reconfigurations.is_empty()
+                && self.reconfigurations[0].0 <= self.open_min_slot
+            {
+                self.quorum = self.reconfigurations.remove(0).1;
+            }
         }
     }
 
@@ -126,12 +238,11 @@ impl SlotWindow {
             return;
         }
 
-        let quorum = self.quorum;
         let last_promised = self.max_promised;
-        self.open.extend(
-            (self.open_min_slot + self.open.len() as u64..=max_slot)
-                .map(|_| Acceptor::new(last_promised, quorum)),
-        );
+        for slot in self.open_min_slot + self.open.len() as u64..=max_slot {
+            let quorum = self.quorum(slot);
+            self.open.push(Acceptor::new(last_promised, quorum));
+        }
     }
 }
 
@@ -179,6 +290,12 @@ pub struct EmptySlotRef<'a> {
 }
 
 impl<'a> EmptySlotRef<'a> {
+    /// Highest ballot promised across the window, which the slot is
+    /// promised once filled
+    pub fn promised(&self) -> Option<Ballot> {
+        self.window.max_promised
+    }
+
     /// Filts the slot as open
     pub fn fill(self) -> OpenSlotMutRef<'a> {
         self.window.fill_open_slots(self.slot);
@@ -198,10 +315,10 @@ impl<'a> Iterator for DecisionIter<'a> {
             return None;
         }
 
-        let slot = self.0.execute_start;
-        let val = self.0.decided[self.0.execute_start].1.clone();
-        self.0.execute_start = slot + 1;
-        Some((slot as Slot, val))
+        let i = self.0.execute_start;
+        let val = self.0.decided[i].1.clone();
+        self.0.execute_start = i + 1;
+        Some((self.0.min_slot() + i as Slot, val))
     }
 }
 
@@ -234,10 +351,11 @@ impl<'a> SlotMutRef<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::FlexibleQuorum;
 
     #[test]
     fn fill_open_slots() {
-        let mut window = SlotWindow::new(3);
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(3, 3)));
         assert_eq!(0, window.open_min_slot);
 
         window.fill_open_slots(5);
@@ -261,7 +379,7 @@ mod tests {
 
     #[test]
     fn windows() {
-        let mut window = SlotWindow::new(3);
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(3, 3)));
         assert!(match window.slot_mut(0) {
             SlotMutRef::Open(_) => true,
             _ => false,
@@ -312,7 +430,7 @@ mod tests {
 
     #[test]
     fn open_one() {
-        let mut window = SlotWindow::new(2);
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
         {
             window.slot_mut(1).unwrap_empty().fill();
         }
@@ -328,7 +446,7 @@ mod tests {
 
     #[test]
     fn drain() {
-        let mut window = SlotWindow::new(2);
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
         {
             window.slot_mut(1).unwrap_empty().fill().acceptor().resolve(Ballot(0, 5), "1".into())
         }
@@ -363,9 +481,95 @@ mod tests {
         }
     }
 
+    #[test]
+    fn compact() {
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
+        assert_eq!(None, window.executed_through());
+        for slot in 0..4 {
+            window.slot_mut(slot).unwrap_open().acceptor().resolve(Ballot(0, 5), "v".into());
+        }
+
+        // only executed decisions are compacted
+        window.compact(2);
+        assert_eq!(4, window.decided.len());
+
+        assert_eq!(2, window.drain_decisions().take(2).count());
+        assert_eq!(Some(1), window.executed_through());
+        window.compact(2);
+        assert_eq!(2, window.decided.len());
+        assert!(matches!(window.slot_mut(1), SlotMutRef::ResolutionTruncated));
+        window.slot_mut(2).unwrap_resolved();
+
+        // decisions after compaction retain the slot numbers
+        let decisions = window.drain_decisions().collect::<Vec<_>>();
+        assert_eq!(vec![(2, "v".into()), (3, "v".into())], decisions);
+        assert_eq!(Some(3), window.executed_through());
+
+        window.compact(10);
+        assert!(window.decided.is_empty());
+        assert_eq!((4..5), window.open_range());
+        assert!(matches!(window.slot_mut(3), SlotMutRef::ResolutionTruncated));
+    }
+
+    #[test]
+    fn advance_to() {
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
+        window.slot_mut(0).unwrap_open().acceptor().resolve(Ballot(0, 5), "0".into());
+        window.slot_mut(3).unwrap_empty().fill().acceptor().resolve(Ballot(0, 5), "3".into());
+        window.slot_mut(6).unwrap_empty().fill().acceptor().resolve(Ballot(0, 5), "6".into());
+
+        window.advance_to(4);
+        assert_eq!(Some(4), window.executed_through());
+        assert_eq!((5..7), window.open_range());
+        assert!(matches!(window.slot_mut(3), SlotMutRef::ResolutionTruncated));
+        assert_eq!(0, window.drain_decisions().count());
+
+        window.slot_mut(5).unwrap_open().acceptor().resolve(Ballot(0, 5), "5".into());
+        let decisions = window.drain_decisions().collect::<Vec<_>>();
+        assert_eq!(vec![(5, "5".into()), (6, "6".into())], decisions);
+
+        // advancing past the open window
+        window.advance_to(20);
+        assert_eq!((21..22), window.open_range());
+        assert_eq!(Some(20), window.executed_through());
+    }
+
+    #[test]
+    fn missing() {
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
+        assert_eq!(None, window.missing());
+
+        window.slot_mut(3).unwrap_empty().fill();
+        assert_eq!(None, window.missing());
+
+        assert_eq!(None, window.last_resolved());
+
+        window.slot_mut(2).unwrap_open().acceptor().resolve(Ballot(0, 5), "2".into());
+        assert_eq!(Some(0..2), window.missing());
+        assert_eq!(Some(2), window.last_resolved());
+
+        window.slot_mut(0).unwrap_open().acceptor().resolve(Ballot(0, 5), "0".into());
+        assert_eq!(Some(1..2), window.missing());
+
+        window.slot_mut(1).unwrap_open().acceptor().resolve(Ballot(0, 5), "1".into());
+        assert_eq!(None, window.missing());
+        assert_eq!(Some(2), window.last_resolved());
+    }
+
+    #[test]
+    fn restore_promise() {
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
+        window.restore_promise(Ballot(3, 1));
+        assert_eq!(Some(Ballot(3, 1)), window.slot_mut(0).unwrap_open().acceptor().promised());
+
+        // new slots are opened with the promise
+        let mut slot = window.slot_mut(2).unwrap_empty().fill();
+        assert_eq!(Some(Ballot(3, 1)), slot.acceptor().promised());
+    }
+
     #[test]
     fn next_slot() {
-        let mut window = SlotWindow::new(2);
+        let mut window = SlotWindow::new(Arc::new(FlexibleQuorum::new(2, 2)));
 
         // first slot is considered next since it is not filled with a value
         {